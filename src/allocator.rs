@@ -0,0 +1,725 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicBool, Ordering};
+use linked_list_allocator::Heap;
+use spin::Mutex;
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::{FrameAllocator, Mapper, OffsetPageTable, Page, Size4KiB};
+use x86_64::VirtAddr;
+
+use crate::interrupts::InterruptGuard;
+use crate::memory;
+use crate::qemu::{exit_qemu, QemuExitCode};
+use crate::test_runner;
+
+/// Virtual address at which the heap starts. Any address works, as long as it is not used for
+/// anything else; this one is easy to recognize in a debugger.
+pub const HEAP_START: usize = 0x_4444_4444_0000;
+
+/// Size of the heap in bytes
+pub const HEAP_SIZE: usize = 100 * 1024;
+
+// The allocator behind Box, Vec, String, etc. The alloc crate calls this whenever it needs memory,
+// and it hands out pieces of the heap. By default, this is the ListAllocator, which keeps a linked
+// list of the free regions of the heap, so that freed memory can actually be reused. Two features
+// swap in a different allocator, mostly to compare them in the benchmarks:
+// - bump-allocator: the BumpAllocator, which is faster, but only reuses memory once everything
+//   has been freed
+// - fixed-size-block-allocator: the FixedSizeBlockAllocator, which is faster for small
+//   allocations, and uses the ListAllocator's heap for the rest
+//
+// They all offer the same functions, so the rest of this module does not care which one it is.
+// Starts out empty, because the heap needs to be mapped before it can be used, see init_heap.
+#[global_allocator]
+static ALLOCATOR: Backend = Backend::new();
+
+#[cfg(not(any(feature = "bump-allocator", feature = "fixed-size-block-allocator")))]
+type Backend = ListAllocator;
+#[cfg(all(feature = "bump-allocator", not(feature = "fixed-size-block-allocator")))]
+type Backend = BumpAllocator;
+#[cfg(all(feature = "fixed-size-block-allocator", not(feature = "bump-allocator")))]
+type Backend = FixedSizeBlockAllocator;
+
+#[cfg(all(feature = "bump-allocator", feature = "fixed-size-block-allocator"))]
+compile_error!("only one of the features bump-allocator and fixed-size-block-allocator can be enabled");
+
+/// Set once an allocation failed, so that the test runner can tell running out of memory apart
+/// from other panics
+static OUT_OF_MEMORY: AtomicBool = AtomicBool::new(false);
+
+/// A snapshot of the heap's usage, see stats
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct HeapStats {
+    /// Bytes handed out, including what the allocator adds for alignment
+    pub used: usize,
+    /// Bytes not handed out
+    pub free: usize,
+    /// Number of allocations and deallocations since boot
+    pub allocations: u64,
+    pub deallocations: u64,
+    /// Size of the largest allocation that would currently succeed. If this is much smaller than
+    /// free, the heap is fragmented.
+    pub largest_free_block: usize,
+}
+
+/// The heap along with its counters. These live behind the same lock as the heap, so that a
+/// snapshot of them always matches the heap's usage.
+struct TrackedHeap {
+    heap: Heap,
+    allocations: u64,
+    deallocations: u64,
+}
+
+impl TrackedHeap {
+    fn stats(&mut self) -> HeapStats {
+        return HeapStats {
+            used: self.heap.used(),
+            free: self.heap.free(),
+            allocations: self.allocations,
+            deallocations: self.deallocations,
+            largest_free_block: largest_free_block(&mut self.heap),
+        };
+    }
+}
+
+/// Returns the size of the largest allocation that would currently succeed on the heap.
+/// linked_list_allocator does not let us walk its list of free regions, so we find out by trying: a
+/// binary search over the sizes, where every allocation that succeeds is freed right away. This
+/// bypasses the counters, and freeing merges the region back into its neighbours, so it leaves the
+/// heap as it found it.
+fn largest_free_block(heap: &mut Heap) -> usize {
+    // the largest size known to fit, and the smallest size known not to fit
+    let mut fits = 0;
+    let mut too_large = heap.free() + 1;
+    while too_large - fits > 1 {
+        let size = fits + (too_large - fits) / 2;
+        let layout = Layout::from_size_align(size, 1).unwrap();
+        match heap.allocate_first_fit(layout) {
+            Ok(ptr) => {
+                // we just allocated it with this layout
+                unsafe { heap.deallocate(ptr, layout) };
+                fits = size;
+            },
+            Err(()) => too_large = size,
+        }
+    }
+    return fits;
+}
+
+/// Wraps the Heap to count allocations and to notice failed ones.
+/// Interrupts are disabled while the heap is locked, so that an interrupt handler that allocates
+/// (e.g. by formatting into a String) cannot find it locked by the code it interrupted.
+/// Unused with the bump-allocator and fixed-size-block-allocator features.
+#[cfg_attr(
+    any(feature = "bump-allocator", feature = "fixed-size-block-allocator"),
+    allow(dead_code)
+)]
+struct ListAllocator(Mutex<TrackedHeap>);
+
+#[cfg_attr(
+    any(feature = "bump-allocator", feature = "fixed-size-block-allocator"),
+    allow(dead_code)
+)]
+impl ListAllocator {
+    const fn new() -> Self {
+        return ListAllocator(Mutex::new(TrackedHeap {
+            heap: Heap::empty(),
+            allocations: 0,
+            deallocations: 0,
+        }));
+    }
+
+    /// Hands the heap_size bytes at heap_start to the allocator.
+    ///
+    /// # Safety
+    ///
+    /// The memory has to be mapped and unused, and this may only be called once.
+    unsafe fn init(&self, heap_start: usize, heap_size: usize) {
+        let _interrupts = InterruptGuard::new();
+        self.0.lock().heap.init(heap_start as *mut u8, heap_size);
+    }
+
+    /// Allocates memory for the layout, or fails if there is no block large enough. Unlike alloc,
+    /// a failure does not count as running out of memory.
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, ()> {
+        let _interrupts = InterruptGuard::new();
+        let mut tracked = self.0.lock();
+        let ptr = tracked.heap.allocate_first_fit(layout)?;
+        tracked.allocations += 1;
+        return Ok(ptr);
+    }
+
+    fn stats(&self) -> HeapStats {
+        let _interrupts = InterruptGuard::new();
+        return self.0.lock().stats();
+    }
+
+    /// Like stats, but returns None instead of waiting if the heap is locked
+    fn try_stats(&self) -> Option<HeapStats> {
+        let _interrupts = InterruptGuard::new();
+        return self.0.try_lock().map(|mut tracked| tracked.stats());
+    }
+}
+
+unsafe impl GlobalAlloc for ListAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        return allocated_or_null(self.allocate(layout));
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let _interrupts = InterruptGuard::new();
+        let mut tracked = self.0.lock();
+        // the alloc crate only hands back pointers that alloc returned, which are never null
+        tracked.heap.deallocate(NonNull::new_unchecked(ptr), layout);
+        tracked.deallocations += 1;
+    }
+}
+
+/// Turns the result of an allocation into what GlobalAlloc::alloc returns, i.e. null if it failed,
+/// and notes the failure, see out_of_memory
+fn allocated_or_null(result: Result<NonNull<u8>, ()>) -> *mut u8 {
+    return match result {
+        Ok(ptr) => ptr.as_ptr(),
+        Err(()) => {
+            OUT_OF_MEMORY.store(true, Ordering::Relaxed);
+            core::ptr::null_mut()
+        },
+    };
+}
+
+/// The simplest allocator there is: it hands out the heap front to back, by moving a pointer to
+/// the start of the free memory ("bumping" it) past every allocation. That makes allocating very
+/// fast, but there is no way to reuse a single freed allocation, since the allocator does not
+/// remember where they are. It only counts the allocations that are still alive, and once that
+/// count drops to 0, the whole heap is free again.
+/// That works well for memory that is never freed anyway, e.g. during early boot, or for a batch of
+/// allocations that are all freed together. A single allocation that lives forever keeps the rest
+/// of the heap from ever being reused, though.
+/// Like the ListAllocator, it disables interrupts while it is locked.
+pub struct BumpAllocator(Mutex<Bump>);
+
+/// The state of a BumpAllocator, see there
+struct Bump {
+    heap_start: usize,
+    heap_end: usize,
+    /// Where the next allocation starts, before it is aligned
+    next: usize,
+    /// Number of allocations that have not been freed yet
+    live: usize,
+    allocations: u64,
+    deallocations: u64,
+}
+
+impl Bump {
+    fn stats(&self) -> HeapStats {
+        let free = self.heap_end - self.next;
+        return HeapStats {
+            used: self.next - self.heap_start,
+            free,
+            allocations: self.allocations,
+            deallocations: self.deallocations,
+            // the free memory is always in one piece, right after next
+            largest_free_block: free,
+        };
+    }
+}
+
+impl BumpAllocator {
+    /// Creates an allocator without a heap, see init
+    pub const fn new() -> Self {
+        return BumpAllocator(Mutex::new(Bump {
+            heap_start: 0,
+            heap_end: 0,
+            next: 0,
+            live: 0,
+            allocations: 0,
+            deallocations: 0,
+        }));
+    }
+
+    /// Hands the heap_size bytes at heap_start to the allocator.
+    ///
+    /// # Safety
+    ///
+    /// The memory has to be mapped and unused, and this may only be called once.
+    pub unsafe fn init(&self, heap_start: usize, heap_size: usize) {
+        let _interrupts = InterruptGuard::new();
+        let mut bump = self.0.lock();
+        bump.heap_start = heap_start;
+        bump.heap_end = heap_start + heap_size;
+        bump.next = heap_start;
+    }
+
+    /// Allocates memory for the layout, or fails if the rest of the heap is too small. Unlike
+    /// alloc, a failure does not count as running out of memory.
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, ()> {
+        let _interrupts = InterruptGuard::new();
+        let mut bump = self.0.lock();
+        // the alignment is always a power of two, so rounding up to it only needs a mask
+        let start = bump.next.checked_add(layout.align() - 1).ok_or(())? & !(layout.align() - 1);
+        let end = start.checked_add(layout.size()).ok_or(())?;
+        if end > bump.heap_end {
+            return Err(());
+        }
+        bump.next = end;
+        bump.live += 1;
+        bump.allocations += 1;
+        // before init, heap_end is 0, so we only get here with a heap, which never starts at 0
+        return NonNull::new(start as *mut u8).ok_or(());
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        let _interrupts = InterruptGuard::new();
+        return self.0.lock().stats();
+    }
+
+    /// Like stats, but returns None instead of waiting if the heap is locked
+    pub fn try_stats(&self) -> Option<HeapStats> {
+        let _interrupts = InterruptGuard::new();
+        return self.0.try_lock().map(|bump| bump.stats());
+    }
+}
+
+impl Default for BumpAllocator {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+unsafe impl GlobalAlloc for BumpAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        return allocated_or_null(self.allocate(layout));
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
+        let _interrupts = InterruptGuard::new();
+        let mut bump = self.0.lock();
+        bump.live -= 1;
+        bump.deallocations += 1;
+        if bump.live == 0 {
+            bump.next = bump.heap_start;
+        }
+    }
+}
+
+/// The block sizes of the FixedSizeBlockAllocator. Every block is aligned to its size, so each of
+/// them has to be a power of two, and large enough to hold a FreeBlock.
+const BLOCK_SIZES: [usize; 9] = [8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+
+/// A free block of a FixedSizeBlockAllocator. The list of free blocks lives in the free blocks
+/// themselves, since nobody uses their memory anyway.
+struct FreeBlock {
+    next: Option<&'static mut FreeBlock>,
+}
+
+/// An allocator that serves small allocations from blocks of a few fixed sizes (see BLOCK_SIZES).
+/// Every allocation gets the smallest block that fits it, and a freed block goes on a list of free
+/// blocks of its size, to be handed out again by the next allocation of that size. That makes
+/// allocating and freeing small things (like a task's Box, or a short String) just a matter of
+/// taking a block off a list or putting it back, instead of searching the heap's list of free
+/// regions like the ListAllocator does.
+/// New blocks, and allocations that are too large for any block, come from a linked list heap.
+/// Blocks are never given back to it, so memory that once held small allocations is only ever
+/// reused for small allocations of the same size; it also wastes up to half of a block when an
+/// allocation is just a bit larger than the next smaller block size.
+/// Like the ListAllocator, it disables interrupts while it is locked.
+pub struct FixedSizeBlockAllocator(Mutex<FixedSizeBlocks>);
+
+/// The state of a FixedSizeBlockAllocator, see there
+struct FixedSizeBlocks {
+    /// The first free block of each size in BLOCK_SIZES
+    free_lists: [Option<&'static mut FreeBlock>; BLOCK_SIZES.len()],
+    fallback: Heap,
+    allocations: u64,
+    deallocations: u64,
+}
+
+impl FixedSizeBlocks {
+    fn stats(&mut self) -> HeapStats {
+        // the free blocks count as used for the fallback heap
+        let mut free_blocks = 0;
+        for (list, size) in self.free_lists.iter().zip(BLOCK_SIZES) {
+            let mut block = list.as_deref();
+            while let Some(free) = block {
+                free_blocks += size;
+                block = free.next.as_deref();
+            }
+        }
+        return HeapStats {
+            used: self.fallback.used() - free_blocks,
+            free: self.fallback.free() + free_blocks,
+            allocations: self.allocations,
+            deallocations: self.deallocations,
+            // this leaves out the free blocks, since only allocations that fit them can use them
+            largest_free_block: largest_free_block(&mut self.fallback),
+        };
+    }
+}
+
+/// Returns the index into BLOCK_SIZES of the smallest block that fits the layout, or None if it is
+/// too large for any of them
+fn block_index(layout: &Layout) -> Option<usize> {
+    // blocks are aligned to their size, so the block has to be at least as large as the alignment
+    let required = layout.size().max(layout.align());
+    return BLOCK_SIZES.iter().position(|&size| size >= required);
+}
+
+impl FixedSizeBlockAllocator {
+    /// Creates an allocator without a heap, see init
+    pub const fn new() -> Self {
+        return FixedSizeBlockAllocator(Mutex::new(FixedSizeBlocks {
+            free_lists: [const { None }; BLOCK_SIZES.len()],
+            fallback: Heap::empty(),
+            allocations: 0,
+            deallocations: 0,
+        }));
+    }
+
+    /// Hands the heap_size bytes at heap_start to the allocator.
+    ///
+    /// # Safety
+    ///
+    /// The memory has to be mapped and unused, and this may only be called once.
+    pub unsafe fn init(&self, heap_start: usize, heap_size: usize) {
+        let _interrupts = InterruptGuard::new();
+        self.0.lock().fallback.init(heap_start as *mut u8, heap_size);
+    }
+
+    /// Allocates memory for the layout, or fails if there is neither a free block nor room for a
+    /// new one. Unlike alloc, a failure does not count as running out of memory.
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, ()> {
+        let _interrupts = InterruptGuard::new();
+        let mut blocks = self.0.lock();
+        let ptr = match block_index(&layout) {
+            Some(index) => match blocks.free_lists[index].take() {
+                Some(block) => {
+                    blocks.free_lists[index] = block.next.take();
+                    NonNull::from(block).cast()
+                },
+                None => {
+                    // no free block of this size, so we carve a new one out of the fallback heap
+                    let size = BLOCK_SIZES[index];
+                    let block_layout = Layout::from_size_align(size, size).unwrap();
+                    blocks.fallback.allocate_first_fit(block_layout)?
+                },
+            },
+            None => blocks.fallback.allocate_first_fit(layout)?,
+        };
+        blocks.allocations += 1;
+        return Ok(ptr);
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        let _interrupts = InterruptGuard::new();
+        return self.0.lock().stats();
+    }
+
+    /// Like stats, but returns None instead of waiting if the heap is locked
+    pub fn try_stats(&self) -> Option<HeapStats> {
+        let _interrupts = InterruptGuard::new();
+        return self.0.try_lock().map(|mut blocks| blocks.stats());
+    }
+}
+
+impl Default for FixedSizeBlockAllocator {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+unsafe impl GlobalAlloc for FixedSizeBlockAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        return allocated_or_null(self.allocate(layout));
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let _interrupts = InterruptGuard::new();
+        let mut blocks = self.0.lock();
+        match block_index(&layout) {
+            Some(index) => {
+                // the block is ours again, and every block size is large enough and aligned enough
+                // for a FreeBlock
+                let block = ptr as *mut FreeBlock;
+                block.write(FreeBlock {
+                    next: blocks.free_lists[index].take(),
+                });
+                blocks.free_lists[index] = Some(&mut *block);
+            },
+            // the alloc crate only hands back pointers that alloc returned, which are never null
+            None => blocks.fallback.deallocate(NonNull::new_unchecked(ptr), layout),
+        }
+        blocks.deallocations += 1;
+    }
+}
+
+/// Returns whether an allocation has failed so far
+pub fn out_of_memory() -> bool {
+    return OUT_OF_MEMORY.load(Ordering::Relaxed);
+}
+
+/// Returns how much of the heap is in use, and how often it was used
+pub fn stats() -> HeapStats {
+    return ALLOCATOR.stats();
+}
+
+/// Logs the heap's stats, e.g. for a shell command
+pub fn print_stats() {
+    let stats = stats();
+    crate::log::info!(
+        "heap: {} of {} bytes used, {} free, largest free block {}",
+        stats.used,
+        HEAP_SIZE,
+        stats.free,
+        stats.largest_free_block
+    );
+    crate::log::info!(
+        "heap: {} allocations, {} deallocations, {} live",
+        stats.allocations,
+        stats.deallocations,
+        stats.allocations - stats.deallocations
+    );
+}
+
+/// Reports a failed allocation of the layout as an error, along with how much of the heap is in
+/// use, so that we can tell a single absurdly large allocation apart from a heap that filled up.
+pub fn report_alloc_error(layout: Layout) {
+    crate::log::error!(
+        "allocation of {} bytes (aligned to {}) failed",
+        layout.size(),
+        layout.align()
+    );
+    // the failed allocation released the lock again, but we might have run out of memory while
+    // somebody was holding it, and they are never going to release it
+    match ALLOCATOR.try_stats() {
+        Some(stats) => {
+            crate::log::error!(
+                "heap: {} bytes used, {} free, largest free block {}",
+                stats.used,
+                stats.free,
+                stats.largest_free_block
+            );
+        },
+        None => {
+            crate::log::error!("heap: locked, no usage available");
+        },
+    }
+}
+
+/// Called by the alloc crate when an allocation that cannot fail does fail, e.g. in Box::new or
+/// when a Vec grows. Without this, running out of memory would abort without saying why.
+/// In a test run, we exit QEMU with QemuExitCode::OutOfMemory, so that the host can tell running
+/// out of memory apart from failed tests. Otherwise there is nothing we could do about it, so we
+/// halt.
+#[alloc_error_handler]
+fn alloc_error(layout: Layout) -> ! {
+    OUT_OF_MEMORY.store(true, Ordering::Relaxed);
+    report_alloc_error(layout);
+    if test_runner::running() {
+        exit_qemu(QemuExitCode::OutOfMemory);
+    }
+    crate::hlt_loop();
+}
+
+/// Maps every page of the heap to a fresh frame, and hands the heap to the ALLOCATOR.
+/// Until this is called, every allocation fails.
+/// The pages right below and right after the heap are guard pages (see memory::GuardPage): they
+/// stay unmapped, so that running off either end of the heap causes a page fault, instead of
+/// silently overwriting whatever would be mapped there. This fails if either of them is mapped
+/// already.
+pub fn init_heap(
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
+    let heap_start = VirtAddr::new(HEAP_START as u64);
+    let heap_end = heap_start + HEAP_SIZE as u64 - 1u64;
+    let first_page = Page::containing_address(heap_start);
+    let last_page = Page::containing_address(heap_end);
+    for guard_page in [first_page - 1, last_page + 1] {
+        if let Ok(frame) = mapper.translate_page(guard_page) {
+            return Err(MapToError::PageAlreadyMapped(frame));
+        }
+    }
+    let pages = Page::range_inclusive(first_page, last_page);
+
+    for page in pages {
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or(MapToError::FrameAllocationFailed)?;
+        // the frame is fresh from the frame allocator, so nobody else uses it
+        unsafe { memory::create_mapping(page, frame, mapper, frame_allocator)? };
+    }
+
+    // the heap is mapped now, and nothing else uses it
+    unsafe {
+        ALLOCATOR.init(HEAP_START, HEAP_SIZE);
+    }
+    return Ok(());
+}
+
+#[test_case]
+fn test_box() {
+    use alloc::boxed::Box;
+    let x = Box::new(41);
+    let y = Box::new(13);
+    assert_eq!(*x, 41);
+    assert_eq!(*y, 13);
+}
+
+// Pushing past the Vec's capacity makes it reallocate, which has to keep the values
+#[test_case]
+fn test_vec_grows() {
+    use alloc::vec::Vec;
+    let n = 1000;
+    let mut vec = Vec::with_capacity(4);
+    for i in 0..n {
+        vec.push(i);
+    }
+    assert!(vec.capacity() >= n);
+    assert_eq!(vec.iter().sum::<usize>(), (n - 1) * n / 2);
+}
+
+// Allocates more than the heap can hold in total, which only works if freed memory is reused. The
+// BumpAllocator only does that once everything is freed, which never happens during the tests.
+#[cfg(not(feature = "bump-allocator"))]
+#[test_case]
+fn test_many_boxes() {
+    use alloc::boxed::Box;
+    for i in 0..HEAP_SIZE {
+        let x = Box::new(i);
+        assert_eq!(*x, i);
+    }
+}
+
+// Asks the heap for more memory than it has, and checks that the report of the failure with the
+// requested size made it to the screen. We ask the heap directly rather than going through the
+// ALLOCATOR, since that would count as running out of memory for the rest of the test run.
+#[test_case]
+fn test_report_alloc_error() {
+    use crate::vga_buffer::{BUFFER_HEIGHT, BUFFER_WIDTH, WRITER};
+
+    let layout = Layout::from_size_align(1000 * HEAP_SIZE, 8).unwrap();
+    assert!(ALLOCATOR.allocate(layout).is_err());
+    report_alloc_error(layout);
+
+    let expected = "allocation of 102400000 bytes (aligned to 8) failed";
+    let writer = WRITER.lock();
+    let found = (0..BUFFER_HEIGHT).any(|row| {
+        (0..=BUFFER_WIDTH - expected.len()).any(|start| {
+            expected.bytes().enumerate().all(|(i, byte)| {
+                return writer.read_char(row, start + i).map(|(c, _, _)| c) == Some(byte);
+            })
+        })
+    });
+    assert!(found, "the report of the failed allocation is not on the screen");
+}
+
+// Allocating counts as an allocation and uses memory, and freeing counts as a deallocation and
+// gives the memory back
+#[test_case]
+fn test_stats() {
+    use crate::bench::black_box;
+    use alloc::boxed::Box;
+
+    let before = stats();
+    let x = black_box(Box::new([42u8; 64]));
+    let during = stats();
+    assert!(during.used >= before.used + 64);
+    assert_eq!(during.allocations, before.allocations + 1);
+    assert_eq!(during.deallocations, before.deallocations);
+
+    drop(x);
+    let after = stats();
+    // the BumpAllocator only gets its memory back once everything is freed, see there
+    if cfg!(not(feature = "bump-allocator")) {
+        assert_eq!(after.used, before.used);
+    }
+    assert_eq!(after.allocations, before.allocations + 1);
+    assert_eq!(after.deallocations, before.deallocations + 1);
+    assert!(after.largest_free_block > 0 && after.largest_free_block <= after.free);
+}
+
+// Once every allocation is freed, the BumpAllocator starts over at the start of its heap, but not
+// before that. The heap is just an array on the stack here, so that the test does not depend on
+// what else lives on the real heap.
+#[test_case]
+fn test_bump_allocator_reuse() {
+    let mut memory = [0u8; 256];
+    let bump = BumpAllocator::new();
+    unsafe { bump.init(memory.as_mut_ptr() as usize, memory.len()) };
+    let layout = Layout::from_size_align(100, 1).unwrap();
+
+    let first = unsafe { bump.alloc(layout) };
+    let second = unsafe { bump.alloc(layout) };
+    assert_eq!(first, memory.as_mut_ptr());
+    assert_eq!(second, first.wrapping_add(100));
+    // the rest of the heap is too small
+    assert!(bump.allocate(layout).is_err());
+
+    unsafe { bump.dealloc(first, layout) };
+    assert!(bump.allocate(layout).is_err());
+    unsafe { bump.dealloc(second, layout) };
+    assert_eq!(bump.stats().used, 0);
+    assert_eq!(unsafe { bump.alloc(layout) }, first);
+    let stats = bump.stats();
+    assert_eq!((stats.allocations, stats.deallocations), (3, 2));
+}
+
+// Every allocation starts at a multiple of its alignment, skipping whatever is in between, and an
+// allocation that does not fit fails without changing anything
+#[test_case]
+fn test_bump_allocator_alignment() {
+    let mut memory = [0u8; 256];
+    let bump = BumpAllocator::new();
+    unsafe { bump.init(memory.as_mut_ptr() as usize, memory.len()) };
+
+    let byte = bump.allocate(Layout::from_size_align(1, 1).unwrap()).unwrap();
+    let aligned = bump.allocate(Layout::from_size_align(8, 64).unwrap()).unwrap();
+    assert_eq!(aligned.as_ptr() as usize % 64, 0);
+    assert!(aligned.as_ptr() > byte.as_ptr());
+    let stats = bump.stats();
+    assert_eq!(stats.used, aligned.as_ptr() as usize + 8 - memory.as_ptr() as usize);
+
+    assert!(bump.allocate(Layout::from_size_align(1000, 1).unwrap()).is_err());
+    assert_eq!(bump.stats(), stats);
+}
+
+// A freed block goes back on its list, and the next allocation of that size gets it again, while
+// an allocation of another size does not
+#[test_case]
+fn test_fixed_size_block_reuse() {
+    let mut memory = [0u8; 4096];
+    let blocks = FixedSizeBlockAllocator::new();
+    unsafe { blocks.init(memory.as_mut_ptr() as usize, memory.len()) };
+    let layout = Layout::from_size_align(24, 8).unwrap();
+
+    let first = unsafe { blocks.alloc(layout) };
+    assert_eq!(first as usize % 32, 0);
+    unsafe { blocks.dealloc(first, layout) };
+    let small = blocks.allocate(Layout::from_size_align(8, 8).unwrap()).unwrap();
+    assert_ne!(small.as_ptr(), first);
+    // 32 bytes are the same block size as 24
+    assert_eq!(unsafe { blocks.alloc(Layout::from_size_align(32, 8).unwrap()) }, first);
+    let stats = blocks.stats();
+    assert_eq!((stats.allocations, stats.deallocations), (3, 1));
+}
+
+// Allocations larger than the largest block come straight from the fallback heap, and go back to
+// it, instead of onto a list of blocks
+#[test_case]
+fn test_fixed_size_block_fallback() {
+    let mut memory = [0u8; 8192];
+    let blocks = FixedSizeBlockAllocator::new();
+    unsafe { blocks.init(memory.as_mut_ptr() as usize, memory.len()) };
+    let layout = Layout::from_size_align(3000, 8).unwrap();
+    assert_eq!(block_index(&layout), None);
+
+    let before = blocks.stats();
+    let large = unsafe { blocks.alloc(layout) };
+    assert!(!large.is_null());
+    assert!(blocks.stats().used >= before.used + 3000);
+    unsafe { blocks.dealloc(large, layout) };
+    let after = blocks.stats();
+    assert_eq!(after.used, before.used);
+    assert_eq!(after.largest_free_block, before.largest_free_block);
+    assert!(blocks.allocate(Layout::from_size_align(10_000, 8).unwrap()).is_err());
+}
@@ -0,0 +1,67 @@
+use crate::serial_println;
+use crate::test_runner::Testable;
+use crate::tsc;
+
+/// How often a benchmark runs the code it measures, so that a single slow (e.g. interrupted)
+/// iteration does not skew the result too much
+pub const BENCH_ITERATIONS: u64 = 1000;
+
+/// Something that can be benchmarked, like Testable for tests. Functions benchmark themselves by
+/// running BENCH_ITERATIONS times.
+pub trait Benchable {
+    /// Runs the benchmark, and returns how many TSC cycles it took in total, see tsc::now_cycles
+    fn run(&self) -> u64;
+
+    /// The name printed in the benchmark output
+    fn name(&self) -> &'static str;
+}
+
+impl<T> Benchable for T
+where
+    T: Fn(),
+{
+    fn run(&self) -> u64 {
+        let start = tsc::now_cycles();
+        for _ in 0..BENCH_ITERATIONS {
+            self();
+        }
+        return tsc::now_cycles() - start;
+    }
+
+    // see Testable::name
+    fn name(&self) -> &'static str {
+        return core::any::type_name::<T>();
+    }
+}
+
+/// Makes a benchmark runnable by the test runner, since #[test_case] is the only thing the compiler
+/// collects for us. Use it like this:
+///
+/// ```ignore
+/// #[test_case]
+/// const BENCH_FOO: Bench = Bench(&bench_foo);
+/// ```
+///
+/// Benchmarks are measured in TSC cycles, which are converted to nanoseconds if the TSC's frequency
+/// is known, see tsc::frequency.
+pub struct Bench(pub &'static dyn Benchable);
+
+impl Testable for Bench {
+    fn run(&self) {
+        let cycles = self.0.run() / BENCH_ITERATIONS;
+        match tsc::cycles_to_nanos(cycles) {
+            Some(nanos) => serial_println!("{}: {} cycles/iter (~{} ns/iter)", self.name(), cycles, nanos),
+            None => serial_println!("{}: {} cycles/iter", self.name(), cycles),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        return self.0.name();
+    }
+}
+
+/// Hides the value from the optimizer, so that it cannot throw away the work of a benchmark just
+/// because nobody uses its result.
+pub fn black_box<T>(value: T) -> T {
+    return core::hint::black_box(value);
+}
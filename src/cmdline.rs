@@ -0,0 +1,76 @@
+use crate::log::{self, Level};
+use crate::serial;
+
+/// The kernel's command line, e.g. "loglevel=debug noserial". Our bootloader has no way to pass
+/// one, so like the test_runner's TEST_FILTER, it is read at compile time instead, e.g.
+/// `TDOS_CMDLINE="loglevel=debug" cargo run`.
+pub const CMDLINE: &str = match option_env!("TDOS_CMDLINE") {
+    Some(cmdline) => cmdline,
+    None => "",
+};
+
+/// A command line: words separated by whitespace, each of which is either a key=value pair or a
+/// bare flag. It is only parsed when asked for something, so it just borrows the string, and
+/// needs neither the heap nor any setup.
+#[derive(Debug, Clone, Copy)]
+pub struct CmdLine<'a> {
+    line: &'a str,
+}
+
+impl<'a> CmdLine<'a> {
+    pub const fn new(line: &'a str) -> Self {
+        return CmdLine { line };
+    }
+
+    /// Every word as a (key, value) pair. A bare flag has an empty value, and only the first =
+    /// splits the word, so "a=b=c" has the key "a" and the value "b=c".
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+        return self
+            .line
+            .split_whitespace()
+            .map(|word| word.split_once('=').unwrap_or((word, "")));
+    }
+
+    /// Returns the value of the key, which is empty for a bare flag, or None if the key is not on
+    /// the command line. If a key is there more than once, the last one wins.
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        return self.iter().filter(|(k, _)| *k == key).map(|(_, value)| value).last();
+    }
+}
+
+/// Returns the value of the key on the kernel's command line, see CmdLine::get
+pub fn get(key: &str) -> Option<&'static str> {
+    return CmdLine::new(CMDLINE).get(key);
+}
+
+/// Applies the keys of the kernel's command line that we know about:
+/// - loglevel=<level> sets the least important log level that still gets logged, e.g.
+///   loglevel=debug, see log::set_max_level
+/// - noserial turns off all output to the serial ports, see serial::set_enabled
+///
+/// Everything else is left for whoever wants to look it up with get.
+pub fn apply() {
+    if let Some(name) = get("loglevel") {
+        match Level::from_name(name) {
+            Some(level) => log::set_max_level(level),
+            None => {
+                log::warn!("cmdline: unknown loglevel {}", name);
+            },
+        }
+    }
+    if get("noserial").is_some() {
+        serial::set_enabled(false);
+    }
+}
+
+// Bare flags and key=value pairs, with the value running to the end of the word
+#[test_case]
+fn test_cmdline() {
+    let cmdline = CmdLine::new("  loglevel=debug noserial path=/a=b  loglevel=trace");
+    assert_eq!(cmdline.get("loglevel"), Some("trace"));
+    assert_eq!(cmdline.get("noserial"), Some(""));
+    assert_eq!(cmdline.get("path"), Some("/a=b"));
+    assert_eq!(cmdline.get("serial"), None);
+    assert_eq!(cmdline.iter().count(), 4);
+    assert_eq!(CmdLine::new("").get("noserial"), None);
+}
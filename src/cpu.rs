@@ -0,0 +1,128 @@
+use core::arch::x86_64::{__cpuid, CpuidResult};
+use lazy_static::lazy_static;
+
+use crate::serial_println;
+
+/// The cpuid leaf with the vendor string, and the highest basic leaf the CPU supports
+const LEAF_VENDOR: u32 = 0;
+/// The cpuid leaf with the basic feature flags
+const LEAF_FEATURES: u32 = 1;
+/// The cpuid leaf with the highest extended leaf the CPU supports
+const LEAF_EXTENDED_MAX: u32 = 0x8000_0000;
+/// The three cpuid leaves that hold the brand string, 16 bytes each
+const LEAF_BRAND: [u32; 3] = [0x8000_0002, 0x8000_0003, 0x8000_0004];
+
+/// CPU features that we might want to use, see has_feature
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Feature {
+    /// The rdrand instruction, a hardware random number generator
+    Rdrand,
+    /// An on-chip APIC, the successor of the 8259 PIC
+    Apic,
+    /// SSE2 instructions
+    Sse2,
+    /// The time stamp counter, i.e. the rdtsc instruction
+    Tsc,
+    /// An on-chip floating point unit
+    Fpu,
+}
+
+impl Feature {
+    /// Returns the register (false: edx, true: ecx) and bit of the feature in cpuid leaf 1
+    fn location(self) -> (bool, u32) {
+        return match self {
+            Feature::Rdrand => (true, 30),
+            Feature::Apic => (false, 9),
+            Feature::Sse2 => (false, 26),
+            Feature::Tsc => (false, 4),
+            Feature::Fpu => (false, 0),
+        };
+    }
+}
+
+/// Runs cpuid for the leaf. cpuid exists on every x86_64 CPU, and leaves the CPU does not know
+/// return garbage rather than faulting.
+fn cpuid(leaf: u32) -> CpuidResult {
+    return __cpuid(leaf);
+}
+
+// cpuid is slow (it even traps into the hypervisor when running in a VM), and the strings never
+// change, so we only ask once.
+lazy_static! {
+    // 12 characters, e.g. "GenuineIntel", stored in ebx, edx, ecx, in that order
+    static ref VENDOR: [u8; 12] = {
+        let result = cpuid(LEAF_VENDOR);
+        let mut vendor = [0; 12];
+        vendor[0..4].copy_from_slice(&result.ebx.to_le_bytes());
+        vendor[4..8].copy_from_slice(&result.edx.to_le_bytes());
+        vendor[8..12].copy_from_slice(&result.ecx.to_le_bytes());
+        vendor
+    };
+
+    // Up to 48 characters, e.g. "QEMU Virtual CPU version 2.5+", padded with NUL bytes. Old CPUs
+    // do not have the brand string leaves, in which case this is empty.
+    static ref BRAND: [u8; 48] = {
+        let mut brand = [0; 48];
+        if cpuid(LEAF_EXTENDED_MAX).eax >= LEAF_BRAND[2] {
+            for (i, leaf) in LEAF_BRAND.iter().enumerate() {
+                let result = cpuid(*leaf);
+                for (j, register) in [result.eax, result.ebx, result.ecx, result.edx].iter().enumerate() {
+                    let start = i * 16 + j * 4;
+                    brand[start..start + 4].copy_from_slice(&register.to_le_bytes());
+                }
+            }
+        }
+        brand
+    };
+}
+
+/// Returns the CPU's vendor string, e.g. "GenuineIntel" or "AuthenticAMD"
+pub fn vendor() -> &'static str {
+    return core::str::from_utf8(&*VENDOR).unwrap_or("unknown");
+}
+
+/// Returns the CPU's brand string, e.g. "QEMU Virtual CPU version 2.5+", or an empty string if the
+/// CPU does not have one
+pub fn brand() -> &'static str {
+    let brand = core::str::from_utf8(&*BRAND).unwrap_or("");
+    // some CPUs pad it with spaces on the left, and it is NUL terminated on the right
+    return brand.trim_end_matches('\0').trim();
+}
+
+/// Returns whether the CPU supports the feature
+pub fn has_feature(feature: Feature) -> bool {
+    let result = cpuid(LEAF_FEATURES);
+    let (in_ecx, bit) = feature.location();
+    let register = if in_ecx { result.ecx } else { result.edx };
+    return register & (1 << bit) != 0;
+}
+
+/// Prints the vendor, brand string, and which of our Features the CPU supports to SERIAL1
+pub fn print_info() {
+    serial_println!("cpu: {} ({})", vendor(), brand());
+    for feature in [
+        Feature::Rdrand,
+        Feature::Apic,
+        Feature::Sse2,
+        Feature::Tsc,
+        Feature::Fpu,
+    ] {
+        serial_println!("  {:?}: {}", feature, has_feature(feature));
+    }
+}
+
+// QEMU either emulates a CPU itself, which claims to be an AMD or Intel one, or it passes through
+// the host's CPU under KVM. Hypervisor signatures like "KVMKVMKVM" are not vendors: those come from
+// leaf 0x4000_0000, not from leaf 0.
+#[test_case]
+fn test_vendor() {
+    let known = ["GenuineIntel", "AuthenticAMD"];
+    assert!(known.contains(&vendor()), "unknown vendor {:?}", vendor());
+}
+
+// Every x86_64 CPU has SSE2 and an FPU
+#[test_case]
+fn test_has_feature() {
+    assert!(has_feature(Feature::Sse2));
+    assert!(has_feature(Feature::Fpu));
+}
@@ -0,0 +1,401 @@
+use core::arch::asm;
+use core::fmt;
+use core::mem::offset_of;
+
+/// How many bytes hexdump shows per row
+const HEXDUMP_ROW_LENGTH: usize = 16;
+
+/// backtrace stops after this many frames, in case the chain of frames loops somehow
+const MAX_BACKTRACE_DEPTH: usize = 64;
+/// backtrace also stops at a frame that is further than this from the one before it. Frames are not
+/// that large, so the saved rbp is probably garbage, and might not even be mapped.
+const MAX_FRAME_SIZE: u64 = 64 * 1024;
+
+/// Writes to SERIAL1 through serial_print!. Our dumps could be long, and we do not want to hold
+/// SERIAL1 (with interrupts disabled) for all of it, so they are written piece by piece instead.
+struct SerialWriter;
+
+impl fmt::Write for SerialWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        crate::serial_print!("{}", s);
+        return Ok(());
+    }
+}
+
+/// Prints the len bytes starting at addr to SERIAL1, e.g. for looking at some structure in memory
+/// while bringing it up, in rows of 16 bytes like this:
+///
+/// 0x0000000000201000: 74 64 6f 73 00 ff                                tdos..
+///
+/// i.e. the address of the row, the bytes in hex, and the bytes again as ASCII, where anything that
+/// is not printable shows up as a dot.
+///
+/// # Safety
+///
+/// The caller has to make sure that all len bytes starting at addr are mapped and readable. We read
+/// them one byte at a time, but we cannot check whether they are there, so reading anything else
+/// page faults.
+pub unsafe fn hexdump(addr: usize, len: usize) {
+    // writing to the serial port does not fail
+    let _ = write_hexdump(&mut SerialWriter, addr, len);
+}
+
+/// Writes the hexdump of the len bytes starting at addr to out, see hexdump
+///
+/// # Safety
+///
+/// Same as hexdump
+pub unsafe fn write_hexdump(out: &mut impl fmt::Write, addr: usize, len: usize) -> fmt::Result {
+    let mut row = [0u8; HEXDUMP_ROW_LENGTH];
+    for row_start in (0..len).step_by(HEXDUMP_ROW_LENGTH) {
+        let row_length = HEXDUMP_ROW_LENGTH.min(len - row_start);
+        // we copy the row first, so that we read every byte exactly once. The reads are volatile,
+        // since the memory we look at might be MMIO, or change behind the compiler's back in some
+        // other way, and we want to see what is actually there.
+        for (i, byte) in row[..row_length].iter_mut().enumerate() {
+            let ptr = (addr + row_start + i) as *const u8;
+            *byte = core::ptr::read_volatile(ptr);
+        }
+
+        write!(out, "{:#018x}:", addr + row_start)?;
+        for i in 0..HEXDUMP_ROW_LENGTH {
+            match row[..row_length].get(i) {
+                Some(byte) => write!(out, " {:02x}", byte)?,
+                // pad a short last row, so that its ASCII lines up with the rows above it
+                None => out.write_str("   ")?,
+            }
+        }
+        out.write_str("  ")?;
+        for &byte in &row[..row_length] {
+            let c = if byte.is_ascii_graphic() || byte == b' ' {
+                char::from(byte)
+            } else {
+                '.'
+            };
+            out.write_char(c)?;
+        }
+        out.write_char('\n')?;
+    }
+    return Ok(());
+}
+
+/// The general purpose registers and the flags, as dump_registers found them
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+#[repr(C)]
+pub struct Registers {
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rbp: u64,
+    pub rsp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rflags: u64,
+}
+
+impl Registers {
+    /// The general purpose registers with their names, i.e. everything but rflags
+    pub fn general(&self) -> [(&'static str, u64); 16] {
+        return [
+            ("rax", self.rax),
+            ("rbx", self.rbx),
+            ("rcx", self.rcx),
+            ("rdx", self.rdx),
+            ("rsi", self.rsi),
+            ("rdi", self.rdi),
+            ("rbp", self.rbp),
+            ("rsp", self.rsp),
+            ("r8", self.r8),
+            ("r9", self.r9),
+            ("r10", self.r10),
+            ("r11", self.r11),
+            ("r12", self.r12),
+            ("r13", self.r13),
+            ("r14", self.r14),
+            ("r15", self.r15),
+        ];
+    }
+}
+
+/// Four registers per line, with the names and values lined up, e.g.
+///
+/// rax=0x0000000000000001 rbx=0x0000000000000000 rcx=...
+/// ...
+/// r8 =0x0000000000000000 r9 =0x0000000000000000 r10=...
+/// ...
+/// rflags=0x0000000000000246
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (name, value)) in self.general().iter().enumerate() {
+            let separator = if i % 4 == 3 { "\n" } else { " " };
+            write!(f, "{:<3}={:#018x}{}", name, value, separator)?;
+        }
+        return write!(f, "rflags={:#018x}", self.rflags);
+    }
+}
+
+/// Returns what the registers hold right where this is called, which is why it is always inlined;
+/// as a function of its own, we would only see what the call left in them.
+/// Note that the compiler decides what goes into the registers around this, so this is mostly
+/// useful for rsp, rbp, and rflags, and for getting a rough idea about the rest. One of the
+/// registers also holds the address of the Registers we write to, since the instructions need it
+/// somewhere. Every register is written to memory with a single mov, so none of them is
+/// overwritten before we read it.
+#[inline(always)]
+pub fn dump_registers() -> Registers {
+    let mut registers = Registers::default();
+    // only writes to the local registers, and pops what it pushes
+    unsafe {
+        asm!(
+            "mov [{registers} + {rax}], rax",
+            "mov [{registers} + {rbx}], rbx",
+            "mov [{registers} + {rcx}], rcx",
+            "mov [{registers} + {rdx}], rdx",
+            "mov [{registers} + {rsi}], rsi",
+            "mov [{registers} + {rdi}], rdi",
+            "mov [{registers} + {rbp}], rbp",
+            "mov [{registers} + {rsp}], rsp",
+            "mov [{registers} + {r8}], r8",
+            "mov [{registers} + {r9}], r9",
+            "mov [{registers} + {r10}], r10",
+            "mov [{registers} + {r11}], r11",
+            "mov [{registers} + {r12}], r12",
+            "mov [{registers} + {r13}], r13",
+            "mov [{registers} + {r14}], r14",
+            "mov [{registers} + {r15}], r15",
+            // there is no mov for the flags, so they take a detour over the stack
+            "pushfq",
+            "pop qword ptr [{registers} + {rflags}]",
+            registers = in(reg) &mut registers as *mut Registers,
+            rax = const offset_of!(Registers, rax),
+            rbx = const offset_of!(Registers, rbx),
+            rcx = const offset_of!(Registers, rcx),
+            rdx = const offset_of!(Registers, rdx),
+            rsi = const offset_of!(Registers, rsi),
+            rdi = const offset_of!(Registers, rdi),
+            rbp = const offset_of!(Registers, rbp),
+            rsp = const offset_of!(Registers, rsp),
+            r8 = const offset_of!(Registers, r8),
+            r9 = const offset_of!(Registers, r9),
+            r10 = const offset_of!(Registers, r10),
+            r11 = const offset_of!(Registers, r11),
+            r12 = const offset_of!(Registers, r12),
+            r13 = const offset_of!(Registers, r13),
+            r14 = const offset_of!(Registers, r14),
+            r15 = const offset_of!(Registers, r15),
+            rflags = const offset_of!(Registers, rflags),
+            options(preserves_flags),
+        );
+    }
+    return registers;
+}
+
+/// Prints the return addresses of the functions that led up to this call to SERIAL1, innermost
+/// first. We have no symbols in the kernel to look the addresses up in, but the host does; e.g.
+/// `addr2line -f -e target/x86_64-tdos/debug/tdos 0x...` names the function and line.
+pub fn backtrace() {
+    // writing to the serial port does not fail
+    let _ = write_backtrace(&mut SerialWriter);
+}
+
+/// Writes the backtrace to out, one frame per line, see backtrace.
+/// Every function keeps its frame pointer in rbp, and the first thing it does is push the rbp of
+/// its caller, right below the return address that call pushed. So rbp points at the caller's rbp,
+/// which points at its caller's rbp, and so on, with a return address right above each of them.
+/// This only works because we build with frame pointers (see .cargo/config.toml); without them,
+/// rbp is just another register, and the chain is garbage.
+/// There is nothing that marks the end of the chain, so we stop at the first frame that does not
+/// look like one: a null or misaligned rbp, a caller whose frame is not above ours on the stack
+/// (which would also loop forever), or one that is suspiciously far away. And after
+/// MAX_BACKTRACE_DEPTH frames, regardless.
+#[inline(never)]
+pub fn write_backtrace(out: &mut impl fmt::Write) -> fmt::Result {
+    let mut rbp: u64;
+    unsafe { asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags)) };
+    out.write_str("backtrace:\n")?;
+    for depth in 0..MAX_BACKTRACE_DEPTH {
+        if rbp == 0 || !rbp.is_multiple_of(8) {
+            break;
+        }
+        let frame = rbp as *const u64;
+        // the checks above and below are all we can do to make sure that the frame is on the stack,
+        // which is mapped
+        let (caller_rbp, return_address) = unsafe { (frame.read(), frame.add(1).read()) };
+        if return_address == 0 {
+            break;
+        }
+        writeln!(out, "{:>4}: {:#018x}", depth, return_address)?;
+        if caller_rbp <= rbp || caller_rbp - rbp > MAX_FRAME_SIZE {
+            break;
+        }
+        rbp = caller_rbp;
+    }
+    return Ok(());
+}
+
+/// Stops at a breakpoint, for poking around while debugging: the breakpoint handler dumps the
+/// registers and the top of the stack to SERIAL1 (see interrupts), and then execution simply
+/// continues after it. Called with arguments, it prints them like serial_println! first, so that
+/// the dump can be told apart from those of other breakpoints:
+///
+/// ```ignore
+/// brk!();
+/// brk!("before remapping page {:?}", page);
+/// ```
+///
+/// It works anywhere in the kernel once the IDT is loaded. In builds without debug assertions (like
+/// `--release`), it compiles to nothing, so that a forgotten breakpoint does not ship.
+#[macro_export]
+macro_rules! brk {
+    () => {
+        if cfg!(debug_assertions) {
+            $crate::debug::_brk();
+        }
+    };
+    ($($arg:tt)+) => {
+        if cfg!(debug_assertions) {
+            $crate::serial_println!($($arg)+);
+            $crate::debug::_brk();
+        }
+    };
+}
+
+#[doc(hidden)]
+pub fn _brk() {
+    x86_64::instructions::interrupts::int3();
+}
+
+// The rows show the bytes both as hex and as ASCII, and the short last row is padded
+#[test_case]
+fn test_hexdump() {
+    use alloc::string::String;
+
+    static BYTES: [u8; 18] = *b"tdos\x00\xffhexdump test";
+
+    let addr = BYTES.as_ptr() as usize;
+    let mut dump = String::new();
+    unsafe { write_hexdump(&mut dump, addr, BYTES.len()) }.unwrap();
+    let mut lines = dump.lines();
+    assert_eq!(
+        lines.next().unwrap(),
+        alloc::format!(
+            "{:#018x}: 74 64 6f 73 00 ff 68 65 78 64 75 6d 70 20 74 65  tdos..hexdump te",
+            addr
+        )
+    );
+    assert_eq!(
+        lines.next().unwrap(),
+        // the 14 missing bytes are padded with 3 spaces each
+        alloc::format!("{:#018x}: 73 74{:42}  st", addr + 16, "")
+    );
+    assert!(lines.next().is_none());
+
+    // and the same goes out over SERIAL1, which we cannot read back, so this only checks that it
+    // does not fault
+    unsafe { hexdump(addr, BYTES.len()) };
+}
+
+// We cannot put a value into a register and be sure the compiler leaves it there until the dump,
+// but one register has to hold the address of the dump itself, so that one shows up. The stack
+// pointer points somewhere close to our locals, and the always set bit 1 of rflags is set.
+#[test_case]
+fn test_dump_registers() {
+    let local = 0u64;
+    let registers = dump_registers();
+    // the copy we return is not where the asm wrote to, but it is on the same stack
+    let local_address = &local as *const u64 as u64;
+    assert!(registers.rsp.abs_diff(local_address) < 4096);
+    assert_eq!(registers.rflags & (1 << 1), 1 << 1);
+    assert!(registers
+        .general()
+        .iter()
+        .any(|(name, value)| !matches!(*name, "rsp" | "rbp") && value.abs_diff(local_address) < 4096));
+}
+
+// Every register shows up with its name, lined up in rows of four
+#[test_case]
+fn test_registers_display() {
+    use alloc::string::ToString;
+
+    let registers = Registers {
+        rax: 1,
+        r8: 0xdead_beef,
+        rflags: 0x246,
+        ..Registers::default()
+    };
+    let text = registers.to_string();
+    let mut lines = text.lines();
+    assert!(lines
+        .next()
+        .unwrap()
+        .starts_with("rax=0x0000000000000001 rbx=0x0000000000000000 "));
+    lines.next();
+    assert!(lines.next().unwrap().starts_with("r8 =0x00000000deadbeef r9 ="));
+    lines.next();
+    assert_eq!(lines.next().unwrap(), "rflags=0x0000000000000246");
+    assert!(lines.next().is_none());
+}
+
+// A backtrace from a few calls deep has a different return address for each of them
+#[test_case]
+fn test_backtrace() {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    #[inline(never)]
+    fn outer(out: &mut String) {
+        inner(out);
+        // keeps the call to inner from becoming a jump, which would not leave a frame behind
+        core::hint::black_box(());
+    }
+
+    #[inline(never)]
+    fn inner(out: &mut String) {
+        write_backtrace(out).unwrap();
+        core::hint::black_box(());
+    }
+
+    let mut text = String::new();
+    outer(&mut text);
+    let mut addresses: Vec<&str> = text
+        .lines()
+        .skip(1)
+        .map(|line| line.split_whitespace().last().unwrap())
+        .collect();
+    let frames = addresses.len();
+    addresses.sort_unstable();
+    addresses.dedup();
+    assert!(frames >= 2, "only {} frames in the backtrace", frames);
+    assert!(addresses.len() >= 2, "the backtrace repeats a single address: {}", text);
+
+    // and the same goes out over SERIAL1, which we cannot read back
+    backtrace();
+}
+
+// In a debug build, brk! hits the breakpoint handler exactly once, which dumps to SERIAL1, and
+// execution carries on right after it. In a release build, it does nothing at all.
+#[test_case]
+fn test_brk() {
+    use crate::interrupts::{counts, last_breakpoint};
+
+    // breakpoints are vector 3
+    let breakpoints = || counts().nth(3).unwrap().1;
+    let before = (breakpoints(), last_breakpoint());
+    brk!("test_brk: the register dump below is expected");
+    let after = (breakpoints(), last_breakpoint());
+    if cfg!(debug_assertions) {
+        assert_eq!(after.0, before.0 + 1);
+        assert_ne!(after.1, before.1);
+    } else {
+        assert_eq!(after, before);
+    }
+}
@@ -0,0 +1,101 @@
+use core::fmt;
+
+/// A fixed size buffer to format into with write!, for where allocating is not an option, e.g. in
+/// the panic handler, where the heap might be what we panicked about. Whatever does not fit is
+/// dropped, instead of failing: a cut off message is still a lot better than none. It is only ever
+/// cut between two characters, so the buffer always holds a valid str.
+pub struct FmtBuffer<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+    truncated: bool,
+}
+
+impl<const N: usize> FmtBuffer<N> {
+    pub const fn new() -> Self {
+        return FmtBuffer {
+            bytes: [0; N],
+            len: 0,
+            truncated: false,
+        };
+    }
+
+    /// What was written so far, as far as it fit
+    pub fn as_str(&self) -> &str {
+        // write_str only ever copies whole characters of a str
+        return core::str::from_utf8(&self.bytes[..self.len]).unwrap();
+    }
+
+    /// Whether anything was dropped, because the buffer was full
+    pub fn truncated(&self) -> bool {
+        return self.truncated;
+    }
+}
+
+impl<const N: usize> Default for FmtBuffer<N> {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl<const N: usize> fmt::Write for FmtBuffer<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let space = N - self.len;
+        let mut end = s.len().min(space);
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.bytes[self.len..self.len + end].copy_from_slice(&s.as_bytes()[..end]);
+        self.len += end;
+        if end < s.len() {
+            self.truncated = true;
+        }
+        // not an error, so that whoever formats into the buffer does not give up on everything else
+        return Ok(());
+    }
+}
+
+/// Prints what was written, followed by "[truncated]" if some of it did not fit
+impl<const N: usize> fmt::Display for FmtBuffer<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())?;
+        if self.truncated {
+            f.write_str(" [truncated]")?;
+        }
+        return Ok(());
+    }
+}
+
+/// Formats to a lot of x, more than fits into any buffer we use
+#[cfg(test)]
+pub struct Long;
+
+#[cfg(test)]
+impl fmt::Display for Long {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for _ in 0..10_000 {
+            f.write_str("x")?;
+        }
+        return Ok(());
+    }
+}
+
+// Whatever fits is kept, the rest is dropped, and a character is never cut in half
+#[test_case]
+fn test_truncate() {
+    use core::fmt::Write;
+
+    let mut buffer = FmtBuffer::<8>::new();
+    buffer.write_str("abc").unwrap();
+    assert_eq!(buffer.as_str(), "abc");
+    assert!(!buffer.truncated());
+    // ä takes two bytes, so the third one does not fit anymore
+    buffer.write_str("ääää").unwrap();
+    assert_eq!(buffer.as_str(), "abcää");
+    assert!(buffer.truncated());
+    assert_eq!(alloc::format!("{}", buffer), "abcää [truncated]");
+
+    let mut buffer = FmtBuffer::<1024>::new();
+    write!(buffer, "message: {}", Long).unwrap();
+    assert_eq!(buffer.as_str().len(), 1024);
+    assert!(buffer.as_str().starts_with("message: xxx"));
+}
@@ -0,0 +1,199 @@
+/// How the bytes of a pixel are laid out in the framebuffer
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PixelFormat {
+    /// Red first, then green and blue, plus possibly a padding byte
+    Rgb,
+    /// Blue first, then green and red, plus possibly a padding byte. This is what most UEFI
+    /// firmware (and QEMU) sets up.
+    Bgr,
+    /// A single byte of brightness per pixel
+    U8,
+}
+
+/// Describes a linear framebuffer: the pixels are stored row by row, with stride pixels per row,
+/// of which only the first width are visible. The rest of a row is padding that the hardware
+/// wants for alignment.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct FrameBufferInfo {
+    pub width: usize,
+    pub height: usize,
+    pub stride: usize,
+    pub bytes_per_pixel: usize,
+    pub format: PixelFormat,
+}
+
+impl FrameBufferInfo {
+    /// The number of bytes the framebuffer takes up
+    pub fn size(&self) -> usize {
+        return self.stride * self.height * self.bytes_per_pixel;
+    }
+}
+
+/// A color with 8 bits per channel, which the FrameBuffer translates into its PixelFormat
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb::new(0, 0, 0);
+    pub const WHITE: Rgb = Rgb::new(0xff, 0xff, 0xff);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        return Rgb { r, g, b };
+    }
+
+    /// The perceived brightness of the color, for PixelFormat::U8. Our eyes are most sensitive to
+    /// green and least to blue, so the channels are weighted like ITU-R BT.601 does (0.299 red,
+    /// 0.587 green, 0.114 blue), scaled to integers that add up to 256.
+    fn luma(self) -> u8 {
+        return ((self.r as u16 * 77 + self.g as u16 * 150 + self.b as u16 * 29) >> 8) as u8;
+    }
+}
+
+/// Draws into a linear framebuffer, i.e. a chunk of memory where every pixel of the screen has a
+/// few bytes, as opposed to the VGA text buffer, which only knows characters.
+/// Our bootloader only ever boots into VGA text mode, so there is no framebuffer to hand this yet;
+/// that needs a bootloader that switches into a graphics mode and tells us where it put the
+/// framebuffer, along with its FrameBufferInfo.
+pub struct FrameBuffer {
+    buffer: &'static mut [u8],
+    info: FrameBufferInfo,
+}
+
+impl FrameBuffer {
+    /// Panics if the buffer is too small for the info
+    pub fn new(buffer: &'static mut [u8], info: FrameBufferInfo) -> Self {
+        assert!(
+            buffer.len() >= info.size(),
+            "framebuffer of {} bytes is too small for {:?}",
+            buffer.len(),
+            info
+        );
+        return FrameBuffer { buffer, info };
+    }
+
+    pub fn info(&self) -> FrameBufferInfo {
+        return self.info;
+    }
+
+    /// The offset of the first byte of the pixel at x, y in the buffer
+    fn offset(&self, x: usize, y: usize) -> usize {
+        return (y * self.info.stride + x) * self.info.bytes_per_pixel;
+    }
+
+    /// Sets the pixel at x, y to the color. Pixels outside of the screen are silently dropped, so
+    /// that shapes can stick out of the screen.
+    pub fn put_pixel(&mut self, x: usize, y: usize, color: Rgb) {
+        if x >= self.info.width || y >= self.info.height {
+            return;
+        }
+        let offset = self.offset(x, y);
+        let bytes: [u8; 3] = match self.info.format {
+            PixelFormat::Rgb => [color.r, color.g, color.b],
+            PixelFormat::Bgr => [color.b, color.g, color.r],
+            PixelFormat::U8 => [color.luma(); 3],
+        };
+        // the padding bytes (and, for U8, the two bytes too many) are left alone
+        let channels = self.info.bytes_per_pixel.min(3);
+        for (i, byte) in bytes[..channels].iter().enumerate() {
+            // like the VGA text buffer, the framebuffer is read by the hardware behind the
+            // compiler's back, so the writes must not be optimized away
+            unsafe { core::ptr::write_volatile(&mut self.buffer[offset + i], *byte) };
+        }
+    }
+
+    /// Returns the bytes of the pixel at x, y, in the framebuffer's PixelFormat, or None if the
+    /// pixel is outside of the screen
+    pub fn pixel_bytes(&self, x: usize, y: usize) -> Option<&[u8]> {
+        if x >= self.info.width || y >= self.info.height {
+            return None;
+        }
+        let offset = self.offset(x, y);
+        return Some(&self.buffer[offset..offset + self.info.bytes_per_pixel]);
+    }
+
+    /// Fills the rectangle of width w and height h with x, y as its top left corner. The parts of
+    /// it outside of the screen are dropped.
+    pub fn fill_rect(&mut self, x: usize, y: usize, w: usize, h: usize, color: Rgb) {
+        let right = x.saturating_add(w).min(self.info.width);
+        let bottom = y.saturating_add(h).min(self.info.height);
+        for y in y..bottom {
+            for x in x..right {
+                self.put_pixel(x, y, color);
+            }
+        }
+    }
+
+    /// Fills the whole screen with the color
+    pub fn clear(&mut self, color: Rgb) {
+        self.fill_rect(0, 0, self.info.width, self.info.height, color);
+    }
+
+    /// Copies count rows of pixels starting at row src to the rows starting at dst, including their
+    /// padding. The rows may overlap, e.g. for scrolling everything up by a few rows. Rows that
+    /// would end up below the screen are dropped.
+    pub fn copy_rows(&mut self, src: usize, dst: usize, count: usize) {
+        let row_size = self.info.stride * self.info.bytes_per_pixel;
+        let count = count.min(self.info.height.saturating_sub(src.max(dst)));
+        self.buffer
+            .copy_within(src * row_size..(src + count) * row_size, dst * row_size);
+    }
+}
+
+/// Leaks a zeroed buffer for a framebuffer with the info, so that the tests can draw without an
+/// actual framebuffer
+#[cfg(test)]
+pub fn test_framebuffer(info: FrameBufferInfo) -> FrameBuffer {
+    use alloc::vec;
+
+    return FrameBuffer::new(vec![0; info.size()].leak(), info);
+}
+
+// Filling the screen sets every visible pixel in the right byte order, and leaves the padding alone
+#[test_case]
+fn test_clear() {
+    let info = FrameBufferInfo {
+        width: 16,
+        height: 8,
+        stride: 20,
+        bytes_per_pixel: 4,
+        format: PixelFormat::Bgr,
+    };
+    let mut framebuffer = test_framebuffer(info);
+    framebuffer.clear(Rgb::new(0x11, 0x22, 0x33));
+    assert_eq!(framebuffer.pixel_bytes(0, 0), Some(&[0x33, 0x22, 0x11, 0][..]));
+    assert_eq!(framebuffer.pixel_bytes(15, 7), Some(&[0x33, 0x22, 0x11, 0][..]));
+    assert_eq!(framebuffer.pixel_bytes(16, 0), None);
+    let padding = framebuffer.offset(16, 0);
+    assert!(framebuffer.buffer[padding..padding + 4 * 4]
+        .iter()
+        .all(|byte| *byte == 0));
+}
+
+// Rgb keeps the channels in order, U8 has a single byte of brightness, and fill_rect stays inside
+// the rectangle and the screen
+#[test_case]
+fn test_pixel_formats() {
+    let mut info = FrameBufferInfo {
+        width: 8,
+        height: 8,
+        stride: 8,
+        bytes_per_pixel: 3,
+        format: PixelFormat::Rgb,
+    };
+    let mut framebuffer = test_framebuffer(info);
+    framebuffer.fill_rect(6, 6, 10, 10, Rgb::new(1, 2, 3));
+    assert_eq!(framebuffer.pixel_bytes(7, 7), Some(&[1, 2, 3][..]));
+    assert_eq!(framebuffer.pixel_bytes(5, 7), Some(&[0, 0, 0][..]));
+
+    info.format = PixelFormat::U8;
+    info.bytes_per_pixel = 1;
+    let mut framebuffer = test_framebuffer(info);
+    framebuffer.put_pixel(0, 0, Rgb::WHITE);
+    framebuffer.put_pixel(1, 0, Rgb::new(0, 0xff, 0));
+    assert_eq!(framebuffer.pixel_bytes(0, 0), Some(&[0xff][..]));
+    assert_eq!(framebuffer.pixel_bytes(1, 0), Some(&[149][..]));
+}
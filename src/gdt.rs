@@ -5,31 +5,76 @@ use x86_64::structures::{
 };
 use x86_64::VirtAddr;
 
+/// Index into the interrupt stack table of the stack the double fault handler runs on
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
 
+/// Index into the interrupt stack table of the stack the general protection fault handler runs on
+pub const GENERAL_PROTECTION_FAULT_IST_INDEX: u16 = 1;
+
+// The task state segment (TSS). On x86_64 it no longer holds any task state, but it holds the
+// interrupt stack table (IST): a list of known good stacks the CPU can switch to before calling
+// an interrupt handler.
+// We need that for the double fault handler: A stack overflow hits the guard page below the
+// kernel stack, which causes a page fault. The CPU then tries to push the interrupt stack frame
+// onto the very same stack, which faults again, resulting in a double fault. Pushing the frame for
+// the double fault handler would fault again, which is a triple fault, and that resets the
+// machine. Switching to a fresh stack from the IST before calling the double fault handler breaks
+// this chain.
 lazy_static! {
     static ref TSS: TaskStateSegment = {
         let mut tss = TaskStateSegment::new();
         tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
+            // We do not have any memory management yet, so the stack is just a static array.
+            // Note that it has no guard page, so overflowing it silently corrupts memory.
+            const STACK_SIZE: usize = 4096 * 5;
+            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+            let stack_start = VirtAddr::from_ptr(&raw const STACK);
+            // stacks grow downwards, so the IST entry points at the end of the stack
+            stack_start + STACK_SIZE
+        };
+        // A general protection fault can be caused by a broken stack segment or stack pointer, so
+        // its handler gets a known good stack, too.
+        tss.interrupt_stack_table[GENERAL_PROTECTION_FAULT_IST_INDEX as usize] = {
+            const STACK_SIZE: usize = 4096 * 5;
+            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+            let stack_start = VirtAddr::from_ptr(&raw const STACK);
+            stack_start + STACK_SIZE
+        };
+        // rsp0: when an interrupt arrives while the CPU runs user code (ring 3), it switches to the
+        // kernel's privilege level, and with that to this stack, since the user's stack is nothing
+        // the kernel should trust. Nothing runs in ring 3 yet, so this is only used once it does.
+        tss.privilege_stack_table[0] = {
             const STACK_SIZE: usize = 4096 * 5;
             static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-            let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
-            let stack_end = stack_start + STACK_SIZE;
-            stack_end
+            let stack_start = VirtAddr::from_ptr(&raw const STACK);
+            stack_start + STACK_SIZE
         };
         tss
     };
 }
 
+// The global descriptor table (GDT). Segmentation is mostly unused in 64-bit mode, but the GDT is
+// still needed to switch between kernel mode (ring 0) and user mode (ring 3), and it is where the
+// TSS is loaded from. Alongside the table, we keep the selectors of its entries, since we need them
+// to load the segments.
+// The order of the entries matters for syscall and sysret, which do not read the selectors from
+// anywhere, but compute them from a single base selector: the kernel's data segment has to follow
+// its code segment, and the user's code segment has to follow the user's data segment.
 lazy_static! {
     static ref GDT: (GlobalDescriptorTable, Selectors) = {
         let mut gdt = GlobalDescriptorTable::new();
         let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
+        let data_selector = gdt.add_entry(Descriptor::kernel_data_segment());
+        let user_data_selector = gdt.add_entry(Descriptor::user_data_segment());
+        let user_code_selector = gdt.add_entry(Descriptor::user_code_segment());
         let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
         (
             gdt,
             Selectors {
                 code_selector,
+                data_selector,
+                user_code_selector,
+                user_data_selector,
                 tss_selector,
             },
         )
@@ -38,9 +83,31 @@ lazy_static! {
 
 struct Selectors {
     code_selector: SegmentSelector,
+    // not loaded anywhere yet, since ring 0 works fine with a null stack segment, see
+    // kernel_selectors
+    data_selector: SegmentSelector,
+    user_code_selector: SegmentSelector,
+    user_data_selector: SegmentSelector,
     tss_selector: SegmentSelector,
 }
 
+/// Returns the selectors of the kernel's (code, data) segments. The kernel does not load its data
+/// segment itself, since ring 0 works fine with a null stack segment, but syscall needs it to be
+/// right after the code segment.
+pub fn kernel_selectors() -> (SegmentSelector, SegmentSelector) {
+    return (GDT.1.code_selector, GDT.1.data_selector);
+}
+
+/// Returns the selectors of the user mode (code, data) segments, e.g. for the stack frame that
+/// iretq pops to switch to ring 3. Their descriptors have a privilege level (DPL) of 3, and so do
+/// the selectors (their RPL), since the CPU refuses a selector with a lower privilege level than
+/// the code using it.
+pub fn user_selectors() -> (SegmentSelector, SegmentSelector) {
+    return (GDT.1.user_code_selector, GDT.1.user_data_selector);
+}
+
+/// Loads the GDT, reloads the code segment register so it points at our code segment, and loads
+/// the TSS, so the CPU finds our interrupt stack table.
 pub fn init() {
     use x86_64::instructions::{
         segmentation::{Segment, CS},
@@ -52,3 +119,24 @@ pub fn init() {
         load_tss(GDT.1.tss_selector);
     }
 }
+
+// Besides the null entry, there are the kernel's and the user's code and data segments, and the
+// TSS, which takes up two entries. The user segments are the only ones with privilege level 3.
+#[test_case]
+fn test_user_segments() {
+    let entries = GDT.0.as_raw_slice();
+    assert_eq!(entries.len(), 7);
+
+    // the DPL lives in bits 45 and 46 of a descriptor
+    let dpl = |selector: SegmentSelector| (entries[usize::from(selector.index())] >> 45) & 0b11;
+    let (user_code, user_data) = user_selectors();
+    assert_eq!(dpl(user_code), 3);
+    assert_eq!(dpl(user_data), 3);
+    assert_eq!(user_code.rpl(), x86_64::PrivilegeLevel::Ring3);
+    assert_eq!(user_data.rpl(), x86_64::PrivilegeLevel::Ring3);
+    let (code, data) = kernel_selectors();
+    assert_eq!(dpl(code), 0);
+    assert_eq!(dpl(data), 0);
+    assert_eq!(data.index(), code.index() + 1);
+    assert_eq!(user_code.index(), user_data.index() + 1);
+}
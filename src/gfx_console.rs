@@ -0,0 +1,596 @@
+use core::fmt;
+
+use crate::framebuffer::{FrameBuffer, Rgb};
+use crate::global::Global;
+
+/// The size of a character cell in pixels
+pub const GLYPH_WIDTH: usize = 8;
+pub const GLYPH_HEIGHT: usize = 16;
+
+/// The glyph we draw for characters the font does not have, a hollow box
+const PLACEHOLDER: u8 = 0x7f;
+
+/// An 8x16 bitmap font for the printable ASCII characters, plus the PLACEHOLDER in place of DEL.
+/// Every glyph is 16 rows of 8 pixels, top to bottom, with the leftmost pixel in the highest bit,
+/// like the VGA's own fonts. Capitals are 9 rows high, starting at row 3, with the baseline at row
+/// 11 and room for descenders below it. The rightmost column stays empty, so that characters next
+/// to each other do not touch.
+const PRINTABLE: [[u8; GLYPH_HEIGHT]; 96] = [
+    // 0x20 ' '
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x21 '!'
+    [
+        0x00, 0x00, 0x00, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x00, 0x10, 0x10, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x22 '"'
+    [
+        0x00, 0x00, 0x00, 0x28, 0x28, 0x28, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x23 '#'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x28, 0x28, 0xfe, 0x28, 0x28, 0xfe, 0x28, 0x28, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x24 '$'
+    [
+        0x00, 0x00, 0x00, 0x10, 0x7c, 0x90, 0x90, 0x7c, 0x12, 0x12, 0x7c, 0x10, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x25 '%'
+    [
+        0x00, 0x00, 0x00, 0x00, 0xc4, 0xc8, 0x10, 0x10, 0x20, 0x4c, 0x8c, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x26 '&'
+    [
+        0x00, 0x00, 0x00, 0x30, 0x48, 0x48, 0x30, 0x64, 0x94, 0x88, 0x94, 0x62, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x27 '\''
+    [
+        0x00, 0x00, 0x00, 0x10, 0x10, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x28 '('
+    [
+        0x00, 0x00, 0x00, 0x08, 0x10, 0x20, 0x20, 0x20, 0x20, 0x20, 0x10, 0x08, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x29 ')'
+    [
+        0x00, 0x00, 0x00, 0x20, 0x10, 0x08, 0x08, 0x08, 0x08, 0x08, 0x10, 0x20, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x2a '*'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x44, 0x28, 0xfe, 0x28, 0x44, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x2b '+'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x10, 0xfe, 0x10, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x2c ','
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x10, 0x20, 0x00, 0x00,
+    ],
+    // 0x2d '-'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xfe, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x2e '.'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x2f '/'
+    [
+        0x00, 0x00, 0x00, 0x02, 0x04, 0x04, 0x08, 0x10, 0x20, 0x40, 0x40, 0x80, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x30 '0'
+    [
+        0x00, 0x00, 0x00, 0x7c, 0x82, 0x86, 0x8a, 0x92, 0xa2, 0xc2, 0x82, 0x7c, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x31 '1'
+    [
+        0x00, 0x00, 0x00, 0x10, 0x30, 0x50, 0x10, 0x10, 0x10, 0x10, 0x10, 0x7c, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x32 '2'
+    [
+        0x00, 0x00, 0x00, 0x7c, 0x82, 0x02, 0x04, 0x18, 0x20, 0x40, 0x80, 0xfe, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x33 '3'
+    [
+        0x00, 0x00, 0x00, 0x7c, 0x82, 0x02, 0x02, 0x3c, 0x02, 0x02, 0x82, 0x7c, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x34 '4'
+    [
+        0x00, 0x00, 0x00, 0x0c, 0x14, 0x24, 0x44, 0x84, 0xfe, 0x04, 0x04, 0x04, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x35 '5'
+    [
+        0x00, 0x00, 0x00, 0xfe, 0x80, 0x80, 0xfc, 0x02, 0x02, 0x02, 0x82, 0x7c, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x36 '6'
+    [
+        0x00, 0x00, 0x00, 0x3c, 0x40, 0x80, 0xfc, 0x82, 0x82, 0x82, 0x82, 0x7c, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x37 '7'
+    [
+        0x00, 0x00, 0x00, 0xfe, 0x02, 0x04, 0x08, 0x10, 0x10, 0x10, 0x10, 0x10, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x38 '8'
+    [
+        0x00, 0x00, 0x00, 0x7c, 0x82, 0x82, 0x82, 0x7c, 0x82, 0x82, 0x82, 0x7c, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x39 '9'
+    [
+        0x00, 0x00, 0x00, 0x7c, 0x82, 0x82, 0x82, 0x7e, 0x02, 0x02, 0x04, 0x78, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x3a ':'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x3b ';'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x18, 0x18, 0x10, 0x20, 0x00, 0x00,
+    ],
+    // 0x3c '<'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x04, 0x08, 0x10, 0x20, 0x10, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x3d '='
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xfe, 0x00, 0xfe, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x3e '>'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x40, 0x20, 0x10, 0x08, 0x10, 0x20, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x3f '?'
+    [
+        0x00, 0x00, 0x00, 0x7c, 0x82, 0x02, 0x04, 0x08, 0x10, 0x10, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x40 '@'
+    [
+        0x00, 0x00, 0x00, 0x7c, 0x82, 0x9e, 0xa2, 0xa2, 0xa6, 0x9a, 0x80, 0x7c, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x41 'A'
+    [
+        0x00, 0x00, 0x00, 0x10, 0x28, 0x44, 0x82, 0x82, 0xfe, 0x82, 0x82, 0x82, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x42 'B'
+    [
+        0x00, 0x00, 0x00, 0xfc, 0x82, 0x82, 0x82, 0xfc, 0x82, 0x82, 0x82, 0xfc, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x43 'C'
+    [
+        0x00, 0x00, 0x00, 0x7c, 0x82, 0x80, 0x80, 0x80, 0x80, 0x80, 0x82, 0x7c, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x44 'D'
+    [
+        0x00, 0x00, 0x00, 0xf8, 0x84, 0x82, 0x82, 0x82, 0x82, 0x82, 0x84, 0xf8, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x45 'E'
+    [
+        0x00, 0x00, 0x00, 0xfe, 0x80, 0x80, 0x80, 0xf8, 0x80, 0x80, 0x80, 0xfe, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x46 'F'
+    [
+        0x00, 0x00, 0x00, 0xfe, 0x80, 0x80, 0x80, 0xf8, 0x80, 0x80, 0x80, 0x80, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x47 'G'
+    [
+        0x00, 0x00, 0x00, 0x7c, 0x82, 0x80, 0x80, 0x9e, 0x82, 0x82, 0x82, 0x7c, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x48 'H'
+    [
+        0x00, 0x00, 0x00, 0x82, 0x82, 0x82, 0x82, 0xfe, 0x82, 0x82, 0x82, 0x82, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x49 'I'
+    [
+        0x00, 0x00, 0x00, 0x7c, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x7c, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x4a 'J'
+    [
+        0x00, 0x00, 0x00, 0x3e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x84, 0x84, 0x78, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x4b 'K'
+    [
+        0x00, 0x00, 0x00, 0x82, 0x84, 0x88, 0x90, 0xe0, 0x90, 0x88, 0x84, 0x82, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x4c 'L'
+    [
+        0x00, 0x00, 0x00, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0xfe, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x4d 'M'
+    [
+        0x00, 0x00, 0x00, 0x82, 0xc6, 0xaa, 0x92, 0x82, 0x82, 0x82, 0x82, 0x82, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x4e 'N'
+    [
+        0x00, 0x00, 0x00, 0x82, 0xc2, 0xa2, 0x92, 0x8a, 0x86, 0x82, 0x82, 0x82, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x4f 'O'
+    [
+        0x00, 0x00, 0x00, 0x7c, 0x82, 0x82, 0x82, 0x82, 0x82, 0x82, 0x82, 0x7c, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x50 'P'
+    [
+        0x00, 0x00, 0x00, 0xfc, 0x82, 0x82, 0x82, 0xfc, 0x80, 0x80, 0x80, 0x80, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x51 'Q'
+    [
+        0x00, 0x00, 0x00, 0x7c, 0x82, 0x82, 0x82, 0x82, 0x82, 0x8a, 0x84, 0x7a, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x52 'R'
+    [
+        0x00, 0x00, 0x00, 0xfc, 0x82, 0x82, 0x82, 0xfc, 0x90, 0x88, 0x84, 0x82, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x53 'S'
+    [
+        0x00, 0x00, 0x00, 0x7c, 0x82, 0x80, 0x80, 0x7c, 0x02, 0x02, 0x82, 0x7c, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x54 'T'
+    [
+        0x00, 0x00, 0x00, 0xfe, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x55 'U'
+    [
+        0x00, 0x00, 0x00, 0x82, 0x82, 0x82, 0x82, 0x82, 0x82, 0x82, 0x82, 0x7c, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x56 'V'
+    [
+        0x00, 0x00, 0x00, 0x82, 0x82, 0x82, 0x82, 0x44, 0x44, 0x28, 0x28, 0x10, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x57 'W'
+    [
+        0x00, 0x00, 0x00, 0x82, 0x82, 0x82, 0x82, 0x92, 0x92, 0xaa, 0xc6, 0x82, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x58 'X'
+    [
+        0x00, 0x00, 0x00, 0x82, 0x82, 0x44, 0x28, 0x10, 0x28, 0x44, 0x82, 0x82, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x59 'Y'
+    [
+        0x00, 0x00, 0x00, 0x82, 0x82, 0x44, 0x28, 0x10, 0x10, 0x10, 0x10, 0x10, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x5a 'Z'
+    [
+        0x00, 0x00, 0x00, 0xfe, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0xfe, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x5b '['
+    [
+        0x00, 0x00, 0x00, 0x3c, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x3c, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x5c '\\'
+    [
+        0x00, 0x00, 0x00, 0x80, 0x40, 0x40, 0x20, 0x10, 0x08, 0x04, 0x04, 0x02, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x5d ']'
+    [
+        0x00, 0x00, 0x00, 0x78, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x78, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x5e '^'
+    [
+        0x00, 0x00, 0x00, 0x10, 0x28, 0x44, 0x82, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x5f '_'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xfe, 0x00, 0x00,
+    ],
+    // 0x60 '`'
+    [
+        0x00, 0x00, 0x00, 0x20, 0x10, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x61 'a'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7c, 0x02, 0x7e, 0x82, 0x86, 0x7a, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x62 'b'
+    [
+        0x00, 0x00, 0x00, 0x80, 0x80, 0x80, 0xfc, 0x82, 0x82, 0x82, 0x82, 0xfc, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x63 'c'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7c, 0x80, 0x80, 0x80, 0x80, 0x7c, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x64 'd'
+    [
+        0x00, 0x00, 0x00, 0x02, 0x02, 0x02, 0x7e, 0x82, 0x82, 0x82, 0x82, 0x7e, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x65 'e'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7c, 0x82, 0xfe, 0x80, 0x80, 0x7c, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x66 'f'
+    [
+        0x00, 0x00, 0x00, 0x1c, 0x20, 0x20, 0xfc, 0x20, 0x20, 0x20, 0x20, 0x20, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x67 'g'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7e, 0x82, 0x82, 0x82, 0x82, 0x7e, 0x02, 0x02, 0x7c, 0x00,
+    ],
+    // 0x68 'h'
+    [
+        0x00, 0x00, 0x00, 0x80, 0x80, 0x80, 0xfc, 0x82, 0x82, 0x82, 0x82, 0x82, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x69 'i'
+    [
+        0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x30, 0x10, 0x10, 0x10, 0x10, 0x38, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x6a 'j'
+    [
+        0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x84, 0x78, 0x00,
+    ],
+    // 0x6b 'k'
+    [
+        0x00, 0x00, 0x00, 0x80, 0x80, 0x80, 0x84, 0x88, 0xf0, 0x88, 0x84, 0x82, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x6c 'l'
+    [
+        0x00, 0x00, 0x00, 0x30, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x38, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x6d 'm'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xec, 0x92, 0x92, 0x92, 0x92, 0x92, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x6e 'n'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xfc, 0x82, 0x82, 0x82, 0x82, 0x82, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x6f 'o'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7c, 0x82, 0x82, 0x82, 0x82, 0x7c, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x70 'p'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xfc, 0x82, 0x82, 0x82, 0x82, 0xfc, 0x80, 0x80, 0x80, 0x00,
+    ],
+    // 0x71 'q'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7e, 0x82, 0x82, 0x82, 0x82, 0x7e, 0x02, 0x02, 0x02, 0x00,
+    ],
+    // 0x72 'r'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xbc, 0xc2, 0x80, 0x80, 0x80, 0x80, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x73 's'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7e, 0x80, 0x7c, 0x02, 0x02, 0xfc, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x74 't'
+    [
+        0x00, 0x00, 0x00, 0x20, 0x20, 0x20, 0xfc, 0x20, 0x20, 0x20, 0x20, 0x1c, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x75 'u'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x82, 0x82, 0x82, 0x82, 0x82, 0x7e, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x76 'v'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x82, 0x82, 0x44, 0x44, 0x28, 0x10, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x77 'w'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x82, 0x82, 0x92, 0x92, 0xaa, 0x44, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x78 'x'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x82, 0x44, 0x28, 0x28, 0x44, 0x82, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x79 'y'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x82, 0x82, 0x82, 0x82, 0x82, 0x7e, 0x02, 0x02, 0x7c, 0x00,
+    ],
+    // 0x7a 'z'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xfe, 0x04, 0x08, 0x20, 0x40, 0xfe, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x7b '{'
+    [
+        0x00, 0x00, 0x00, 0x0c, 0x10, 0x10, 0x10, 0x60, 0x10, 0x10, 0x10, 0x0c, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x7c '|'
+    [
+        0x00, 0x00, 0x00, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x00,
+    ],
+    // 0x7d '}'
+    [
+        0x00, 0x00, 0x00, 0x60, 0x10, 0x10, 0x10, 0x0c, 0x10, 0x10, 0x10, 0x60, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x7e '~'
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x62, 0x92, 0x8c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // 0x7f DEL, the placeholder
+    [
+        0x00, 0x00, 0x00, 0xfe, 0x82, 0x82, 0x82, 0x82, 0x82, 0x82, 0x82, 0xfe, 0x00, 0x00, 0x00, 0x00,
+    ],
+];
+
+/// The font indexed by ASCII code. The control characters have no glyphs, they are all blank.
+pub const FONT: [[u8; GLYPH_HEIGHT]; 128] = {
+    let mut font = [[0; GLYPH_HEIGHT]; 128];
+    let mut i = 0;
+    while i < PRINTABLE.len() {
+        font[0x20 + i] = PRINTABLE[i];
+        i += 1;
+    }
+    font
+};
+
+/// The graphical console, once there is a framebuffer to draw it on, see init. print! and friends
+/// write to it if the VGA is not in text mode, see vga_buffer::text_mode.
+pub static CONSOLE: Global<GfxConsole> = Global::new("gfx_console::CONSOLE");
+
+/// Sets up the CONSOLE on the framebuffer, and clears it. Panics if it is called twice.
+pub fn init(framebuffer: FrameBuffer) {
+    CONSOLE.init(GfxConsole::new(framebuffer));
+}
+
+/// The graphical equivalent of vga_buffer::Writer: it draws text onto a framebuffer, character cell
+/// by character cell, with the FONT. Like the Writer, it writes to the last row and scrolls
+/// everything up when that row is full, but it does not keep any scrollback.
+pub struct GfxConsole {
+    framebuffer: FrameBuffer,
+    column: usize,
+    foreground: Rgb,
+    background: Rgb,
+}
+
+impl GfxConsole {
+    /// Panics if the framebuffer is too small for even a single character cell, since there would
+    /// be no row to write to, or to scroll
+    pub fn new(framebuffer: FrameBuffer) -> Self {
+        let info = framebuffer.info();
+        assert!(
+            info.width >= GLYPH_WIDTH && info.height >= GLYPH_HEIGHT,
+            "gfx_console: a {}x{} framebuffer cannot fit a single {}x{} character cell",
+            info.width,
+            info.height,
+            GLYPH_WIDTH,
+            GLYPH_HEIGHT
+        );
+        let mut console = GfxConsole {
+            framebuffer,
+            column: 0,
+            foreground: Rgb::new(0xaa, 0xaa, 0xaa),
+            background: Rgb::BLACK,
+        };
+        console.clear();
+        return console;
+    }
+
+    /// The number of character cells that fit on the screen side by side
+    pub fn columns(&self) -> usize {
+        return self.framebuffer.info().width / GLYPH_WIDTH;
+    }
+
+    /// The number of character cells that fit on the screen on top of each other
+    pub fn rows(&self) -> usize {
+        return self.framebuffer.info().height / GLYPH_HEIGHT;
+    }
+
+    /// Sets the colors for everything written from now on
+    pub fn set_color(&mut self, foreground: Rgb, background: Rgb) {
+        self.foreground = foreground;
+        self.background = background;
+    }
+
+    /// Fills the screen with the background color, and starts over in the first column
+    pub fn clear(&mut self) {
+        self.framebuffer.clear(self.background);
+        self.column = 0;
+    }
+
+    /// Writes a single character to the last row at self.column, and advances the column. In case
+    /// the row is full, or the character is a newline, we start a new line first. Characters the
+    /// FONT does not have show up as the PLACEHOLDER.
+    pub fn write_char(&mut self, c: char) {
+        match c {
+            '\n' => self.new_line(),
+            '\r' => self.column = 0,
+            c => {
+                if self.column >= self.columns() {
+                    self.new_line();
+                }
+                let glyph = match c {
+                    ' '..='~' => c as u8,
+                    _ => PLACEHOLDER,
+                };
+                self.draw_glyph(self.rows() - 1, self.column, glyph);
+                self.column += 1;
+            },
+        }
+    }
+
+    pub fn write_string(&mut self, s: &str) {
+        for c in s.chars() {
+            self.write_char(c);
+        }
+    }
+
+    /// Draws the FONT's glyph for the ASCII code into the character cell at row and column, in the
+    /// foreground color on the background color
+    fn draw_glyph(&mut self, row: usize, column: usize, ascii: u8) {
+        let top = row * GLYPH_HEIGHT;
+        let left = column * GLYPH_WIDTH;
+        for (y, bits) in FONT[ascii as usize].iter().enumerate() {
+            for x in 0..GLYPH_WIDTH {
+                let color = if bits & (0x80 >> x) != 0 {
+                    self.foreground
+                } else {
+                    self.background
+                };
+                self.framebuffer.put_pixel(left + x, top + y, color);
+            }
+        }
+    }
+
+    /// Scrolls everything up by one row of characters by copying the pixels, and blanks the last
+    /// row. The pixels below the last full row of characters are left alone.
+    fn new_line(&mut self) {
+        let rows = self.rows();
+        self.framebuffer.copy_rows(GLYPH_HEIGHT, 0, (rows - 1) * GLYPH_HEIGHT);
+        let width = self.framebuffer.info().width;
+        self.framebuffer
+            .fill_rect(0, (rows - 1) * GLYPH_HEIGHT, width, GLYPH_HEIGHT, self.background);
+        self.column = 0;
+    }
+}
+
+impl fmt::Write for GfxConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_string(s);
+        return Ok(());
+    }
+}
+
+/// A console on a small framebuffer in memory, 4 characters wide and 3 high
+#[cfg(test)]
+fn test_console() -> GfxConsole {
+    use crate::framebuffer::{test_framebuffer, FrameBufferInfo, PixelFormat};
+
+    return GfxConsole::new(test_framebuffer(FrameBufferInfo {
+        width: 4 * GLYPH_WIDTH,
+        height: 3 * GLYPH_HEIGHT,
+        stride: 4 * GLYPH_WIDTH,
+        bytes_per_pixel: 4,
+        format: PixelFormat::Rgb,
+    }));
+}
+
+/// Returns whether the pixels of the character cell at row and column show the glyph in white on
+/// black
+#[cfg(test)]
+fn shows_glyph(console: &GfxConsole, row: usize, column: usize, ascii: u8) -> bool {
+    return FONT[ascii as usize].iter().enumerate().all(|(y, bits)| {
+        return (0..GLYPH_WIDTH).all(|x| {
+            let on = bits & (0x80 >> x) != 0;
+            let expected: &[u8] = if on { &[0xff, 0xff, 0xff, 0] } else { &[0, 0, 0, 0] };
+            let pixel = console
+                .framebuffer
+                .pixel_bytes(column * GLYPH_WIDTH + x, row * GLYPH_HEIGHT + y);
+            return pixel == Some(expected);
+        });
+    });
+}
+
+// Rendering 'A' sets exactly the pixels of its glyph in the foreground color
+#[test_case]
+fn test_render_glyph() {
+    let mut console = test_console();
+    console.set_color(Rgb::WHITE, Rgb::BLACK);
+    console.write_char('A');
+    assert!(shows_glyph(&console, 2, 0, b'A'));
+    // the glyph is not blank, so this also checks that something was drawn at all
+    assert!(FONT[b'A' as usize].iter().any(|bits| *bits != 0));
+    assert!(shows_glyph(&console, 2, 1, b' '));
+}
+
+// A new line moves every row of characters up, and a full row continues on the next line
+#[test_case]
+fn test_scroll() {
+    let mut console = test_console();
+    console.set_color(Rgb::WHITE, Rgb::BLACK);
+    console.write_string("ab\ncdefg\u{e9}");
+    assert!(shows_glyph(&console, 0, 0, b'a'));
+    assert!(shows_glyph(&console, 0, 1, b'b'));
+    assert!(shows_glyph(&console, 1, 3, b'f'));
+    assert!(shows_glyph(&console, 2, 0, b'g'));
+    assert!(shows_glyph(&console, 2, 1, PLACEHOLDER));
+}
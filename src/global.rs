@@ -0,0 +1,84 @@
+use core::ops::Deref;
+use spin::Once;
+
+use crate::spin_lock::{SpinLock, SpinLockGuard};
+
+/// A global behind a SpinLock that is initialized explicitly, e.g. the vga_buffer::WRITER.
+/// A lazy_static initializes itself whenever it happens to be used first, which hides the order in
+/// which things are set up, and makes it impossible to tell whether something was set up at all.
+/// A Global instead has to be initialized by calling init, and using it before that panics with a
+/// message saying which Global it was, instead of quietly setting it up in the middle of whatever
+/// used it.
+/// It derefs to the SpinLock, so it is locked just like one. The exception is try_lock, which also
+/// returns None if the Global is not initialized yet, so that e.g. a panic handler can use it
+/// without panicking again.
+pub struct Global<T> {
+    name: &'static str,
+    inner: Once<SpinLock<T>>,
+}
+
+impl<T> Global<T> {
+    /// Creates an uninitialized Global. The name shows up in the panic message if it is used
+    /// before it is initialized.
+    pub const fn new(name: &'static str) -> Self {
+        return Global {
+            name,
+            inner: Once::new(),
+        };
+    }
+
+    /// Initializes the Global with the value. Panics if it is initialized already.
+    pub fn init(&self, value: T) {
+        let mut initialized = false;
+        self.inner.call_once(|| {
+            initialized = true;
+            return SpinLock::new(value);
+        });
+        assert!(initialized, "{} is initialized twice", self.name);
+    }
+
+    /// Returns the SpinLock, or None if the Global is not initialized yet
+    pub fn get(&self) -> Option<&SpinLock<T>> {
+        return self.inner.r#try();
+    }
+
+    /// Locks the SpinLock if the Global is initialized and the SpinLock is not locked already
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        return self.get()?.try_lock();
+    }
+}
+
+impl<T> Deref for Global<T> {
+    type Target = SpinLock<T>;
+
+    fn deref(&self) -> &SpinLock<T> {
+        return match self.get() {
+            Some(inner) => inner,
+            None => panic!("{} is used before it is initialized", self.name),
+        };
+    }
+}
+
+// An uninitialized Global has nothing to lock, and after init, it holds the value
+#[test_case]
+fn test_init() {
+    let global = Global::new("test_init::global");
+    assert!(global.get().is_none());
+    assert!(global.try_lock().is_none());
+
+    global.init(42);
+    assert_eq!(*global.lock(), 42);
+    assert!(global.try_lock().is_some());
+}
+
+#[test_case]
+const TEST_USE_BEFORE_INIT: crate::test_runner::ShouldPanic = crate::test_runner::ShouldPanic {
+    name: concat!(module_path!(), "::test_use_before_init"),
+    test: test_use_before_init,
+};
+
+#[cfg(test)]
+fn test_use_before_init() {
+    let global: Global<u32> = Global::new("test_use_before_init::global");
+    let _ = global.lock();
+}
@@ -1,34 +1,771 @@
 use crate::gdt;
-use crate::println;
+use crate::memory::GuardPage;
+use crate::qemu::{exit_qemu, QemuExitCode};
+use crate::{println, serial_println};
+use core::fmt;
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
 use lazy_static::lazy_static;
-use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+use spin::{Mutex, Once};
+use x86_64::structures::idt::{
+    EntryOptions, HandlerFunc, InterruptDescriptorTable, InterruptStackFrame, InterruptStackFrameValue,
+    PageFaultErrorCode,
+};
+use x86_64::{PrivilegeLevel, VirtAddr};
 
-lazy_static! {
-    static ref IDT: InterruptDescriptorTable = {
-        let mut idt = InterruptDescriptorTable::new();
-        idt.breakpoint.set_handler_fn(breakpoint_handler);
+/// Runs the closure with interrupts disabled, and restores whether they were enabled afterwards.
+/// Re-exported, so that a critical section does not need the whole x86_64 path; see
+/// InterruptGuard for a critical section that is not a closure.
+pub use x86_64::instructions::interrupts::without_interrupts;
+
+/// Disables interrupts while it lives, and restores whether they were enabled when it is dropped,
+/// like without_interrupts does for a closure. This is handier for a long critical section with
+/// early returns, or one that has to last exactly as long as some other guard, e.g. a lock that an
+/// interrupt handler also takes:
+///
+/// ```ignore
+/// let _interrupts = InterruptGuard::new();
+/// let buffer = BUFFER.lock();
+/// ```
+///
+/// Locals are dropped in the reverse order of their declaration, so the lock is released first, and
+/// interrupts are only enabled again after that. Since the guard restores the previous state
+/// instead of just enabling interrupts, guards can be nested.
+#[must_use = "interrupts are enabled again as soon as the guard is dropped"]
+pub struct InterruptGuard {
+    were_enabled: bool,
+}
+
+impl InterruptGuard {
+    pub fn new() -> Self {
+        // the interrupt flag lives in rflags, which is where are_enabled looks
+        let were_enabled = x86_64::instructions::interrupts::are_enabled();
+        if were_enabled {
+            x86_64::instructions::interrupts::disable();
+        }
+        return InterruptGuard { were_enabled };
+    }
+}
+
+impl Default for InterruptGuard {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        if self.were_enabled {
+            x86_64::instructions::interrupts::enable();
+        }
+    }
+}
+
+/// The IDT, along with which of its entries (besides the CPU exceptions) have a handler.
+/// Device drivers add their handlers with register, instead of everybody editing one big table
+/// definition in this module.
+struct Idt {
+    table: InterruptDescriptorTable,
+    registered: [bool; 256],
+}
+
+impl Idt {
+    /// Creates an IDT with handlers for the CPU exceptions we handle
+    fn new() -> Self {
+        let mut table = InterruptDescriptorTable::new();
+        table.divide_error.set_handler_fn(divide_error_handler);
+        table.breakpoint.set_handler_fn(breakpoint_handler);
+        table.invalid_opcode.set_handler_fn(invalid_opcode_handler);
+        table.page_fault.set_handler_fn(page_fault_handler);
         unsafe {
-            idt.double_fault
+            table
+                .double_fault
                 .set_handler_fn(double_fault_handler)
                 .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+            table
+                .general_protection_fault
+                .set_handler_fn(general_protection_fault_handler)
+                .set_stack_index(gdt::GENERAL_PROTECTION_FAULT_IST_INDEX);
         }
-        idt
-    };
+        return Idt {
+            table,
+            registered: [false; 256],
+        };
+    }
+
+    /// Sets the handler for the vector, and returns its entry, or None if the vector already has
+    /// one. The handler is just an address here, so that entry points written in assembly fit in,
+    /// too; see register_raw.
+    ///
+    /// # Safety
+    ///
+    /// The handler has to be the address of an interrupt handler.
+    unsafe fn register(&mut self, vector: u8, handler: VirtAddr) -> Option<&mut EntryOptions> {
+        // the first 32 vectors are the CPU exceptions, which are set up in new
+        assert!(
+            vector >= FIRST_FREE_VECTOR,
+            "vector {} is reserved for CPU exceptions",
+            vector
+        );
+        if self.registered[usize::from(vector)] {
+            return None;
+        }
+        self.registered[usize::from(vector)] = true;
+        return Some(unsafe { self.table[usize::from(vector)].set_handler_addr(handler) });
+    }
+}
+
+/// The first vector that is not reserved for CPU exceptions
+pub const FIRST_FREE_VECTOR: u8 = 32;
+
+// Vectors of the CPU exceptions we handle, e.g. to look up their counts
+pub const DIVIDE_ERROR_VECTOR: u8 = 0;
+pub const BREAKPOINT_VECTOR: u8 = 3;
+pub const INVALID_OPCODE_VECTOR: u8 = 6;
+pub const DOUBLE_FAULT_VECTOR: u8 = 8;
+pub const GENERAL_PROTECTION_FAULT_VECTOR: u8 = 13;
+pub const PAGE_FAULT_VECTOR: u8 = 14;
+
+/// How often each vector fired so far. Every handler counts itself with count.
+static COUNTS: [AtomicU64; 256] = [const { AtomicU64::new(0) }; 256];
+
+/// Counts one interrupt on the vector. Every interrupt handler should call this first thing, so
+/// that print_stats shows it.
+/// This only needs to be atomic, not ordered with anything else, so it is a single relaxed
+/// increment, which is cheap enough even for the timer.
+pub fn count(vector: u8) {
+    COUNTS[usize::from(vector)].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns how often each vector fired so far, as (vector, count) pairs for all 256 vectors
+pub fn counts() -> impl Iterator<Item = (u8, u64)> {
+    return (0..=u8::MAX).map(|vector| (vector, COUNTS[usize::from(vector)].load(Ordering::Relaxed)));
+}
+
+/// Returns how often the vector fired so far
+pub fn count_of(vector: u8) -> u64 {
+    return COUNTS[usize::from(vector)].load(Ordering::Relaxed);
+}
+
+/// Prints how often each vector fired so far to SERIAL1, skipping those that never fired. Useful to
+/// check that e.g. the timer actually ticks, or to catch an interrupt storm.
+pub fn print_stats() {
+    serial_println!("interrupt counts:");
+    for (vector, count) in counts().filter(|(_, count)| *count != 0) {
+        serial_println!("{:>5} {:>10}", vector, count);
+    }
+}
+
+// The CPU reads the IDT from memory on every interrupt, so this must never move, which is why it
+// lives in a static. Since the CPU reads it without caring about the mutex, entries may only be
+// changed with interrupts disabled.
+lazy_static! {
+    static ref IDT: Mutex<Idt> = Mutex::new(Idt::new());
+}
+
+/// What the handlers of the exceptions we cannot resume from do once they reported the exception,
+/// instead of halting, see set_fault_exit
+static FAULT_EXIT: Once<fn() -> !> = Once::new();
+
+/// Makes the handlers of the exceptions we cannot resume from (e.g. a page fault) call exit once
+/// they reported the exception, instead of halting. This is for integration tests that trigger
+/// such an exception on purpose, to check that the kernel's own handler ran (e.g. with count_of)
+/// and then exit QEMU. Only the first call has an effect.
+pub fn set_fault_exit(exit: fn() -> !) {
+    FAULT_EXIT.call_once(|| exit);
 }
 
+/// Ends the handler of an exception we cannot resume from: calls the fault exit if there is one
+/// (see set_fault_exit), and otherwise exits QEMU with QemuExitCode::Failed in a test run, since
+/// the test could never finish, or halts.
+fn halt_after_fault() -> ! {
+    if let Some(exit) = FAULT_EXIT.r#try() {
+        exit();
+    }
+    if crate::test_runner::running() {
+        exit_qemu(QemuExitCode::Failed);
+    }
+    crate::hlt_loop();
+}
+
+/// Loads the IDT. Handlers registered afterwards still take effect, since the CPU only remembers
+/// where the IDT is.
 pub fn init_dt() {
-    IDT.load();
+    let idt = IDT.lock();
+    // the IDT lives in a static, so it stays where it is for as long as the CPU uses it
+    unsafe {
+        idt.table.load_unsafe();
+    }
 }
 
+/// Registers the handler for the interrupt vector. Vectors below FIRST_FREE_VECTOR belong to the
+/// CPU exceptions, and are handled in this module.
+///
+/// # Panics
+///
+/// If the vector already has a handler, since two drivers claiming the same vector is a bug
+/// that would otherwise silently break one of them.
+pub fn register(vector: u8, handler: HandlerFunc) {
+    // the type of the handler makes sure it is one
+    unsafe { register_with(vector, handler_address(handler), |_| {}) };
+}
+
+/// Returns the address of the handler, for the IDT
+fn handler_address(handler: HandlerFunc) -> VirtAddr {
+    return VirtAddr::new(handler as usize as u64);
+}
+
+/// Like register, but the handler runs on the stack with the given index in the IST, see gdt.
+///
+/// # Safety
+///
+/// The stack index must be valid and not used by any other handler that could interrupt this one,
+/// or the handlers overwrite each other's stack.
+pub unsafe fn register_with_stack(vector: u8, handler: HandlerFunc, stack_index: u16) {
+    unsafe {
+        register_with(vector, handler_address(handler), |entry| {
+            entry.set_stack_index(stack_index);
+        });
+    }
+}
+
+/// Registers an entry point written in assembly, for handlers that need the registers exactly as
+/// they were when the interrupt fired, which an extern "x86-interrupt" fn cannot get at, since it
+/// saves and uses them as it sees fit. See syscall for an example.
+/// The privilege level is the least privileged ring that may trigger the interrupt with int; for
+/// anything else, int causes a general protection fault. Hardware interrupts and exceptions
+/// ignore it.
+///
+/// # Safety
+///
+/// The entry point has to behave like an interrupt handler: it must leave every register it does
+/// not mean to change as it found it, and return with iretq.
+///
+/// # Panics
+///
+/// If the vector already has a handler, see register.
+pub unsafe fn register_raw(vector: u8, entry_point: VirtAddr, privilege_level: PrivilegeLevel) {
+    unsafe {
+        register_with(vector, entry_point, |entry| {
+            entry.set_privilege_level(privilege_level);
+        });
+    }
+}
+
+/// Registers the handler and lets configure_entry change its entry's options
+///
+/// # Safety
+///
+/// See Idt::register
+unsafe fn register_with(vector: u8, handler: VirtAddr, configure_entry: impl FnOnce(&mut EntryOptions)) {
+    let registered = without_interrupts(|| {
+        let mut idt = IDT.lock();
+        return unsafe { idt.register(vector, handler) }.map(configure_entry).is_some();
+    });
+    // panic only after we released the lock, and interrupts are back on
+    if !registered {
+        panic!("vector {} already has a handler", vector);
+    }
+}
+
+/// Wrapper to print an interrupt stack frame as a register dump, one register per line, e.g.
+/// "rip    0x0000000000203c5e"
+pub struct RegisterDump<'a>(pub &'a InterruptStackFrameValue);
+
+impl fmt::Display for RegisterDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let registers = [
+            ("rip", self.0.instruction_pointer.as_u64()),
+            ("cs", self.0.code_segment),
+            ("rflags", self.0.cpu_flags),
+            ("rsp", self.0.stack_pointer.as_u64()),
+            ("ss", self.0.stack_segment),
+        ];
+        for (name, value) in registers {
+            writeln!(f, "{:<6} {:#018x}", name, value)?;
+        }
+        return Ok(());
+    }
+}
+
+/// Instruction pointer of the last breakpoint we hit, i.e. the address right after the int3
+static LAST_BREAKPOINT: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the instruction pointer of the last breakpoint we hit, or 0 if there was none yet
+pub fn last_breakpoint() -> u64 {
+    return LAST_BREAKPOINT.load(Ordering::Relaxed);
+}
+
+/// How many bytes from the top of the stack the breakpoint handler dumps
+const BREAKPOINT_STACK_BYTES: u64 = 64;
+
+/// Breakpoints are what debuggers use to stop a program (by replacing an instruction with int3).
+/// We do not have a debugger, but we can at least dump the registers the CPU saved for us, and
+/// then continue right after the int3. SERIAL1 also gets the top of the stack, see debug::brk!.
 extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
+    count(BREAKPOINT_VECTOR);
+    LAST_BREAKPOINT.store(stack_frame.instruction_pointer.as_u64(), Ordering::Relaxed);
+    let registers = RegisterDump(&stack_frame);
+    serial_println!("EXCEPTION: BREAKPOINT\n{}", registers);
+    println!("EXCEPTION: BREAKPOINT\n{}", registers);
+
+    // like InstructionBytes, we stop at the end of the stack pointer's page, since the stack might
+    // end there
+    let rsp = stack_frame.stack_pointer;
+    let page_end = (rsp + 1u64).align_up(4096u64);
+    let len = BREAKPOINT_STACK_BYTES.min(page_end - rsp);
+    serial_println!("stack:");
+    // the interrupted code was just using its stack, so at least its page is mapped
+    unsafe { crate::debug::hexdump(rsp.as_u64() as usize, len as usize) };
+}
+
+/// The first line the double fault handler writes to SERIAL1, so that whatever collects the serial
+/// output (e.g. CI) can tell that a double fault happened, even if the kernel dies right after
+pub const DOUBLE_FAULT_MARKER: &str = "!!! DOUBLE FAULT !!!";
+
+/// The magic number in a DoubleFaultRecord that the double fault handler actually wrote, "DBLFAULT"
+/// in ASCII
+pub const DOUBLE_FAULT_MAGIC: u64 = u64::from_le_bytes(*b"DBLFAULT");
+
+/// What the double fault handler knows about the double fault, i.e. the stack frame the CPU pushed
+/// and the error code (which is always 0 for a double fault)
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[repr(C)]
+pub struct DoubleFaultRecord {
+    pub magic: u64,
+    pub instruction_pointer: u64,
+    pub code_segment: u64,
+    pub cpu_flags: u64,
+    pub stack_pointer: u64,
+    pub stack_segment: u64,
+    pub error_code: u64,
 }
 
-extern "x86-interrupt" fn double_fault_handler(stack_frame: InterruptStackFrame, _error_coded: u64) -> ! {
+/// Where the double fault handler leaves its DoubleFaultRecord. It is part of the kernel's image,
+/// so it is always mapped, and since it is not mangled, the symbol table says where it is, e.g. for
+/// looking at it with QEMU's monitor or a debugger after the kernel died. A reset keeps the
+/// contents of memory, so it even survives a triple fault, until something overwrites it.
+#[no_mangle]
+static mut DOUBLE_FAULT_RECORD: DoubleFaultRecord = DoubleFaultRecord {
+    magic: 0,
+    instruction_pointer: 0,
+    code_segment: 0,
+    cpu_flags: 0,
+    stack_pointer: 0,
+    stack_segment: 0,
+    error_code: 0,
+};
+
+/// Returns the record of the last double fault, or None if there has not been one
+pub fn double_fault_record() -> Option<DoubleFaultRecord> {
+    // the double fault handler only ever writes this with a single volatile write, so reading it
+    // does not race with anything but another double fault
+    let record = unsafe { core::ptr::addr_of!(DOUBLE_FAULT_RECORD).read_volatile() };
+    if record.magic != DOUBLE_FAULT_MAGIC {
+        return None;
+    }
+    return Some(record);
+}
+
+/// Turns the value into 16 hex digits, without going through fmt, see write_double_fault_report
+fn hex_digits(value: u64) -> [u8; 16] {
+    let mut digits = [0; 16];
+    for (i, digit) in digits.iter_mut().enumerate() {
+        let nibble = (value >> (60 - 4 * i)) & 0xf;
+        *digit = b"0123456789abcdef"[nibble as usize];
+    }
+    return digits;
+}
+
+/// Writes the DOUBLE_FAULT_MARKER line followed by one line per field of the record, e.g.
+/// "rip=0x0000000000201234", using write for every piece. This only uses the stack, and a bit of
+/// it at that, since we might be in the double fault handler because the stack ran out.
+fn write_double_fault_report(record: &DoubleFaultRecord, write: &mut impl FnMut(&[u8])) {
+    write(DOUBLE_FAULT_MARKER.as_bytes());
+    write(b"\n");
+    let fields = [
+        ("rip", record.instruction_pointer),
+        ("cs", record.code_segment),
+        ("rflags", record.cpu_flags),
+        ("rsp", record.stack_pointer),
+        ("ss", record.stack_segment),
+        ("error code", record.error_code),
+    ];
+    for (name, value) in fields {
+        write(name.as_bytes());
+        write(b"=0x");
+        write(&hex_digits(value));
+        write(b"\n");
+    }
+}
+
+/// A double fault means that the CPU faulted while it tried to call the handler of another
+/// exception, e.g. because there is none, or because the stack overflowed. If this handler faults
+/// as well, the CPU gives up and resets (a triple fault), which leaves no trace at all.
+/// So before anything that could fault again, we write down what we know: into the
+/// DOUBLE_FAULT_RECORD, and straight to SERIAL1's ports, without locks or formatting (see
+/// serial::emergency_write). Only after that do we panic, which prints more, but which needs a lot
+/// more to go right.
+/// A stack overflow always ends up here rather than in the page fault handler: the page fault is
+/// caused by pushing onto the stack, and the CPU needs that same stack to push the page fault's
+/// stack frame. So if the stack pointer is at the stack's guard page, we say that it overflowed.
+extern "x86-interrupt" fn double_fault_handler(stack_frame: InterruptStackFrame, error_code: u64) -> ! {
+    count(DOUBLE_FAULT_VECTOR);
+    let record = DoubleFaultRecord {
+        magic: DOUBLE_FAULT_MAGIC,
+        instruction_pointer: stack_frame.instruction_pointer.as_u64(),
+        code_segment: stack_frame.code_segment,
+        cpu_flags: stack_frame.cpu_flags,
+        stack_pointer: stack_frame.stack_pointer.as_u64(),
+        stack_segment: stack_frame.stack_segment,
+        error_code,
+    };
+    // nothing else writes to the record, and we are not going to return
+    unsafe { core::ptr::addr_of_mut!(DOUBLE_FAULT_RECORD).write_volatile(record) };
+    write_double_fault_report(&record, &mut crate::serial::emergency_write);
+    // the push that faulted went to the 8 bytes below the stack pointer
+    if let Some(guard_page) = GuardPage::containing(stack_frame.stack_pointer - 8u64) {
+        panic!("EXCEPTION: DOUBLE FAULT ({})\n{:#?}", guard_page, stack_frame);
+    }
     panic!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
 }
 
+/// Number of bytes InstructionBytes prints
+const INSTRUCTION_BYTES: u64 = 8;
+
+/// Wrapper to print the first few bytes of code at an address as hex, e.g. "0f 0b 48 89 c7"
+/// It stops at the end of the address's page, since the next page might not be mapped, and a page
+/// fault while handling another exception is the last thing we want.
+pub struct InstructionBytes(pub VirtAddr);
+
+impl fmt::Display for InstructionBytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let start = self.0.as_u64();
+        let page_end = self.0.align_up(4096u64).as_u64();
+        // an address at the start of a page is already aligned
+        let page_end = if page_end == start { start + 4096 } else { page_end };
+        let end = core::cmp::min(start + INSTRUCTION_BYTES, page_end);
+        for address in start..end {
+            // the CPU just executed (or tried to execute) code here, so the page is mapped
+            let byte = unsafe { (address as *const u8).read_volatile() };
+            if address != start {
+                write!(f, " ")?;
+            }
+            write!(f, "{:02x}", byte)?;
+        }
+        return Ok(());
+    }
+}
+
+/// The first line of the divide error handler's report, e.g.
+/// "EXCEPTION: DIVIDE ERROR (division by zero or quotient overflow) at 0x203c5e: 48 f7 f1 ..."
+pub struct DivideError(pub VirtAddr);
+
+impl fmt::Display for DivideError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(
+            f,
+            "EXCEPTION: DIVIDE ERROR (division by zero or quotient overflow) at {:#x}: {}",
+            self.0.as_u64(),
+            InstructionBytes(self.0)
+        );
+    }
+}
+
+/// Wrapper to print the error code of a general protection fault in a human readable way.
+/// If the fault was caused by loading a segment, the error code is the selector of that segment,
+/// i.e. the index of the descriptor and the table it lives in. Otherwise, it is 0.
+pub struct SelectorErrorCode(pub u64);
+
+impl fmt::Display for SelectorErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 == 0 {
+            return write!(f, "not caused by a segment selector");
+        }
+        // bit 0 is set if the fault happened while delivering an external interrupt, and bits 1
+        // and 2 say which table the index belongs to
+        let table = match (self.0 >> 1) & 0b11 {
+            0b00 => "GDT",
+            0b10 => "LDT",
+            _ => "IDT",
+        };
+        let external = if self.0 & 1 != 0 { " (external)" } else { "" };
+        return write!(
+            f,
+            "selector {:#x}: index {} in the {}{}",
+            self.0,
+            self.0 >> 3,
+            table,
+            external
+        );
+    }
+}
+
+/// Wrapper to print a page fault's error code in a human readable way, e.g. "write to a
+/// non-present page in kernel mode"
+pub struct PageFaultCause(pub PageFaultErrorCode);
+
+impl fmt::Display for PageFaultCause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = if self.0.contains(PageFaultErrorCode::INSTRUCTION_FETCH) {
+            "instruction fetch from"
+        } else if self.0.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
+            "write to"
+        } else {
+            "read from"
+        };
+        // if this bit is not set, the page was not present; otherwise it was present, but the
+        // access was not allowed by the page's flags
+        let page = if self.0.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+            "a protected"
+        } else {
+            "a non-present"
+        };
+        let mode = if self.0.contains(PageFaultErrorCode::USER_MODE) {
+            "user"
+        } else {
+            "kernel"
+        };
+        return write!(f, "{} {} page in {} mode", access, page, mode);
+    }
+}
+
+/// The guard page that the last page fault hit, see last_guard_page: 0 for none, otherwise the
+/// GuardPage's discriminant plus 1
+static LAST_GUARD_PAGE: AtomicU8 = AtomicU8::new(0);
+/// Every GuardPage, in the order of their discriminants
+const GUARD_PAGES: [GuardPage; 3] = [GuardPage::Stack, GuardPage::BelowHeap, GuardPage::AboveHeap];
+
+/// Returns the guard page that the last page fault hit, or None if it did not hit one (or there
+/// was no page fault yet). The page fault handler sets this before it halts, so that a test that
+/// hits a guard page on purpose can check what the handler made of it, see set_fault_exit.
+pub fn last_guard_page() -> Option<GuardPage> {
+    let index = usize::from(LAST_GUARD_PAGE.load(Ordering::Relaxed));
+    return index.checked_sub(1).map(|index| GUARD_PAGES[index]);
+}
+
+/// Page faults happen when accessing memory that is not mapped, or not mapped with the flags the
+/// access needs. The CPU stores the address we tried to access in the Cr2 register, and tells us
+/// what kind of access it was through the error code.
+/// We cannot fix the fault (yet), so we report it and halt, see halt_after_fault. Note that the
+/// handler must not touch any memory that is not mapped itself, so nothing here may allocate.
+/// If the address is in one of the guard pages next to the heap and the stack, we say what that
+/// most likely means, e.g. "heap underflow", which is a lot more helpful than just the address.
+extern "x86-interrupt" fn page_fault_handler(stack_frame: InterruptStackFrame, error_code: PageFaultErrorCode) {
+    count(PAGE_FAULT_VECTOR);
+    use x86_64::registers::control::Cr2;
+
+    let address = Cr2::read();
+    let guard_page = GuardPage::containing(address);
+    LAST_GUARD_PAGE.store(
+        guard_page.map_or(0, |guard_page| guard_page as u8 + 1),
+        Ordering::Relaxed,
+    );
+    if let Some(guard_page) = guard_page {
+        serial_println!("EXCEPTION: PAGE FAULT in a guard page: {}", guard_page);
+        println!("EXCEPTION: PAGE FAULT in a guard page: {}", guard_page);
+    }
+    let cause = PageFaultCause(error_code);
+    serial_println!("EXCEPTION: PAGE FAULT\n{} at {:?}\n{:#?}", cause, address, stack_frame);
+    println!("EXCEPTION: PAGE FAULT\n{} at {:?}\n{:#?}", cause, address, stack_frame);
+    halt_after_fault();
+}
+
+/// Instruction pointer of the division the last divide error happened at, or 0 for none yet
+static LAST_DIVIDE_ERROR: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the instruction pointer of the division the last divide error happened at, or None if
+/// there was none yet. The divide error handler sets this before it halts, so that a test can check
+/// what the handler reported, see set_fault_exit.
+pub fn last_divide_error() -> Option<VirtAddr> {
+    let rip = LAST_DIVIDE_ERROR.load(Ordering::Relaxed);
+    return (rip != 0).then(|| VirtAddr::new(rip));
+}
+
+/// The CPU raises this when div or idiv divides by zero, or when the quotient does not fit into the
+/// destination register (e.g. i64::MIN / -1); it does not tell us which of the two it was.
+/// Returning would only run the division again, so we report where it happened, with the bytes of
+/// the instruction so that the address can be looked up in the kernel's disassembly, and halt, see
+/// halt_after_fault.
+/// Note that Rust's own / and % check for both and panic instead, so this only happens in assembly.
+extern "x86-interrupt" fn divide_error_handler(stack_frame: InterruptStackFrame) {
+    count(DIVIDE_ERROR_VECTOR);
+    LAST_DIVIDE_ERROR.store(stack_frame.instruction_pointer.as_u64(), Ordering::Relaxed);
+    let report = DivideError(stack_frame.instruction_pointer);
+    serial_println!("{}\n{:#?}", report, stack_frame);
+    println!("{}\n{:#?}", report, stack_frame);
+    halt_after_fault();
+}
+
+/// The CPU raises this for instructions it does not know, including ud2, which exists to raise it
+/// on purpose. We report where it happened, and the bytes of the instruction, so that the address
+/// can be looked up in the kernel's disassembly, and then halt, see halt_after_fault.
+extern "x86-interrupt" fn invalid_opcode_handler(stack_frame: InterruptStackFrame) {
+    count(INVALID_OPCODE_VECTOR);
+    let rip = stack_frame.instruction_pointer;
+    let bytes = InstructionBytes(rip);
+    serial_println!(
+        "EXCEPTION: INVALID OPCODE at {:#x}: {}\n{:#?}",
+        rip.as_u64(),
+        bytes,
+        stack_frame
+    );
+    println!(
+        "EXCEPTION: INVALID OPCODE at {:#x}: {}\n{:#?}",
+        rip.as_u64(),
+        bytes,
+        stack_frame
+    );
+    halt_after_fault();
+}
+
+/// General protection faults are the CPU's catch-all for privilege and segmentation violations,
+/// e.g. loading a segment register with a selector that points outside of the GDT, or running a
+/// privileged instruction in user mode. Like the page fault handler, we can only report the fault
+/// and halt.
+/// This runs on its own stack from the IST, since the fault might have been caused by a broken
+/// stack. For the same reason, nothing here may allocate.
+extern "x86-interrupt" fn general_protection_fault_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    count(GENERAL_PROTECTION_FAULT_VECTOR);
+    let cause = SelectorErrorCode(error_code);
+    serial_println!("EXCEPTION: GENERAL PROTECTION FAULT\n{}\n{:#?}", cause, stack_frame);
+    println!("EXCEPTION: GENERAL PROTECTION FAULT\n{}\n{:#?}", cause, stack_frame);
+    halt_after_fault();
+}
+
 #[test_case]
 fn test_breakpoint_exception() {
     x86_64::instructions::interrupts::int3();
+    assert_ne!(last_breakpoint(), 0);
+}
+
+// The register dump has every register in its own line, with its value as 16 hex digits
+#[test_case]
+fn test_register_dump() {
+    use alloc::format;
+    let frame = InterruptStackFrameValue {
+        instruction_pointer: VirtAddr::new(0x203c5e),
+        code_segment: 0x8,
+        cpu_flags: 0x246,
+        stack_pointer: VirtAddr::new(0x10000201f50),
+        stack_segment: 0,
+    };
+    let dump = format!("{}", RegisterDump(&frame));
+    assert!(dump.contains("rip    0x0000000000203c5e\n"));
+    assert!(dump.contains("rflags 0x0000000000000246\n"));
+    assert_eq!(dump.lines().count(), 5);
+}
+
+// The report says what happened, and where
+#[test_case]
+fn test_divide_error_message() {
+    use alloc::format;
+    // any address in our own code works, the report only reads the bytes there
+    let rip = VirtAddr::new(count as fn(u8) as usize as u64);
+    let message = format!("{}", DivideError(rip));
+    let expected = format!(
+        "EXCEPTION: DIVIDE ERROR (division by zero or quotient overflow) at {:#x}: ",
+        rip.as_u64()
+    );
+    assert!(message.starts_with(&expected), "unexpected message {:?}", message);
+    // followed by the instruction's bytes, at least one of them
+    assert!(message.len() > expected.len());
+}
+
+/// Vector of the software interrupt test_register uses
+#[cfg(test)]
+const TEST_VECTOR: u8 = 200;
+
+#[cfg(test)]
+static TEST_VECTOR_HITS: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(test)]
+extern "x86-interrupt" fn test_vector_handler(_stack_frame: InterruptStackFrame) {
+    TEST_VECTOR_HITS.fetch_add(1, Ordering::Relaxed);
+}
+
+// Registers a handler after the IDT has been loaded, and triggers it with a software interrupt
+#[test_case]
+fn test_register() {
+    register(TEST_VECTOR, test_vector_handler);
+    unsafe {
+        core::arch::asm!("int {}", const TEST_VECTOR, options(nomem, nostack));
+    }
+    assert_eq!(TEST_VECTOR_HITS.load(Ordering::Relaxed), 1);
+}
+
+#[test_case]
+const TEST_REGISTER_TWICE: crate::test_runner::ShouldPanic = crate::test_runner::ShouldPanic {
+    name: concat!(module_path!(), "::test_register_twice"),
+    test: test_register_twice,
+};
+
+// The timer's vector is taken by time::init
+#[cfg(test)]
+fn test_register_twice() {
+    register(crate::pic::InterruptIndex::Timer.as_u8(), test_vector_handler);
+}
+
+/// Vector of the software interrupt test_counts uses
+#[cfg(test)]
+const COUNTED_TEST_VECTOR: u8 = 201;
+
+#[cfg(test)]
+extern "x86-interrupt" fn counted_test_vector_handler(_stack_frame: InterruptStackFrame) {
+    count(COUNTED_TEST_VECTOR);
+}
+
+// Every int on a vector counts exactly once
+#[test_case]
+fn test_counts() {
+    register(COUNTED_TEST_VECTOR, counted_test_vector_handler);
+    for _ in 0..3 {
+        unsafe {
+            core::arch::asm!("int {}", const COUNTED_TEST_VECTOR, options(nomem, nostack));
+        }
+    }
+    let (_, count) = counts().find(|(vector, _)| *vector == COUNTED_TEST_VECTOR).unwrap();
+    assert_eq!(count, 3);
+}
+
+// Interrupts are disabled for as long as the guard lives, and afterwards they are back to what they
+// were before, also when guards are nested
+#[test_case]
+fn test_interrupt_guard() {
+    use x86_64::instructions::interrupts::are_enabled;
+
+    let before = are_enabled();
+    {
+        let _outer = InterruptGuard::new();
+        assert!(!are_enabled());
+        {
+            let _inner = InterruptGuard::new();
+            assert!(!are_enabled());
+        }
+        assert!(!are_enabled());
+    }
+    assert_eq!(are_enabled(), before);
+}
+
+// The report starts with the marker, and has every field in hex
+#[test_case]
+fn test_double_fault_report() {
+    use alloc::vec::Vec;
+
+    let record = DoubleFaultRecord {
+        magic: DOUBLE_FAULT_MAGIC,
+        instruction_pointer: 0x20_1234,
+        code_segment: 8,
+        cpu_flags: 0x202,
+        stack_pointer: 0xdead_beef,
+        stack_segment: 0,
+        error_code: 0,
+    };
+    let mut report = Vec::new();
+    write_double_fault_report(&record, &mut |bytes| report.extend_from_slice(bytes));
+    let report = core::str::from_utf8(&report).unwrap();
+    assert!(report.starts_with("!!! DOUBLE FAULT !!!\n"));
+    assert!(report.contains("\nrip=0x0000000000201234\n"));
+    assert!(report.contains("\nrsp=0x00000000deadbeef\n"));
+    assert!(report.ends_with("\nerror code=0x0000000000000000\n"));
+    assert_eq!(double_fault_record(), None);
 }
@@ -0,0 +1,174 @@
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::task::{Context, Poll};
+use crossbeam_queue::ArrayQueue;
+use futures_util::stream::{Stream, StreamExt};
+use futures_util::task::AtomicWaker;
+use lazy_static::lazy_static;
+use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
+use spin::{Mutex, Once};
+use x86_64::instructions::port::Port;
+use x86_64::structures::idt::InterruptStackFrame;
+
+use crate::interrupts;
+use crate::pic::{self, InterruptIndex};
+
+/// The PS/2 controller's data port, which holds the scancode of the last key press or release
+const DATA_PORT: u16 = 0x60;
+
+/// Capacity of the SCANCODE_QUEUE. A key press is one to three scancodes, and so is its release.
+const SCANCODE_QUEUE_SIZE: usize = 128;
+
+// Scancodes that the keyboard interrupt handler received, but nobody has read yet. The interrupt
+// handler must not allocate (the allocator's lock might be held by whatever it interrupted), so
+// the queue is a fixed size, lock free queue, which is created by the first reader; until then,
+// the handler drops every scancode. Like the serial::SerialBuffer, a full queue drops new
+// scancodes instead of overwriting the oldest ones.
+static SCANCODE_QUEUE: Once<ArrayQueue<u8>> = Once::new();
+
+/// Woken by the keyboard interrupt handler whenever it added a scancode, see ScancodeStream
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Number of scancodes dropped because the SCANCODE_QUEUE was full, and whether we have warned
+/// about that yet. The interrupt handler does not log the warning itself, since logging to the
+/// serial port takes long enough for the next few scancodes to get lost; the reader does it instead.
+static DROPPED_SCANCODES: AtomicU64 = AtomicU64::new(0);
+static WARNED_ABOUT_DROPS: AtomicBool = AtomicBool::new(false);
+
+/// Returns the SCANCODE_QUEUE, creating it if needed. Needs the heap.
+fn scancode_queue() -> &'static ArrayQueue<u8> {
+    return SCANCODE_QUEUE.call_once(|| ArrayQueue::new(SCANCODE_QUEUE_SIZE));
+}
+
+/// Puts the scancode into the SCANCODE_QUEUE, and wakes up whoever waits for it
+fn push_scancode(scancode: u8) {
+    let Some(queue) = SCANCODE_QUEUE.r#try() else {
+        return;
+    };
+    if queue.push(scancode).is_err() {
+        DROPPED_SCANCODES.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    WAKER.wake();
+}
+
+/// The scancodes of the keyboard, as an async stream that never ends: .next().await waits for the
+/// next scancode.
+/// Every scancode goes to only one reader, so there should only be one ScancodeStream at a time.
+pub struct ScancodeStream {
+    // the stream can only be created through new, which creates the queue
+    _private: (),
+}
+
+impl ScancodeStream {
+    pub fn new() -> Self {
+        scancode_queue();
+        return ScancodeStream { _private: () };
+    }
+}
+
+impl Default for ScancodeStream {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl Stream for ScancodeStream {
+    type Item = u8;
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<u8>> {
+        let dropped = DROPPED_SCANCODES.load(Ordering::Relaxed);
+        if dropped > 0 && !WARNED_ABOUT_DROPS.swap(true, Ordering::Relaxed) {
+            crate::log::warn!("keyboard: scancode queue full, dropped {} scancodes", dropped);
+        }
+
+        let queue = scancode_queue();
+        // the common case: there already is a scancode, so we do not need the waker
+        if let Some(scancode) = queue.pop() {
+            return Poll::Ready(Some(scancode));
+        }
+        // The interrupt handler might push a scancode right between the pop above and registering
+        // the waker, in which case it has nobody to wake up. So we check again afterwards.
+        WAKER.register(context.waker());
+        return match queue.pop() {
+            Some(scancode) => {
+                WAKER.take();
+                Poll::Ready(Some(scancode))
+            },
+            None => Poll::Pending,
+        };
+    }
+}
+
+// The keyboard does not send us characters, but scancodes, i.e. one or more bytes per key press and
+// release. The Keyboard type turns those into characters, and for that it needs to remember state
+// across scancodes, like whether shift is currently held down. Only whoever reads the scancodes
+// locks this, never an interrupt handler.
+// The PS/2 controller translates whatever the keyboard sends into scancode set 1 by default.
+lazy_static! {
+    static ref KEYBOARD: Mutex<Keyboard<layouts::Us104Key, ScancodeSet1>> = Mutex::new(Keyboard::new(
+        ScancodeSet1::new(),
+        layouts::Us104Key,
+        HandleControl::Ignore
+    ));
+}
+
+/// Registers the keyboard interrupt handler
+pub fn init() {
+    interrupts::register(InterruptIndex::Keyboard.as_u8(), keyboard_interrupt_handler);
+}
+
+/// The keyboard controller does not send another interrupt until we read the scancode of the key
+/// press from its data port, so we always read it, even if the SCANCODE_QUEUE is full.
+/// Turning scancodes into characters takes a lock and might take a while, so we leave that to
+/// whoever reads the SCANCODE_QUEUE.
+extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    interrupts::count(InterruptIndex::Keyboard.as_u8());
+    let mut port = Port::<u8>::new(DATA_PORT);
+    let scancode = unsafe { port.read() };
+    push_scancode(scancode);
+    pic::notify_end_of_interrupt(InterruptIndex::Keyboard);
+}
+
+/// Feeds the scancode to the KEYBOARD, and returns the character typed if this completes a key
+/// press that produces one. Keys without a character (like the arrow keys) are ignored for now.
+pub fn decode(scancode: u8) -> Option<char> {
+    let mut keyboard = KEYBOARD.lock();
+    if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
+        if let Some(DecodedKey::Unicode(c)) = keyboard.process_keyevent(key_event) {
+            return Some(c);
+        }
+    }
+    return None;
+}
+
+/// Echoes everything typed on the keyboard to the screen. Never finishes, so spawn it as a Task.
+pub async fn print_keypresses() {
+    let mut scancodes = ScancodeStream::new();
+    while let Some(scancode) = scancodes.next().await {
+        if let Some(c) = decode(scancode) {
+            crate::print!("{}", c);
+        }
+    }
+}
+
+// Scancodes come out of the stream in the order they went in, and once the queue is empty, the
+// stream waits instead of ending
+#[test_case]
+fn test_scancode_stream() {
+    use core::task::Waker;
+
+    let mut stream = ScancodeStream::new();
+    let mut context = Context::from_waker(Waker::noop());
+    let scancodes = [0x1e, 0x9e, 0x30, 0xb0];
+    for scancode in scancodes {
+        push_scancode(scancode);
+    }
+    for scancode in scancodes {
+        assert_eq!(
+            Pin::new(&mut stream).poll_next(&mut context),
+            Poll::Ready(Some(scancode))
+        );
+    }
+    assert_eq!(Pin::new(&mut stream).poll_next(&mut context), Poll::Pending);
+}
@@ -0,0 +1,264 @@
+use core::fmt::{self, Write};
+use spin::Mutex;
+
+use crate::interrupts::InterruptGuard;
+use crate::log::Level;
+
+/// How many bytes of log messages the KLOG keeps
+pub const KLOG_SIZE: usize = 8192;
+
+/// A ring buffer of bytes: once it is full, every new byte overwrites the oldest one
+pub struct Ring {
+    bytes: [u8; KLOG_SIZE],
+    /// Index of the oldest byte
+    start: usize,
+    len: usize,
+    /// How many bytes were ever pushed, so that a reader that copies the bytes out bit by bit can
+    /// tell where it left off, and whether that has been overwritten since, see oldest
+    pushed: u64,
+}
+
+impl Ring {
+    pub const fn new() -> Self {
+        return Ring {
+            bytes: [0; KLOG_SIZE],
+            start: 0,
+            len: 0,
+            pushed: 0,
+        };
+    }
+
+    pub fn push(&mut self, byte: u8) {
+        let end = (self.start + self.len) % KLOG_SIZE;
+        self.bytes[end] = byte;
+        if self.len == KLOG_SIZE {
+            self.start = (self.start + 1) % KLOG_SIZE;
+        } else {
+            self.len += 1;
+        }
+        self.pushed += 1;
+    }
+
+    /// Whether bytes have been overwritten, i.e. whether the oldest byte is probably in the middle
+    /// of a message
+    fn wrapped(&self) -> bool {
+        // the ring is only ever full once it has wrapped, or is just about to
+        return self.len == KLOG_SIZE;
+    }
+
+    /// The position of the oldest byte, counting every byte that was ever pushed
+    fn oldest(&self) -> u64 {
+        return self.pushed - self.len as u64;
+    }
+
+    /// Copies the bytes from position on (see oldest) into out, as many as fit, and returns how
+    /// many that were. Bytes older than oldest are gone, so position must not be older.
+    fn copy_from(&self, position: u64, out: &mut [u8]) -> usize {
+        let skipped = (position - self.oldest()) as usize;
+        let count = (self.len - skipped).min(out.len());
+        for (i, byte) in out[..count].iter_mut().enumerate() {
+            *byte = self.bytes[(self.start + skipped + i) % KLOG_SIZE];
+        }
+        return count;
+    }
+}
+
+impl Default for Ring {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl fmt::Write for Ring {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.push(byte);
+        }
+        return Ok(());
+    }
+}
+
+/// The kernel log: everything that went through the log macros, so that it can be looked at later
+/// (like dmesg on Linux), even after it scrolled off the screen, or while nobody was listening on
+/// the serial port. It only keeps the last KLOG_SIZE bytes, though.
+/// Every message is written in one go under the lock, so messages never end up interleaved, even if
+/// an interrupt handler logs while the code it interrupted is logging, too. Interrupts are disabled
+/// while it is locked, like for the WRITER, so that such a handler cannot find it locked.
+static KLOG: Mutex<Ring> = Mutex::new(Ring::new());
+
+/// Appends a log message to the KLOG, in the same format as on the screen, see log::_log
+#[doc(hidden)]
+pub fn append(level: Level, ticks: u64, args: fmt::Arguments) {
+    let _interrupts = InterruptGuard::new();
+    // writing to the ring does not fail
+    let _ = writeln!(KLOG.lock(), "[{}] [{:>8}] {}", level.tag(), ticks, args);
+}
+
+/// How many bytes write_to copies out of the KLOG at a time
+const CHUNK_SIZE: usize = 256;
+
+/// Writes the KLOG to out, oldest message first. If old messages have been overwritten, the first
+/// one is probably cut off, so it is skipped.
+/// The KLOG is copied out a chunk at a time, and only locked (with interrupts disabled) while we
+/// copy a chunk, not while we write it to out, which might take a while, e.g. on SERIAL1. That also
+/// keeps the copy small enough for the stack we might be panicking on. Messages that are logged in
+/// the meantime are not written, and if the ones we have not written yet are overwritten, we go on
+/// with the oldest one left. If the KLOG is locked already, e.g. because we panicked while logging,
+/// this only says so, instead of waiting forever.
+pub fn write_to(out: &mut impl fmt::Write) -> fmt::Result {
+    return write_ring_to(&KLOG, out);
+}
+
+/// write_to for any ring, so that the tests do not need the KLOG
+fn write_ring_to(klog: &Mutex<Ring>, out: &mut impl fmt::Write) -> fmt::Result {
+    let locked = || {
+        let _interrupts = InterruptGuard::new();
+        return klog.try_lock().map(|ring| (ring.oldest(), ring.pushed, ring.wrapped()));
+    };
+    // everything up to end, so that messages logged while we write cannot keep us going forever
+    let Some((mut position, end, mut skipping)) = locked() else {
+        return out.write_str("(klog is locked)\n");
+    };
+    // a chunk, plus the start of a character that the previous chunk cut off, which is at most 3
+    // bytes long
+    let mut buffer = [0; CHUNK_SIZE + 3];
+    let mut carried = 0;
+    while position < end {
+        let copied = {
+            let _interrupts = InterruptGuard::new();
+            let Some(ring) = klog.try_lock() else {
+                return out.write_str("(klog is locked)\n");
+            };
+            if position < ring.oldest() {
+                // overwritten while we wrote the chunks before, so the oldest message left is
+                // probably cut off, too
+                position = ring.oldest();
+                skipping = true;
+                carried = 0;
+            }
+            let wanted = CHUNK_SIZE.min(end.saturating_sub(position) as usize);
+            ring.copy_from(position, &mut buffer[carried..carried + wanted])
+        };
+        position += copied as u64;
+        let filled = carried + copied;
+        let mut chunk = &buffer[..filled];
+        if skipping {
+            let Some(i) = chunk.iter().position(|&byte| byte == b'\n') else {
+                carried = 0;
+                continue;
+            };
+            chunk = &chunk[i + 1..];
+            skipping = false;
+        }
+        // only whole messages are left, which were strs, so anything that is not valid UTF-8 is a
+        // character that the next chunk finishes. The check is just so we never need unsafe: if
+        // it is not, we stop there.
+        let (text, rest) = valid_prefix(chunk);
+        out.write_str(text)?;
+        if rest.len() > 3 {
+            return Ok(());
+        }
+        carried = rest.len();
+        buffer.copy_within(filled - carried..filled, 0);
+    }
+    return Ok(());
+}
+
+/// Splits bytes into their longest prefix that is valid UTF-8, and the rest
+fn valid_prefix(bytes: &[u8]) -> (&str, &[u8]) {
+    let len = match core::str::from_utf8(bytes) {
+        Ok(_) => bytes.len(),
+        Err(error) => error.valid_up_to(),
+    };
+    // the prefix was just checked
+    return (core::str::from_utf8(&bytes[..len]).unwrap(), &bytes[len..]);
+}
+
+/// Replays the KLOG on the screen and on SERIAL1. Every chunk goes through tee_print!, i.e. the
+/// one Tee to both (see tee::with_tee), which is only locked for that chunk, so that the timer and
+/// the keyboard are not held off for the whole KLOG.
+pub fn dump() {
+    /// Hands every chunk write_to copies out to tee_print!
+    struct TeeChunks;
+
+    impl fmt::Write for TeeChunks {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            crate::tee_print!("{}", s);
+            return Ok(());
+        }
+    }
+
+    // printing does not fail
+    let _ = write_to(&mut TeeChunks);
+}
+
+// Logged messages come back out of the KLOG in the order they were logged, in the same format as
+// on the screen
+#[test_case]
+fn test_klog() {
+    use alloc::string::String;
+
+    for i in 0..3 {
+        crate::log::info!("test_klog line {}", i);
+    }
+    let mut text = String::new();
+    write_to(&mut text).unwrap();
+    let lines: alloc::vec::Vec<&str> = text.lines().rev().take(3).collect();
+    for (i, line) in lines.iter().rev().enumerate() {
+        assert!(line.starts_with("[INFO ] ["), "unexpected line {:?}", line);
+        assert!(
+            line.ends_with(&alloc::format!("] test_klog line {}", i)),
+            "unexpected line {:?}",
+            line
+        );
+    }
+}
+
+// A full ring drops the oldest bytes first
+#[test_case]
+fn test_ring_wraps() {
+    let mut ring = Ring::new();
+    let mut bytes = [0; KLOG_SIZE];
+    for byte in b"abc" {
+        ring.push(*byte);
+    }
+    assert_eq!(ring.copy_from(ring.oldest(), &mut bytes), 3);
+    assert_eq!(&bytes[..3], b"abc");
+    assert!(!ring.wrapped());
+
+    for i in 0..KLOG_SIZE {
+        ring.push(b'0' + (i % 10) as u8);
+    }
+    assert!(ring.wrapped());
+    assert_eq!(ring.oldest(), 3);
+    assert_eq!(ring.copy_from(ring.oldest(), &mut bytes), KLOG_SIZE);
+    assert_eq!(bytes[0], b'0');
+    assert_eq!(bytes[KLOG_SIZE - 1], b'0' + ((KLOG_SIZE - 1) % 10) as u8);
+    assert_eq!(ring.copy_from(ring.oldest() + 10, &mut bytes[..4]), 4);
+    assert_eq!(&bytes[..4], b"0123");
+}
+
+// Characters that are cut in two between chunks, or where the ring wraps around, come out in one
+// piece, and a cut off first message is skipped
+#[test_case]
+fn test_write_ring_across_chunks() {
+    let klog = Mutex::new(Ring::new());
+    use alloc::string::String;
+
+    // three bytes at a time, so that some of the chunk boundaries cut an é in two
+    let text = "aé".repeat(KLOG_SIZE / 4);
+    for byte in text.bytes().chain("\ncut off\n".bytes()) {
+        klog.lock().push(byte);
+    }
+    let mut out = String::new();
+    write_ring_to(&klog, &mut out).unwrap();
+    assert_eq!(out, text + "\ncut off\n");
+
+    for byte in "ü\n".repeat(KLOG_SIZE).bytes() {
+        klog.lock().push(byte);
+    }
+    out.clear();
+    write_ring_to(&klog, &mut out).unwrap();
+    // KLOG_SIZE is not a multiple of 3, so the oldest ü is cut off
+    assert_eq!(out, "ü\n".repeat(KLOG_SIZE / 3));
+}
@@ -1,33 +1,161 @@
 #![no_std]
 #![cfg_attr(test, no_main)]
-#![feature(abi_x86_interrupt, custom_test_frameworks)]
+#![feature(abi_x86_interrupt, alloc_error_handler, custom_test_frameworks)]
 #![test_runner(crate::test_runner::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 
-#[cfg(test)]
+extern crate alloc;
+
+use bootloader::BootInfo;
+use core::fmt;
 use core::panic::PanicInfo;
+use fmt_buffer::FmtBuffer;
+use x86_64::VirtAddr;
 
+pub mod allocator;
+pub mod bench;
+pub mod cmdline;
+pub mod cpu;
+pub mod debug;
+pub mod fmt_buffer;
+pub mod framebuffer;
 pub mod gdt;
+pub mod gfx_console;
+pub mod global;
 pub mod interrupts;
+pub mod keyboard;
+pub mod klog;
+pub mod log;
+pub mod memory;
+pub mod pci;
+pub mod pic;
+pub mod power;
 pub mod qemu;
+pub mod rand;
+pub mod rtc;
 #[macro_use]
 pub mod serial;
+pub mod shell;
+pub mod spin_lock;
+pub mod syscall;
+pub mod task;
+pub mod tee;
 pub mod test_runner;
+pub mod time;
+pub mod tsc;
 pub mod vga_buffer;
 
+#[cfg(test)]
+bootloader::entry_point!(test_kernel_main);
+
 /// Entry point for `cargo test`
 #[cfg(test)]
-#[no_mangle]
-pub extern "C" fn _start() -> ! {
-    init();
+fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
+    init(boot_info);
     test_main();
-    loop {}
+    hlt_loop();
+}
+
+/// Initializes the screen and the serial ports, i.e. everything the print macros need.
+/// init does this first thing, so this is only needed on its own by tests that do not call init.
+pub fn init_output() {
+    vga_buffer::init();
+    serial::init();
 }
 
-/// Central function for anything that needs to initialised
-pub fn init() {
+/// Central function for anything that needs to initialised.
+/// The output comes first, so that everything after it can report problems, followed by the
+/// command line, which decides how much of that is reported where. The heap is set up last, since
+/// mapping it may cause page faults that we want to see reported.
+pub fn init(boot_info: &'static BootInfo) {
+    init_output();
+    cmdline::apply();
     gdt::init();
     interrupts::init_dt();
+    time::init();
+    tsc::init();
+    keyboard::init();
+    syscall::init();
+    if !serial::self_test() {
+        log::warn!("serial: SERIAL1 failed its self test, serial output probably goes nowhere");
+    }
+    serial::enable_rx_interrupts();
+    pic::init();
+    x86_64::instructions::interrupts::enable();
+
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    // the bootloader maps all of physical memory at this offset, and this is the only place we call
+    // these functions
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { memory::BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    log::info!("booted at {}", rtc::now());
+}
+
+/// Halts the CPU forever. Unlike an empty loop, which keeps the CPU busy (and with that, a core of
+/// the machine QEMU runs on), hlt sleeps until the next interrupt arrives. Interrupts are still
+/// handled, after which we simply go back to sleep.
+pub fn hlt_loop() -> ! {
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+/// How many bytes of a panic message are reported, see format_panic
+pub const PANIC_MESSAGE_SIZE: usize = 1024;
+
+/// Formats a panic message (or anything else) into a buffer on the stack, cutting it off after
+/// PANIC_MESSAGE_SIZE bytes. The panic handler must not allocate, since we might have panicked
+/// because the heap ran out, and must not take too long either. Formatting the message once into
+/// the buffer, and then writing the buffer wherever it needs to go, takes care of both.
+pub fn format_panic(info: &impl fmt::Display) -> FmtBuffer<PANIC_MESSAGE_SIZE> {
+    use core::fmt::Write;
+
+    let mut message = FmtBuffer::new();
+    // the buffer never fails, but the Display impls of the panic's arguments might
+    let _ = write!(message, "{}", info);
+    return message;
+}
+
+/// Reports a panic in white on red on the screen, so that it is hard to miss, and mirrors it to
+/// SERIAL1, so that it also ends up in the host's log.
+/// A panic can happen while somebody is holding the WRITER (e.g. a panic inside of println!), and
+/// that somebody is never going to release it again. So instead of spinning forever, we only try to
+/// lock the WRITER, and if that fails, the serial port is the only place the panic shows up. The
+/// same goes for SERIAL1, in which case the panic is lost for the host. Both are also skipped if
+/// they are not initialized yet, i.e. if we panic very early during init.
+/// The host also gets the registers, which are too much for the screen. They are dumped first
+/// thing, so that they are as close as possible to what they were in the panicking code; the panic
+/// machinery has run in between, though, so only rsp and rbp are really to be trusted. The same goes
+/// for the backtrace, which starts with the frames of the panic machinery. Last come the most
+/// recent log messages from the klog, which often tell what led up to the panic.
+pub fn report_panic(info: &PanicInfo) {
+    let mut serial = serial::SERIAL1.try_lock();
+    report_panic_to(info, serial.as_deref_mut().map(|serial| serial as &mut dyn fmt::Write));
+}
+
+/// Like report_panic, but the host's copy of the report goes to host instead of SERIAL1 (or
+/// nowhere, if it is None), e.g. so that a test can check what the host gets
+pub fn report_panic_to(info: &PanicInfo, host: Option<&mut dyn fmt::Write>) {
+    use core::fmt::Write;
+    use vga_buffer::{Color, WRITER};
+
+    let registers = debug::dump_registers();
+    let message = format_panic(info);
+
+    // nothing we could do if writing fails, we are already panicking
+    if let Some(mut writer) = WRITER.try_lock() {
+        writer.set_color(Color::White, Color::Red);
+        let _ = writeln!(writer, "{}", message);
+    }
+    if let Some(mut host) = host {
+        let _ = writeln!(host, "{}", message);
+        let _ = writeln!(host, "{}", registers);
+        let _ = debug::write_backtrace(&mut host);
+        let _ = writeln!(host, "recent log messages:");
+        let _ = klog::write_to(&mut host);
+    }
 }
 
 #[cfg(test)]
@@ -37,3 +165,20 @@ fn panic(info: &PanicInfo) -> ! {
 
     test_panic_handler(info)
 }
+
+// hlt_loop never returns, so it has to coerce to a function returning the never type. Note that we
+// cannot actually call it here, since that would stop the test run.
+#[test_case]
+fn test_hlt_loop_never_returns() {
+    let _: fn() -> ! = hlt_loop;
+}
+
+// A panic message that is way too long is cut off at PANIC_MESSAGE_SIZE bytes, and says so
+#[test_case]
+fn test_format_panic() {
+    let message = format_panic(&format_args!("a very long panic message: {}", fmt_buffer::Long));
+    assert!(message.truncated());
+    assert_eq!(message.as_str().len(), PANIC_MESSAGE_SIZE);
+    assert!(message.as_str().starts_with("a very long panic message: xxx"));
+    assert_eq!(format_panic(&"short").as_str(), "short");
+}
@@ -0,0 +1,189 @@
+use core::fmt;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::time;
+use crate::vga_buffer::{self, Color};
+
+/// How important a log message is. Lower levels are more important.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+#[repr(u8)]
+pub enum Level {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl Level {
+    /// Returns the level with the name, e.g. "debug", regardless of case, or None if there is no
+    /// such level
+    pub fn from_name(name: &str) -> Option<Level> {
+        return [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace]
+            .into_iter()
+            .find(|level| level.tag().trim_end().eq_ignore_ascii_case(name));
+    }
+
+    /// The tag the message is prefixed with
+    pub fn tag(self) -> &'static str {
+        return match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN ",
+            Level::Info => "INFO ",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        };
+    }
+
+    /// The color of the tag on the screen
+    fn color(self) -> Color {
+        return match self {
+            Level::Error => Color::LightRed,
+            Level::Warn => Color::Yellow,
+            Level::Info => Color::LightGreen,
+            Level::Debug => Color::LightCyan,
+            Level::Trace => Color::DarkGray,
+        };
+    }
+
+    /// The color of the tag on the host's terminal, as the number of an ANSI color
+    fn ansi_color(self) -> u8 {
+        return match self {
+            Level::Error => 31,
+            Level::Warn => 33,
+            Level::Info => 32,
+            Level::Debug => 36,
+            Level::Trace => 90,
+        };
+    }
+}
+
+/// Messages less important than this level are dropped
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+/// Sets the least important level that still gets logged
+pub fn set_max_level(level: Level) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Returns whether messages of this level are logged
+pub fn enabled(level: Level) -> bool {
+    return level as u8 <= MAX_LEVEL.load(Ordering::Relaxed);
+}
+
+/// Writes a log message to the screen, SERIAL1 and the klog, e.g. "[INFO ] [     123] booted",
+/// unless its level is filtered out. Returns whether the message was written.
+/// The tag is colored by level on both; on the screen through the WRITER's colors, and on the
+/// serial port through ANSI escape codes that the host's terminal understands. The number is the
+/// tick count when the message was logged.
+#[doc(hidden)]
+pub fn _log(level: Level, args: fmt::Arguments) -> bool {
+    return log_to(level, args, crate::serial::write_fmt);
+}
+
+/// _log, but with the serial port's copy of the message going to serial instead of SERIAL1, so that
+/// the tests can check what would have reached the host
+fn log_to(level: Level, args: fmt::Arguments, serial: impl FnOnce(fmt::Arguments)) -> bool {
+    use core::fmt::Write;
+
+    if !enabled(level) {
+        return false;
+    }
+    let ticks = time::ticks();
+    // the colors are swapped and restored under one lock, see vga_buffer::_print_colored
+    vga_buffer::with_writer(|writer| {
+        let (foreground, background) = writer.color();
+        writer.write_string("[");
+        writer.set_color(level.color(), background);
+        writer.write_string(level.tag());
+        writer.set_color(foreground, background);
+        writeln!(writer, "] [{:>8}] {}", ticks, args).unwrap();
+    });
+    serial(format_args!(
+        "[\x1b[{}m{}\x1b[0m] [{:>8}] {}\n",
+        level.ansi_color(),
+        level.tag(),
+        ticks,
+        args
+    ));
+    crate::klog::append(level, ticks, args);
+    return true;
+}
+
+/// Logs an error, i.e. something went wrong, see _log. Use it like println!.
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => ($crate::log::_log($crate::log::Level::Error, format_args!($($arg)*)));
+}
+
+/// Logs a warning, i.e. something looks wrong, but we can carry on, see _log
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => ($crate::log::_log($crate::log::Level::Warn, format_args!($($arg)*)));
+}
+
+/// Logs an info, i.e. something worth knowing about, see _log
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => ($crate::log::_log($crate::log::Level::Info, format_args!($($arg)*)));
+}
+
+/// Logs a debug message, i.e. something only worth knowing when looking for a bug, see _log
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => ($crate::log::_log($crate::log::Level::Debug, format_args!($($arg)*)));
+}
+
+/// Logs a trace message, i.e. every little detail, see _log
+#[macro_export]
+macro_rules! log_trace {
+    ($($arg:tt)*) => ($crate::log::_log($crate::log::Level::Trace, format_args!($($arg)*)));
+}
+
+// #[macro_export] puts the macros at the crate root, with names that do not clash with anything
+// there. These let us use them as log::error! and so on instead.
+pub use crate::{log_debug as debug, log_error as error, log_info as info, log_trace as trace, log_warn as warn};
+
+// A message below the max level is dropped, one at or above it is written. Note that this leaves
+// the max level at its default.
+#[test_case]
+fn test_max_level() {
+    use crate::fmt_buffer::FmtBuffer;
+    use core::fmt::Write;
+
+    assert!(!debug!("test_max_level: this must not show up"));
+    assert!(error!("test_max_level: this must show up"));
+
+    let mut serial = FmtBuffer::<256>::new();
+    let mut to_serial = |args: fmt::Arguments| serial.write_fmt(args).unwrap();
+    assert!(!log_to(
+        Level::Debug,
+        format_args!("test_max_level: dropped"),
+        &mut to_serial
+    ));
+    assert!(log_to(
+        Level::Error,
+        format_args!("test_max_level: written"),
+        &mut to_serial
+    ));
+    assert!(
+        !serial.as_str().contains("dropped"),
+        "unexpected output {:?}",
+        serial.as_str()
+    );
+    assert_eq!(serial.as_str().lines().count(), 1);
+    assert!(serial.as_str().ends_with("] test_max_level: written\n"));
+
+    set_max_level(Level::Debug);
+    assert!(debug!("test_max_level: this must show up, too"));
+    assert!(!trace!("test_max_level: but this must not"));
+    set_max_level(Level::Info);
+}
+
+// Level names work in any case, and only the ones that exist
+#[test_case]
+fn test_level_from_name() {
+    assert_eq!(Level::from_name("debug"), Some(Level::Debug));
+    assert_eq!(Level::from_name("WARN"), Some(Level::Warn));
+    assert_eq!(Level::from_name("warning"), None);
+}
@@ -10,16 +10,13 @@
 #![test_runner(tdos::test_runner::test_runner)]
 // Redefine what the test harness is called. This is needed, because we have no main, but a main
 // function is exactly what the custom_test_frameworks feature calls the function that calls the
-// test_runner. Thus, we need to rename that function, and then we can call it in our _start.
+// test_runner. Thus, we need to rename that function, and then we can call it in our kernel_main.
 #![reexport_test_harness_main = "test_main"]
 
+use bootloader::{entry_point, BootInfo};
 use core::panic::PanicInfo;
 use tdos::println;
-
-mod qemu;
-mod serial;
-#[cfg(test)]
-mod test_runner;
+use tdos::task::Executor;
 
 /// core does not provide its own panic handler, as its defined in std. Since we have a #![no_std]
 /// environment, we have to write our own panic_handler. The #[panic_handler] attribute lets the
@@ -29,8 +26,8 @@ mod test_runner;
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    println!("{}", info);
-    loop {}
+    tdos::report_panic(info);
+    tdos::hlt_loop();
 }
 
 /// Seperate panic handler when running tests. This writes to our SERIAL1 device which is then
@@ -39,55 +36,67 @@ fn panic(info: &PanicInfo) -> ! {
 #[cfg(test)]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    use test_runner::test_panic_handler;
+    use tdos::test_runner::test_panic_handler;
     test_panic_handler(info)
 }
 
-/// The custom entry point for the binary.
+// The bootloader calls our entry point with a pointer to the BootInfo, which tells us e.g. how
+// physical memory is laid out. entry_point! defines the actual _start function for us, and checks
+// that kernel_main has the signature the bootloader expects; with a plain extern "C" fn _start,
+// nothing would stop us from taking the wrong arguments.
+entry_point!(kernel_main);
+
+/// The entry point of the kernel, which is called by _start.
 ///
-/// This function needs #[no_mangle] so that this function is actually going to be called _start,
-/// instead of some cryptic identifier.
-/// This is important, because calling the entry point _start is the regular default calling
-/// convention for such a function for most systems.
-/// This function also is not allowed to return ever, because the function is called by the
+/// This function is not allowed to return ever, because the function is called by the
 /// bootloader directly, instead of a function inside of the code base.
 /// Eventually, we will want to call something like the exit system call.
-#[no_mangle]
-pub extern "C" fn _start() -> ! {
+fn kernel_main(boot_info: &'static BootInfo) -> ! {
+    // this also wipes whatever the bootloader left on the screen, and nothing can be printed
+    // before it
+    tdos::init(boot_info);
     println!("Welcome to tdos!");
-    println!("Unfortunately, this little kernel\nisn't interactive yet... <.<");
-
-    tdos::init();
+    tdos::cpu::print_info();
 
     #[cfg(test)]
     test_main();
 
     // draw_heart();
     println!("It didn't crash!");
-    loop {}
+
+    println!("Type help for a list of commands.");
+
+    let mut executor = Executor::new();
+    executor.spawn(tdos::shell::run());
+    executor.run();
+    // the shell never finishes, but if it did, there would be nothing left to do
+    tdos::hlt_loop();
 }
 
 #[allow(dead_code)]
 fn draw_heart() {
-    println!("   *******     *******   ");
-    println!("  *       *   *       *  ");
-    println!(" *         ***         * ");
-    println!("*  ======       ======  *");
-    println!("*    II     +   II      *");
-    println!("*    II    +++  ======  *");
-    println!("*    II     +       II  *");
-    println!(" *   II         ====== * ");
-    println!("  *                   *  ");
-    println!("   *                 *   ");
-    println!("    *               *    ");
-    println!("     *             *     ");
-    println!("      *           *      ");
-    println!("       *         *       ");
-    println!("        *       *        ");
-    println!("         *     *         ");
-    println!("          *   *          ");
-    println!("           * *           ");
-    println!("            *            ");
+    // every line is equally wide, so centering them keeps the heart in shape
+    tdos::vga_buffer::println_centered(concat!(
+        "   *******     *******   \n",
+        "  *       *   *       *  \n",
+        " *         ***         * \n",
+        "*  ======       ======  *\n",
+        "*    II     +   II      *\n",
+        "*    II    +++  ======  *\n",
+        "*    II     +       II  *\n",
+        " *   II         ====== * \n",
+        "  *                   *  \n",
+        "   *                 *   \n",
+        "    *               *    \n",
+        "     *             *     \n",
+        "      *           *      \n",
+        "       *         *       \n",
+        "        *       *        \n",
+        "         *     *         \n",
+        "          *   *          \n",
+        "           * *           \n",
+        "            *            ",
+    ));
 }
 
 /// Tests the test runner, basically
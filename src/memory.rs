@@ -0,0 +1,185 @@
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use core::fmt;
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags, PhysFrame, Size4KiB,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::allocator::{HEAP_SIZE, HEAP_START};
+
+/// Size of a physical frame (and a page) in bytes
+const FRAME_SIZE: u64 = 4096;
+
+/// Where the bootloader puts the kernel's stack, see kernel-stack-address in Cargo.toml. The
+/// bootloader leaves the first page there unmapped, and the stack starts right after it, so that
+/// running off the end of the stack (which grows down) causes a page fault instead of quietly
+/// overwriting whatever lies below it. The address has to be fixed for us to know where that guard
+/// page is; otherwise, the bootloader picks one on its own.
+pub const KERNEL_STACK_ADDRESS: u64 = 0x5555_0000_0000;
+
+/// The unmapped pages next to the kernel's stack and heap. Nothing should ever touch them, so an
+/// access to one of them almost certainly means that something ran past the end of its memory.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum GuardPage {
+    /// The page below the kernel's stack, see KERNEL_STACK_ADDRESS
+    Stack,
+    /// The page right below the heap, which allocator::init_heap leaves unmapped
+    BelowHeap,
+    /// The page right after the heap, which is not mapped either
+    AboveHeap,
+}
+
+impl GuardPage {
+    /// Returns the guard page the address lies in, if any
+    pub fn containing(address: VirtAddr) -> Option<GuardPage> {
+        let page = Page::<Size4KiB>::containing_address(address).start_address().as_u64();
+        let heap_start = HEAP_START as u64;
+        return match page {
+            KERNEL_STACK_ADDRESS => Some(GuardPage::Stack),
+            _ if page == heap_start - FRAME_SIZE => Some(GuardPage::BelowHeap),
+            _ if page == x86_64::align_up(heap_start + HEAP_SIZE as u64, FRAME_SIZE) => Some(GuardPage::AboveHeap),
+            _ => None,
+        };
+    }
+}
+
+/// What hitting the guard page most likely means, e.g. "stack overflow"
+impl fmt::Display for GuardPage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let diagnosis = match self {
+            GuardPage::Stack => "stack overflow",
+            GuardPage::BelowHeap => "heap underflow",
+            GuardPage::AboveHeap => "heap overflow",
+        };
+        return write!(f, "{}", diagnosis);
+    }
+}
+
+/// Returns a mapper for the currently active page tables.
+/// The bootloader maps all of physical memory into our virtual address space, starting at
+/// physical_memory_offset, so the physical address p can be accessed through the virtual address
+/// physical_memory_offset + p. This is what lets us read and write the page tables, which only know
+/// the physical addresses of each other.
+///
+/// # Safety
+///
+/// The caller has to make sure that all of physical memory really is mapped at
+/// physical_memory_offset. If it is not, we read and write the page tables at the wrong addresses,
+/// i.e. we scribble over whatever memory happens to be mapped there. This function must also only
+/// be called once, since it hands out a &mut to the level 4 page table, and two of those would
+/// alias each other.
+pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+    let level_4_table = active_level_4_table(physical_memory_offset);
+    return OffsetPageTable::new(level_4_table, physical_memory_offset);
+}
+
+/// Returns a mutable reference to the active level 4 page table, whose physical address is stored
+/// in the Cr3 register.
+///
+/// # Safety
+///
+/// Same as for init, which is the only function that is allowed to call this.
+unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut PageTable {
+    use x86_64::registers::control::Cr3;
+
+    let (level_4_table_frame, _) = Cr3::read();
+    let physical_address = level_4_table_frame.start_address();
+    let virtual_address = physical_memory_offset + physical_address.as_u64();
+    let page_table_pointer: *mut PageTable = virtual_address.as_mut_ptr();
+    return &mut *page_table_pointer;
+}
+
+/// Maps the page to the frame, so that it is present and writable, and flushes the page from the
+/// TLB, so that the CPU does not keep using a stale translation.
+/// If this needs new page tables (e.g. because nothing in the page's 1 GiB region was mapped
+/// before), their frames are taken from the frame_allocator. This fails if the page is already
+/// mapped, or if the frame_allocator runs out of frames.
+///
+/// # Safety
+///
+/// The caller has to make sure that the frame is not in use by anything else (unless sharing it is
+/// the point, like for memory mapped IO). Otherwise, writing through the page would silently
+/// overwrite someone else's memory.
+pub unsafe fn create_mapping(
+    page: Page,
+    frame: PhysFrame,
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    mapper.map_to(page, frame, flags, frame_allocator)?.flush();
+    return Ok(());
+}
+
+/// Hands out the usable physical frames from the memory map that the bootloader passes to us.
+/// The memory map tells us which regions of physical memory exist and what they are used for;
+/// only the Usable regions are actually free, everything else holds e.g. the kernel, the page
+/// tables or the boot info itself.
+/// Frames are handed out by index: the allocator remembers how many frames it has already handed
+/// out, and simply returns the next one. This means that frames can never be freed again, but it is
+/// good enough until we need something smarter.
+pub struct BootInfoFrameAllocator {
+    memory_map: &'static MemoryMap,
+    // index of the next usable frame to hand out
+    next: usize,
+}
+
+impl BootInfoFrameAllocator {
+    /// Creates a frame allocator from the memory map the bootloader passed to us.
+    ///
+    /// # Safety
+    ///
+    /// The caller has to make sure that the memory map is valid, i.e. that every frame in a region
+    /// marked as Usable is really unused. Otherwise we would hand out frames that are still in use,
+    /// and whoever gets them overwrites somebody else's memory. This function must also only be
+    /// called once, since two allocators would hand out the same frames.
+    pub unsafe fn init(memory_map: &'static MemoryMap) -> Self {
+        return BootInfoFrameAllocator { memory_map, next: 0 };
+    }
+
+    /// Returns an iterator over every usable frame in the memory map, in order
+    fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
+        let usable_regions = self
+            .memory_map
+            .iter()
+            .filter(|region| region.region_type == MemoryRegionType::Usable);
+        let address_ranges = usable_regions.map(|region| region.range.start_addr()..region.range.end_addr());
+        // the bootloader aligns the regions to the frame size, so every step of FRAME_SIZE bytes
+        // is the start of a frame
+        let frame_addresses = address_ranges.flat_map(|range| range.step_by(FRAME_SIZE as usize));
+        return frame_addresses.map(|address| PhysFrame::containing_address(PhysAddr::new(address)));
+    }
+}
+
+// This is unsafe to implement, because the implementer has to guarantee that every frame is only
+// handed out once, which we do by never handing out a frame with an index smaller than next.
+unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        let frame = self.usable_frames().nth(self.next);
+        self.next += 1;
+        return frame;
+    }
+}
+
+// Only the pages right next to the stack and the heap are guard pages, not the stack and the heap
+// themselves
+#[test_case]
+fn test_guard_page() {
+    let heap_start = VirtAddr::new(HEAP_START as u64);
+    let heap_end = heap_start + HEAP_SIZE as u64;
+    assert_eq!(GuardPage::containing(heap_start - 1u64), Some(GuardPage::BelowHeap));
+    assert_eq!(
+        GuardPage::containing(heap_start - FRAME_SIZE),
+        Some(GuardPage::BelowHeap)
+    );
+    assert_eq!(GuardPage::containing(heap_start - FRAME_SIZE - 1u64), None);
+    assert_eq!(GuardPage::containing(heap_start), None);
+    assert_eq!(GuardPage::containing(heap_end - 1u64), None);
+    assert_eq!(GuardPage::containing(heap_end), Some(GuardPage::AboveHeap));
+
+    let stack_start = VirtAddr::new(KERNEL_STACK_ADDRESS + FRAME_SIZE);
+    assert_eq!(GuardPage::containing(stack_start - 8u64), Some(GuardPage::Stack));
+    assert_eq!(GuardPage::containing(stack_start), None);
+    assert_eq!(alloc::format!("{}", GuardPage::BelowHeap), "heap underflow");
+}
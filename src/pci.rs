@@ -0,0 +1,225 @@
+use alloc::vec::Vec;
+use core::fmt;
+use x86_64::instructions::port::Port;
+
+use crate::interrupts;
+
+/// IO ports of PCI's configuration mechanism #1: the address of a register in some function's
+/// configuration space goes into CONFIG_ADDRESS, after which CONFIG_DATA reads (or writes) that
+/// register
+const CONFIG_ADDRESS_PORT: u16 = 0xcf8;
+const CONFIG_DATA_PORT: u16 = 0xcfc;
+/// Set in CONFIG_ADDRESS to actually access the configuration space
+const CONFIG_ENABLE: u32 = 1 << 31;
+
+/// What reading the vendor id of a function that does not exist returns, since nobody drives the
+/// bus for it
+const NO_VENDOR: u16 = 0xffff;
+
+/// Offsets of the registers in the configuration space's header that we read. Every register is
+/// 32 bits wide, and holds several fields.
+/// vendor id (bits 0-15), device id (bits 16-31)
+const REGISTER_ID: u8 = 0x00;
+/// revision (bits 0-7), programming interface (bits 8-15), subclass (bits 16-23), and class
+/// (bits 24-31)
+const REGISTER_CLASS: u8 = 0x08;
+/// header type (bits 16-22), and whether the device has more than one function (bit 23)
+const REGISTER_HEADER_TYPE: u8 = 0x0c;
+/// The first base address register (BAR), followed by the others
+const REGISTER_BAR0: u8 = 0x10;
+
+/// Set in the header type if the device has functions besides function 0
+const MULTI_FUNCTION: u8 = 1 << 7;
+
+/// The number of devices on a bus, and of functions of a device
+const DEVICES_PER_BUS: u8 = 32;
+const FUNCTIONS_PER_DEVICE: u8 = 8;
+
+/// Where a function sits on the PCI bus. A device (a card, or a chip on the mainboard) may have up
+/// to 8 functions, which look like separate devices to us, e.g. the IDE controller and the ISA
+/// bridge of a single chipset chip.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct PciAddress {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl PciAddress {
+    /// What goes into CONFIG_ADDRESS to access the register at the offset. Registers are 32 bits
+    /// wide, so the lowest two bits of the offset are dropped.
+    fn config_address(&self, offset: u8) -> u32 {
+        return CONFIG_ENABLE
+            | u32::from(self.bus) << 16
+            | u32::from(self.device) << 11
+            | u32::from(self.function) << 8
+            | u32::from(offset & 0xfc);
+    }
+}
+
+/// The usual notation bus:device.function, in hex, e.g. "00:1f.3"
+impl fmt::Display for PciAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{:02x}:{:02x}.{:x}", self.bus, self.device, self.function);
+    }
+}
+
+/// Reads the 32 bit register at the offset in the function's configuration space. Functions that
+/// do not exist read as all ones.
+pub fn read_config(address: PciAddress, offset: u8) -> u32 {
+    let mut config_address = Port::<u32>::new(CONFIG_ADDRESS_PORT);
+    let mut config_data = Port::<u32>::new(CONFIG_DATA_PORT);
+    // an interrupt handler that reads another register in between would change CONFIG_ADDRESS from
+    // under us. Reading the configuration space has no side effects.
+    return interrupts::without_interrupts(|| unsafe {
+        config_address.write(address.config_address(offset));
+        config_data.read()
+    });
+}
+
+/// A function on the PCI bus, with what its configuration space says about it
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct PciDevice {
+    pub address: PciAddress,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    /// What kind of device this is, e.g. 0x03 for a display controller, see class_name
+    pub class: u8,
+    /// Narrows down the class, e.g. 0x00 for a VGA compatible one
+    pub subclass: u8,
+    /// Narrows it down even further, e.g. which register interface a USB controller has
+    pub prog_if: u8,
+    /// The layout of the rest of the header: 0 for a device, 1 for a PCI-to-PCI bridge, and 2 for
+    /// a CardBus bridge
+    pub header_type: u8,
+    /// The base address registers, i.e. where the device's registers are, as read; the ones the
+    /// header type does not have are 0. If bit 0 is set, the rest is an IO port, otherwise it is a
+    /// physical address, whose lowest 4 bits are flags, and which may continue in the next BAR.
+    pub bars: [u32; 6],
+}
+
+impl PciDevice {
+    /// Reads the function's configuration space, or returns None if there is no such function
+    pub fn read(address: PciAddress) -> Option<PciDevice> {
+        let id = read_config(address, REGISTER_ID);
+        let vendor_id = id as u16;
+        if vendor_id == NO_VENDOR {
+            return None;
+        }
+        let class = read_config(address, REGISTER_CLASS);
+        let header_type = (read_config(address, REGISTER_HEADER_TYPE) >> 16) as u8;
+        // a device has 6 BARs, a PCI-to-PCI bridge 2, and a CardBus bridge none
+        let bar_count = match header_type & !MULTI_FUNCTION {
+            0 => 6,
+            1 => 2,
+            _ => 0,
+        };
+        let mut bars = [0; 6];
+        for (i, bar) in bars.iter_mut().enumerate().take(bar_count) {
+            *bar = read_config(address, REGISTER_BAR0 + 4 * i as u8);
+        }
+        return Some(PciDevice {
+            address,
+            vendor_id,
+            device_id: (id >> 16) as u16,
+            class: (class >> 24) as u8,
+            subclass: (class >> 16) as u8,
+            prog_if: (class >> 8) as u8,
+            header_type: header_type & !MULTI_FUNCTION,
+            bars,
+        });
+    }
+
+    /// Returns a name for the device's class, e.g. "display controller"
+    pub fn class_name(&self) -> &'static str {
+        return match self.class {
+            0x00 => "unclassified",
+            0x01 => "mass storage controller",
+            0x02 => "network controller",
+            0x03 => "display controller",
+            0x04 => "multimedia controller",
+            0x05 => "memory controller",
+            0x06 => "bridge",
+            0x07 => "communication controller",
+            0x08 => "system peripheral",
+            0x09 => "input device controller",
+            0x0c => "serial bus controller",
+            _ => "other",
+        };
+    }
+}
+
+/// One line of the inventory, e.g. "00:02.0 1234:1111 03.00 display controller"
+impl fmt::Display for PciDevice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(
+            f,
+            "{} {:04x}:{:04x} {:02x}.{:02x} {}",
+            self.address,
+            self.vendor_id,
+            self.device_id,
+            self.class,
+            self.subclass,
+            self.class_name()
+        );
+    }
+}
+
+/// Finds every function on the PCI bus, by trying every address there is. That is the brute force
+/// way, as opposed to following the bridges from bus 0, but it needs nothing but read_config, and
+/// it is fast enough: function 0 tells us whether a device exists at all, and whether it has other
+/// functions, so we read about 8000 vendor ids on an empty machine.
+/// This only reads, so it is fine to call at any time.
+pub fn enumerate() -> Vec<PciDevice> {
+    let mut devices = Vec::new();
+    for bus in 0..=u8::MAX {
+        for device in 0..DEVICES_PER_BUS {
+            let Some(first) = PciDevice::read(PciAddress {
+                bus,
+                device,
+                function: 0,
+            }) else {
+                continue;
+            };
+            devices.push(first);
+            // the multi function bit is only in function 0's header type, the one in PciDevice is
+            // masked out
+            let header_type = (read_config(first.address, REGISTER_HEADER_TYPE) >> 16) as u8;
+            if header_type & MULTI_FUNCTION == 0 {
+                continue;
+            }
+            for function in 1..FUNCTIONS_PER_DEVICE {
+                devices.extend(PciDevice::read(PciAddress { bus, device, function }));
+            }
+        }
+    }
+    return devices;
+}
+
+// The fields of the address end up in the right bits, and the offset is aligned to a register
+#[test_case]
+fn test_config_address() {
+    let address = PciAddress {
+        bus: 0x12,
+        device: 0x1f,
+        function: 3,
+    };
+    assert_eq!(address.config_address(0x0e), 0x8012_fb0c);
+    assert_eq!(alloc::format!("{}", address), "12:1f.3");
+}
+
+// QEMU's default machine has a host bridge at 00:00.0, an ISA bridge (behind which sit the legacy
+// devices like isa-debug-exit, which are not on the PCI bus themselves), and a VGA card
+#[test_case]
+fn test_enumerate() {
+    use alloc::string::ToString;
+
+    let devices = enumerate();
+    let has = |class, subclass| devices.iter().any(|d| (d.class, d.subclass) == (class, subclass));
+    let first = devices.first().expect("no PCI devices");
+    assert_eq!(first.address.to_string(), "00:00.0");
+    assert_eq!((first.class, first.subclass), (0x06, 0x00), "no host bridge at 00:00.0");
+    assert!(has(0x06, 0x01), "no ISA bridge");
+    assert!(has(0x03, 0x00), "no VGA controller");
+    assert!(devices.iter().all(|d| d.vendor_id != NO_VENDOR));
+}
@@ -0,0 +1,69 @@
+use pic8259::ChainedPics;
+use spin::Mutex;
+
+/// Vector offsets of the two chained 8259 PICs (programmable interrupt controllers).
+/// By default, the PICs deliver the hardware IRQs 0 to 15 on the vectors 0 to 15, which are
+/// already taken by CPU exceptions, so we remap them to the first free vectors instead: the
+/// primary PIC's IRQs 0 to 7 become the vectors 32 to 39, and the secondary's IRQs 8 to 15 become
+/// the vectors 40 to 47.
+pub const PIC_1_OFFSET: u8 = 32;
+pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
+
+/// The IRQ of the first serial interface, which the BIOS leaves masked
+const SERIAL1_IRQ: u8 = 4;
+
+// The secondary PIC is chained to the primary one, i.e. its interrupts go through the primary PIC,
+// which is why the pic8259 crate handles both of them as one ChainedPics. Creating it is unsafe,
+// because wrong offsets would make the PICs deliver interrupts on vectors that mean something
+// else. The mutex makes sure we only ever talk to the PICs from one place at a time.
+pub static PICS: Mutex<ChainedPics> = Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
+
+/// The interrupt vectors of the hardware IRQs we handle
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[repr(u8)]
+pub enum InterruptIndex {
+    Timer = PIC_1_OFFSET,
+    Keyboard,
+    Serial1 = PIC_1_OFFSET + SERIAL1_IRQ,
+}
+
+impl InterruptIndex {
+    pub fn as_u8(self) -> u8 {
+        return self as u8;
+    }
+
+    pub fn as_usize(self) -> usize {
+        return usize::from(self.as_u8());
+    }
+}
+
+/// Remaps the PICs to PIC_1_OFFSET and PIC_2_OFFSET, and unmasks the IRQs we handle that the BIOS
+/// left masked. Note that this does not enable interrupts on the CPU.
+pub fn init() {
+    let mut pics = PICS.lock();
+    unsafe {
+        pics.initialize();
+        let [mask1, mask2] = pics.read_masks();
+        pics.write_masks(mask1 & !(1 << SERIAL1_IRQ), mask2);
+    }
+}
+
+/// Tells the PICs that we are done handling the interrupt, so they can deliver the next one.
+/// Every handler of a hardware IRQ must call this, because until then, the PIC does not send any
+/// further interrupts of the same or lower priority, which effectively freezes them.
+pub fn notify_end_of_interrupt(index: InterruptIndex) {
+    unsafe {
+        PICS.lock().notify_end_of_interrupt(index.as_u8());
+    }
+}
+
+// With interrupts enabled, hlt returns on the next interrupt (at the latest, on the next timer
+// tick), so this test checks that the interrupts are delivered to handlers that acknowledge them,
+// rather than faulting or freezing.
+#[test_case]
+fn test_hlt_with_interrupts_enabled() {
+    assert!(x86_64::instructions::interrupts::are_enabled());
+    for _ in 0..3 {
+        x86_64::instructions::hlt();
+    }
+}
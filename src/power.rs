@@ -0,0 +1,104 @@
+use x86_64::instructions::port::Port;
+
+/// Status and command port of the 8042 PS/2 controller (the "keyboard controller")
+const PS2_COMMAND_PORT: u16 = 0x64;
+/// Set in the PS/2 controller's status while it has not yet taken the last byte we wrote to it
+const PS2_STATUS_INPUT_FULL: u8 = 1 << 1;
+/// The PS/2 controller command that pulses the CPU's reset line
+const PS2_COMMAND_RESET: u8 = 0xFE;
+/// How often we poll the PS/2 controller before giving up on it
+const PS2_MAX_POLLS: usize = 100_000;
+
+/// QEMU's ACPI PM1a control register, and the value that puts the machine into the S5 ("soft off")
+/// sleep state, i.e. turns it off
+const QEMU_PM1A_CONTROL_PORT: u16 = 0x604;
+const QEMU_SLEEP_S5: u16 = 0x2000;
+/// Same for Bochs and QEMU before version 2.0
+const BOCHS_PM1A_CONTROL_PORT: u16 = 0xB004;
+
+/// Polls the status returned by read_status until the input buffer is empty, at most max_polls
+/// times. Returns whether it became empty.
+/// Takes the status read as a function, so that it can be tested without a PS/2 controller.
+fn wait_for_input_buffer(mut read_status: impl FnMut() -> u8, max_polls: usize) -> bool {
+    for _ in 0..max_polls {
+        if read_status() & PS2_STATUS_INPUT_FULL == 0 {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+    return false;
+}
+
+/// Resets the machine.
+/// We ask the PS/2 controller to pulse the CPU's reset line, which is an old, but widely
+/// supported way of resetting a PC, and works under QEMU. If that does not work, we triple fault:
+/// with an empty IDT, any interrupt causes a fault that cannot be handled, and neither can the
+/// resulting double fault, at which point the CPU gives up and resets.
+pub fn reboot() -> ! {
+    let mut command = Port::<u8>::new(PS2_COMMAND_PORT);
+    x86_64::instructions::interrupts::disable();
+    // the controller ignores commands while its input buffer is full
+    if wait_for_input_buffer(|| unsafe { command.read() }, PS2_MAX_POLLS) {
+        unsafe { command.write(PS2_COMMAND_RESET) };
+    }
+    // give the reset a moment to happen
+    for _ in 0..PS2_MAX_POLLS {
+        core::hint::spin_loop();
+    }
+
+    triple_fault();
+}
+
+/// Turns the machine off.
+/// Doing this properly needs ACPI: the port of the PM1a control register and the value for the S5
+/// state are stored in the ACPI tables, which we do not parse (yet). So for now we only know how to
+/// turn off QEMU and Bochs, whose values are well known. On anything else, this just halts.
+/// Note that this is for actually shutting down; tests should use qemu::exit_qemu, which also
+/// reports an exit code.
+pub fn shutdown() -> ! {
+    // TODO: find the PM1a control port and the S5 value in the ACPI tables
+    x86_64::instructions::interrupts::disable();
+    unsafe {
+        Port::<u16>::new(QEMU_PM1A_CONTROL_PORT).write(QEMU_SLEEP_S5);
+        Port::<u16>::new(BOCHS_PM1A_CONTROL_PORT).write(QEMU_SLEEP_S5);
+    }
+    crate::hlt_loop();
+}
+
+/// Resets the CPU by loading an empty IDT and raising an interrupt
+fn triple_fault() -> ! {
+    use x86_64::instructions::tables::{lidt, DescriptorTablePointer};
+    use x86_64::VirtAddr;
+
+    let empty = DescriptorTablePointer {
+        limit: 0,
+        base: VirtAddr::zero(),
+    };
+    unsafe {
+        lidt(&empty);
+    }
+    x86_64::instructions::interrupts::int3();
+    // we should not get here, but if we do, there is nothing left to try
+    crate::hlt_loop();
+}
+
+// The wait ends as soon as the input buffer is empty, and gives up after max_polls
+#[test_case]
+fn test_wait_for_input_buffer() {
+    let mut polls = 0;
+    let statuses = [PS2_STATUS_INPUT_FULL, PS2_STATUS_INPUT_FULL, 0, PS2_STATUS_INPUT_FULL];
+    let read_status = || {
+        polls += 1;
+        return statuses[polls - 1];
+    };
+    assert!(wait_for_input_buffer(read_status, 10));
+    assert_eq!(polls, 3);
+
+    let mut polls = 0;
+    let read_status = || {
+        polls += 1;
+        return PS2_STATUS_INPUT_FULL;
+    };
+    assert!(!wait_for_input_buffer(read_status, 10));
+    assert_eq!(polls, 10);
+}
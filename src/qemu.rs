@@ -1,3 +1,5 @@
+use core::fmt;
+
 /// Enumerates the different exit codes for QEMU. We use this for our test runner, because we want
 /// QEMU to automatically exit after running our tests. The exact values are simply values that are
 /// not used by QEMU otherwise.
@@ -6,6 +8,9 @@
 /// QEMU. When x = 0, this transformation would result in 1, which is the exit code QEMU uses to
 /// denote a failed run making it impossible to distinguish between our tests failing and QEMU
 /// failing.
+/// Besides Success and Failed, there are exit codes for the kinds of failures that a host script
+/// might want to treat differently: a test that hung, a panic outside of any test, and running out
+/// of heap memory.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 #[repr(u32)]
 #[allow(dead_code)] // this code is only really used in tests, so cargo complains about dead code
@@ -13,6 +18,50 @@
 pub enum QemuExitCode {
     Success = 0x10,
     Failed = 0x11,
+    Timeout = 0x12,
+    Panic = 0x13,
+    OutOfMemory = 0x14,
+}
+
+impl QemuExitCode {
+    /// Every exit code, in the order of their values
+    pub const ALL: [QemuExitCode; 5] = [
+        QemuExitCode::Success,
+        QemuExitCode::Failed,
+        QemuExitCode::Timeout,
+        QemuExitCode::Panic,
+        QemuExitCode::OutOfMemory,
+    ];
+}
+
+/// The value written to the isa-debug-exit port
+impl From<QemuExitCode> for u32 {
+    fn from(exit_code: QemuExitCode) -> u32 {
+        return exit_code as u32;
+    }
+}
+
+/// A value that is not one of our exit codes, see QemuExitCode's TryFrom<u32>
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct UnknownExitCode(pub u32);
+
+impl fmt::Display for UnknownExitCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "unknown exit code {:#x}", self.0);
+    }
+}
+
+/// Turns the value back into the exit code, e.g. for a host side test wrapper. Note that the host
+/// sees (code << 1) | 1 as QEMU's exit status, so it has to undo that first.
+impl TryFrom<u32> for QemuExitCode {
+    type Error = UnknownExitCode;
+
+    fn try_from(value: u32) -> Result<QemuExitCode, UnknownExitCode> {
+        return QemuExitCode::ALL
+            .into_iter()
+            .find(|exit_code| u32::from(*exit_code) == value)
+            .ok_or(UnknownExitCode(value));
+    }
 }
 
 /// Exits QEMU with the exit_code.
@@ -26,6 +75,27 @@ pub fn exit_qemu(exit_code: QemuExitCode) {
     use x86_64::instructions::port::Port;
     unsafe {
         let mut port = Port::new(0xf4);
-        port.write(exit_code as u32);
+        port.write(u32::from(exit_code));
+    }
+}
+
+// The host sees (code << 1) | 1, so every code has to stay distinct from QEMU's own exit codes
+// after that transformation, and the values must never change, since host scripts rely on them.
+#[test_case]
+fn test_exit_code_values() {
+    assert_eq!(QemuExitCode::Success as u32, 0x10);
+    assert_eq!(QemuExitCode::Failed as u32, 0x11);
+    assert_eq!(QemuExitCode::Timeout as u32, 0x12);
+    assert_eq!(QemuExitCode::Panic as u32, 0x13);
+    assert_eq!(QemuExitCode::OutOfMemory as u32, 0x14);
+}
+
+// Every exit code survives the trip to its value and back, and any other value is an error
+#[test_case]
+fn test_exit_code_conversions() {
+    for exit_code in QemuExitCode::ALL {
+        assert_eq!(QemuExitCode::try_from(u32::from(exit_code)), Ok(exit_code));
     }
+    assert_eq!(QemuExitCode::try_from(0), Err(UnknownExitCode(0)));
+    assert_eq!(QemuExitCode::try_from(0x15), Err(UnknownExitCode(0x15)));
 }
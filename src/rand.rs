@@ -0,0 +1,109 @@
+use core::arch::asm;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use crate::cpu::{self, Feature};
+use crate::tsc;
+
+/// How often we retry rdrand before giving up. Intel recommends 10 retries, after which a failure
+/// means the hardware is broken rather than just busy.
+const RDRAND_RETRIES: usize = 10;
+
+/// Runs rdrand once, returning None if the CPU had no random number ready.
+///
+/// # Safety
+///
+/// The CPU must support rdrand (see cpu::Feature::Rdrand), otherwise this is an invalid opcode.
+unsafe fn rdrand() -> Option<u64> {
+    let value: u64;
+    let success: u8;
+    // rdrand sets the carry flag if the value is valid
+    asm!("rdrand {0}", "setc {1}", out(reg) value, out(reg_byte) success, options(nomem, nostack));
+    if success == 0 {
+        return None;
+    }
+    return Some(value);
+}
+
+/// Returns a random number from the CPU's hardware random number generator, or None if the CPU
+/// does not have one (or it keeps failing).
+pub fn u64() -> Option<u64> {
+    if !cpu::has_feature(Feature::Rdrand) {
+        return None;
+    }
+    for _ in 0..RDRAND_RETRIES {
+        // we just checked that the CPU supports rdrand
+        if let Some(value) = unsafe { rdrand() } {
+            return Some(value);
+        }
+    }
+    return None;
+}
+
+/// A xorshift pseudo random number generator: fast, tiny, and definitely not cryptographically
+/// secure. The same seed always produces the same numbers, which is what you want for e.g.
+/// reproducible tests, and what you do not want for anything security related.
+pub struct Xorshift {
+    // must never be 0, since xorshift maps 0 to 0 forever
+    state: u64,
+}
+
+impl Xorshift {
+    pub const fn new(seed: u64) -> Self {
+        return Xorshift {
+            state: if seed == 0 { 0x2545_f491_4f6c_dd1d } else { seed },
+        };
+    }
+
+    /// Seeds the generator from the time stamp counter, which is different on every boot
+    pub fn from_tsc() -> Self {
+        return Self::new(tsc::now_cycles());
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        // the xorshift64 shifts from Marsaglia's paper
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        return self.state;
+    }
+}
+
+// Fallback for when there is no rdrand. The interrupt handlers do not use this, but we still lock
+// it with interrupts disabled, so that they can start using it without deadlocking.
+lazy_static! {
+    static ref XORSHIFT: Mutex<Xorshift> = Mutex::new(Xorshift::from_tsc());
+}
+
+/// Returns a pseudo random number from a xorshift generator seeded from the time stamp counter.
+/// Unlike u64, this always works, but it is only good enough for things like hashing.
+pub fn xorshift() -> u64 {
+    return crate::interrupts::without_interrupts(|| XORSHIFT.lock().next_u64());
+}
+
+// A few random numbers in a row should never repeat. Whether the CPU has rdrand depends on the CPU
+// QEMU emulates, and without it, there are no random numbers at all.
+#[test_case]
+fn test_u64() {
+    if !cpu::has_feature(Feature::Rdrand) {
+        assert_eq!(u64(), None);
+        return;
+    }
+    let a = u64().unwrap();
+    let b = u64().unwrap();
+    let c = u64().unwrap();
+    assert!(a != b && b != c && a != c);
+}
+
+// The same seed gives the same numbers, and the numbers do not repeat right away
+#[test_case]
+fn test_xorshift() {
+    let mut a = Xorshift::new(42);
+    let mut b = Xorshift::new(42);
+    let first = a.next_u64();
+    assert_eq!(first, b.next_u64());
+    assert_ne!(first, a.next_u64());
+    // a seed of 0 would get stuck at 0
+    assert_ne!(Xorshift::new(0).next_u64(), 0);
+    assert_ne!(xorshift(), xorshift());
+}
@@ -0,0 +1,152 @@
+use core::fmt;
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+/// The CMOS is accessed through two ports: we write the number of a register to the index port,
+/// and then read that register's value from the data port.
+const CMOS_INDEX_PORT: u16 = 0x70;
+const CMOS_DATA_PORT: u16 = 0x71;
+
+// The CMOS registers of the real-time clock (RTC)
+const REGISTER_SECONDS: u8 = 0x00;
+const REGISTER_MINUTES: u8 = 0x02;
+const REGISTER_HOURS: u8 = 0x04;
+const REGISTER_DAY: u8 = 0x07;
+const REGISTER_MONTH: u8 = 0x08;
+const REGISTER_YEAR: u8 = 0x09;
+const REGISTER_STATUS_A: u8 = 0x0A;
+const REGISTER_STATUS_B: u8 = 0x0B;
+
+/// Set in status register A while the RTC updates its registers, during which they might be
+/// inconsistent
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+/// Set in status register B if the RTC uses 24 hour mode, otherwise it uses 12 hour mode
+const STATUS_B_24_HOUR: u8 = 1 << 1;
+/// Set in status register B if the values are binary, otherwise they are BCD
+const STATUS_B_BINARY: u8 = 1 << 2;
+/// Set in the hours register in 12 hour mode for times after noon
+const HOURS_PM: u8 = 1 << 7;
+
+// Selecting a register and reading it are two separate port accesses, so they must not be
+// interleaved with somebody else's.
+static CMOS: Mutex<()> = Mutex::new(());
+
+/// A point in time as read from the RTC
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+/// Formats the time like "2024-05-01 13:37:00"
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        );
+    }
+}
+
+/// Reads a CMOS register. The caller must hold the CMOS lock.
+fn read_register(register: u8) -> u8 {
+    let mut index = Port::<u8>::new(CMOS_INDEX_PORT);
+    let mut data = Port::<u8>::new(CMOS_DATA_PORT);
+    unsafe {
+        index.write(register);
+        return data.read();
+    }
+}
+
+/// Reads the raw time registers, i.e. without converting them from BCD or 12 hour mode, after
+/// waiting for any update in progress to finish
+fn read_raw() -> [u8; 6] {
+    while read_register(REGISTER_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0 {
+        core::hint::spin_loop();
+    }
+    return [
+        read_register(REGISTER_YEAR),
+        read_register(REGISTER_MONTH),
+        read_register(REGISTER_DAY),
+        read_register(REGISTER_HOURS),
+        read_register(REGISTER_MINUTES),
+        read_register(REGISTER_SECONDS),
+    ];
+}
+
+/// Converts a binary coded decimal, where each nibble is a decimal digit, to binary, e.g. 0x42 to 42
+fn bcd_to_binary(bcd: u8) -> u8 {
+    return (bcd >> 4) * 10 + (bcd & 0x0f);
+}
+
+/// Returns the current date and time, as kept by the RTC. Which time zone that is depends on
+/// whoever set the clock; QEMU uses UTC by default.
+/// The RTC only stores two digits of the year, so this assumes that we are in the 21st century.
+pub fn now() -> DateTime {
+    let (raw, status_b) = crate::interrupts::without_interrupts(|| {
+        let _cmos = CMOS.lock();
+        // An update could start right after we checked the update in progress flag, in which case
+        // we might read some registers before and some after the update. So we read until we get
+        // the same values twice in a row.
+        let mut raw = read_raw();
+        loop {
+            let again = read_raw();
+            if again == raw {
+                break;
+            }
+            raw = again;
+        }
+        return (raw, read_register(REGISTER_STATUS_B));
+    });
+
+    let [year, month, day, mut hour, minute, second] = raw;
+    // in 12 hour mode, the PM flag is in the hour's top bit, which is not part of the BCD value
+    let pm = status_b & STATUS_B_24_HOUR == 0 && hour & HOURS_PM != 0;
+    hour &= !HOURS_PM;
+
+    let convert = |value| {
+        if status_b & STATUS_B_BINARY == 0 {
+            return bcd_to_binary(value);
+        }
+        return value;
+    };
+    let mut hour = convert(hour);
+    if status_b & STATUS_B_24_HOUR == 0 {
+        // 12 AM is midnight, 12 PM is noon
+        hour %= 12;
+        if pm {
+            hour += 12;
+        }
+    }
+    return DateTime {
+        year: 2000 + u16::from(convert(year)),
+        month: convert(month),
+        day: convert(day),
+        hour,
+        minute: convert(minute),
+        second: convert(second),
+    };
+}
+
+#[test_case]
+fn test_bcd_to_binary() {
+    assert_eq!(bcd_to_binary(0x00), 0);
+    assert_eq!(bcd_to_binary(0x09), 9);
+    assert_eq!(bcd_to_binary(0x42), 42);
+    assert_eq!(bcd_to_binary(0x59), 59);
+}
+
+// QEMU initializes the RTC with the host's time, so it should be somewhere in the recent past
+#[test_case]
+fn test_now() {
+    let now = now();
+    assert!(now.year >= 2020, "implausible time {}", now);
+    assert!((1..=12).contains(&now.month));
+    assert!((1..=31).contains(&now.day));
+    assert!(now.hour < 24 && now.minute < 60 && now.second < 60);
+}
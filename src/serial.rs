@@ -1,6 +1,146 @@
-use lazy_static::lazy_static;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::Waker;
+use futures_util::task::AtomicWaker;
 use spin::Mutex;
 use uart_16550::SerialPort;
+use x86_64::instructions::port::Port;
+use x86_64::structures::idt::InterruptStackFrame;
+
+use crate::global::Global;
+use crate::interrupts::{self, InterruptGuard};
+use crate::pic::{self, InterruptIndex};
+
+/// Base port address of the first serial interface
+const SERIAL1_BASE: u16 = 0x3F8;
+/// Base port address of the second serial interface
+const SERIAL2_BASE: u16 = 0x2F8;
+
+/// Offset of the line status register from a UART's base address. Bit 0 of this register is set
+/// when a received byte is waiting to be read from the data register, which is at the base address
+/// itself.
+const LINE_STATUS_OFFSET: u16 = 5;
+const LINE_STATUS_DATA_READY: u8 = 1;
+/// Set in the line status register when the UART can take the next byte to send
+const LINE_STATUS_TRANSMIT_EMPTY: u8 = 1 << 5;
+
+/// Offset of the modem control register from a UART's base address. Setting bit 4 puts the UART
+/// into loopback mode, in which every byte it sends is received by itself instead of going out.
+const MODEM_CONTROL_OFFSET: u16 = 4;
+const MODEM_CONTROL_LOOPBACK: u8 = 1 << 4;
+
+/// The byte the self_test sends to itself, and how often it polls for it before giving up
+const SELF_TEST_BYTE: u8 = 0xAE;
+const SELF_TEST_MAX_POLLS: usize = 10_000;
+
+/// Offset of the interrupt enable register from a UART's base address. Setting bit 0 makes the
+/// UART raise an interrupt whenever it received a byte.
+const INTERRUPT_ENABLE_OFFSET: u16 = 1;
+const INTERRUPT_ENABLE_DATA_AVAILABLE: u8 = 1;
+
+/// Offset of the line control register from a UART's base address. Setting bit 7 (the divisor
+/// latch access bit, DLAB) turns the data and interrupt enable registers into the low and high
+/// byte of the baud rate divisor, until it is cleared again.
+const LINE_CONTROL_OFFSET: u16 = 3;
+const LINE_CONTROL_DLAB: u8 = 1 << 7;
+
+/// The UART's clock, divided by 16. The baud rate is this divided by the divisor.
+const BASE_BAUD: u32 = 115_200;
+/// The lowest baud rate whose divisor still fits into the 16 bits of the divisor registers
+const MIN_BAUD: u32 = BASE_BAUD / u16::MAX as u32 + 1;
+
+/// Capacity of the SERIAL_BUFFER
+const SERIAL_BUFFER_SIZE: usize = 256;
+
+/// Ring buffer for the bytes received on SERIAL1, which the serial interrupt handler pushes to and
+/// read_byte/try_read pop from.
+/// When the buffer is full, newly received bytes are dropped (rather than overwriting the oldest
+/// ones), so that whatever is reading sees a prefix of the input instead of a garbled mix.
+pub struct SerialBuffer {
+    data: [u8; SERIAL_BUFFER_SIZE],
+    // index of the oldest byte in the buffer
+    head: usize,
+    len: usize,
+}
+
+impl SerialBuffer {
+    pub const fn new() -> Self {
+        return SerialBuffer {
+            data: [0; SERIAL_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+        };
+    }
+
+    /// Appends a byte, and returns whether there was room for it
+    pub fn push(&mut self, byte: u8) -> bool {
+        if self.len == SERIAL_BUFFER_SIZE {
+            return false;
+        }
+        self.data[(self.head + self.len) % SERIAL_BUFFER_SIZE] = byte;
+        self.len += 1;
+        return true;
+    }
+
+    /// Removes and returns the oldest byte
+    pub fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.data[self.head];
+        self.head = (self.head + 1) % SERIAL_BUFFER_SIZE;
+        self.len -= 1;
+        return Some(byte);
+    }
+}
+
+impl Default for SerialBuffer {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+/// Whether every received byte is also echoed to SERIAL2 in hex, see set_echo_hex
+static ECHO_HEX: AtomicBool = AtomicBool::new(false);
+
+/// Turns echoing every byte received on SERIAL1 to SERIAL2 as two hex digits on or off, e.g. "AB"
+/// for 0xAB, so that we can see exactly what the host sent, including bytes that have no glyph.
+/// This helps when the bytes do not arrive the way we expect, e.g. because the host's terminal
+/// sends \r\n for enter, or the baud rates do not match.
+/// The bytes are still received as usual, so whatever reads them does not notice.
+pub fn set_echo_hex(echo_hex: bool) {
+    ECHO_HEX.store(echo_hex, Ordering::Relaxed);
+}
+
+/// Echoes the byte as two hex digits to out if set_echo_hex is on. out is _print2 everywhere but
+/// in the tests, which cannot read back what went to SERIAL2.
+fn echo_hex(byte: u8, out: impl FnOnce(fmt::Arguments)) {
+    if ECHO_HEX.load(Ordering::Relaxed) {
+        out(format_args!("{:02X}", byte));
+    }
+}
+
+/// Takes a byte that was received on SERIAL1: echoes it (see set_echo_hex), and pushes it to the
+/// buffer, dropping it if the buffer is full
+fn receive(buffer: &mut SerialBuffer, byte: u8, out: impl FnOnce(fmt::Arguments)) {
+    echo_hex(byte, out);
+    buffer.push(byte);
+}
+
+/// Bytes received on SERIAL1 that have not been read yet.
+/// Since the serial interrupt handler locks this, everybody else must only lock it with interrupts
+/// disabled; otherwise the handler could interrupt them while they hold the lock and spin forever.
+static SERIAL_BUFFER: Mutex<SerialBuffer> = Mutex::new(SerialBuffer::new());
+
+/// Woken by the serial interrupt handler whenever it received bytes, see register_rx_waker
+static RX_WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Makes the serial interrupt handler wake the waker once bytes arrive, for a task that waits for
+/// them with try_read. Register it before calling try_read, so that a byte arriving in between
+/// still wakes the task.
+pub fn register_rx_waker(waker: &Waker) {
+    RX_WAKER.register(waker);
+}
 
 // Our primary serial port is a UART 16550, which is a serial device model supported by all common
 // UARTS (a UART simply being a chip implementing a serial interface).
@@ -8,28 +148,366 @@ use uart_16550::SerialPort;
 // process is writing to this port. The port address for the serial port is 0x3F8, which is the
 // standard port for the first serial interface.
 // Unlike the VGA text buffer, this is obviously port IO though; the VGA text buffer was memory IO.
-lazy_static! {
-    pub static ref SERIAL1: Mutex<SerialPort> = {
-        let mut serial_port = unsafe { SerialPort::new(0x3F8) };
+pub static SERIAL1: Global<SerialPort> = Global::new("serial::SERIAL1");
+
+// The second serial port, which lives at the standard port address for the second serial interface.
+// This lets us separate different kinds of output onto two devices, e.g. test results on SERIAL1
+// and verbose logs on SERIAL2. Note that QEMU only connects this port to something if it is passed
+// a second -serial option.
+pub static SERIAL2: Global<SerialPort> = Global::new("serial::SERIAL2");
+
+/// Initializes SERIAL1 and SERIAL2. Panics if it is called twice.
+pub fn init() {
+    for (serial, base) in [(&SERIAL1, SERIAL1_BASE), (&SERIAL2, SERIAL2_BASE)] {
+        // nothing else uses these ports
+        let mut serial_port = unsafe { SerialPort::new(base) };
         serial_port.init();
-        Mutex::new(serial_port)
-    };
+        serial.init(serial_port);
+    }
+}
+
+/// Whether anything is written to the serial ports, see set_enabled
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Turns all output to the serial ports on or off, e.g. for the noserial flag on the kernel's
+/// command line (see cmdline). The ports are still set up, and can still be read from.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether output to the serial ports is turned on, see set_enabled
+pub fn enabled() -> bool {
+    return ENABLED.load(Ordering::Relaxed);
 }
 
-/// Writes formatted args to the SERIAL1 device.
+/// Writes formatted args to the SERIAL1 device, e.g. for code that already has fmt::Arguments, like
+/// `serial::write_fmt(format_args!("{} ticks", ticks))`. This is what serial_print! does, too.
 /// NOTE: uart_16550::SerialPort already implements fmt::Write, so we can call write_fmt on it
+/// Interrupts are disabled while we hold the lock: otherwise, an interrupt handler that prints
+/// while we are in the middle of printing would spin on the lock forever, since we cannot finish
+/// (and release it) until the handler returns.
+pub fn write_fmt(args: ::core::fmt::Arguments) {
+    use core::fmt::Write;
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    interrupts::without_interrupts(|| {
+        SERIAL1.lock().write_fmt(args).expect("Printing to serial failed");
+    });
+}
+
+/// Writes formatted args to the SERIAL2 device, see write_fmt.
+#[doc(hidden)]
+pub fn _print2(args: ::core::fmt::Arguments) {
+    use core::fmt::Write;
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    interrupts::without_interrupts(|| {
+        SERIAL2.lock().write_fmt(args).expect("Printing to serial failed");
+    });
+}
+
+/// Writes the bytes to SERIAL1 as they are, unlike write_fmt, which needs them to be a valid str.
+/// Like write_fmt, this does nothing if serial output is turned off.
+pub fn write_bytes(bytes: &[u8]) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    interrupts::without_interrupts(|| {
+        let mut serial = SERIAL1.lock();
+        for &byte in bytes {
+            serial.send(byte);
+        }
+    });
+}
+
+/// What every line on the error stream (see serial_eprint!) starts with, so that its lines stand out
+/// even if the host puts both streams into the same log
+const ERROR_PREFIX: &str = "[ERROR] ";
+
+/// Whether the next thing written to the error stream starts a new line. Only used while holding
+/// SERIAL2, so the ordering does not matter.
+static ERROR_AT_LINE_START: AtomicBool = AtomicBool::new(true);
+
+/// Writes to out, starting every line with ERROR_PREFIX. A line can be written in several pieces,
+/// e.g. by format args, so at_line_start remembers whether the last piece ended a line.
+struct Prefixed<'a, W: fmt::Write> {
+    out: &'a mut W,
+    at_line_start: bool,
+}
+
+impl<W: fmt::Write> fmt::Write for Prefixed<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for line in s.split_inclusive('\n') {
+            if self.at_line_start {
+                self.out.write_str(ERROR_PREFIX)?;
+            }
+            self.out.write_str(line)?;
+            self.at_line_start = line.ends_with('\n');
+        }
+        return Ok(());
+    }
+}
+
+/// Writes formatted args to the error stream, i.e. SERIAL2 with every line prefixed, see write_fmt.
 #[doc(hidden)]
-pub fn _print(args: ::core::fmt::Arguments) {
+pub fn _eprint(args: ::core::fmt::Arguments) {
     use core::fmt::Write;
-    SERIAL1.lock().write_fmt(args).expect("Printing to serial failed");
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    interrupts::without_interrupts(|| {
+        let mut serial = SERIAL2.lock();
+        let mut prefixed = Prefixed {
+            out: &mut *serial,
+            at_line_start: ERROR_AT_LINE_START.load(Ordering::Relaxed),
+        };
+        prefixed.write_fmt(args).expect("Printing to serial failed");
+        ERROR_AT_LINE_START.store(prefixed.at_line_start, Ordering::Relaxed);
+    });
+}
+
+/// Registers the serial interrupt handler, and makes SERIAL1 raise an interrupt for every received
+/// byte, so that the handler can move it into the SERIAL_BUFFER.
+pub fn enable_rx_interrupts() {
+    interrupts::register(InterruptIndex::Serial1.as_u8(), serial1_interrupt_handler);
+    // the lock makes sure nobody else touches the UART while we update the register
+    let _serial = SERIAL1.lock();
+    let mut interrupt_enable = Port::<u8>::new(SERIAL1_BASE + INTERRUPT_ENABLE_OFFSET);
+    unsafe {
+        let value = interrupt_enable.read();
+        interrupt_enable.write(value | INTERRUPT_ENABLE_DATA_AVAILABLE);
+    }
+}
+
+/// Returns the divisor closest to the baud rate, and the baud rate that divisor actually gives
+fn divisor_for(rate: u32) -> (u16, u32) {
+    let divisor = (BASE_BAUD + rate / 2) / rate;
+    return (divisor as u16, BASE_BAUD / divisor);
+}
+
+/// Reads SERIAL1's baud rate divisor. The caller must hold the SERIAL1 lock, and interrupts must be
+/// disabled, see set_baud.
+fn read_divisor() -> u16 {
+    let mut line_control = Port::<u8>::new(SERIAL1_BASE + LINE_CONTROL_OFFSET);
+    let mut low = Port::<u8>::new(SERIAL1_BASE);
+    let mut high = Port::<u8>::new(SERIAL1_BASE + 1);
+    unsafe {
+        let previous = line_control.read();
+        line_control.write(previous | LINE_CONTROL_DLAB);
+        let divisor = u16::from_le_bytes([low.read(), high.read()]);
+        line_control.write(previous);
+        return divisor;
+    }
+}
+
+/// Returns SERIAL1's current baud rate
+pub fn baud() -> u32 {
+    return interrupts::without_interrupts(|| {
+        let _serial = SERIAL1.lock();
+        return BASE_BAUD / u32::from(read_divisor());
+    });
+}
+
+/// Sets SERIAL1's baud rate, which init sets to 38400. Only rates that evenly divide
+/// 115200 are exact; any other rate is rounded to the closest one the UART can do, with a warning.
+/// Note that QEMU ignores the baud rate when the port is connected to stdio, but real hardware
+/// (and whatever sits on the other end of the cable) does not.
+/// Panics if the rate is below 2 or above 115200, or if SERIAL1 is not initialized yet.
+pub fn set_baud(rate: u32) {
+    assert!(
+        (MIN_BAUD..=BASE_BAUD).contains(&rate),
+        "invalid baud rate {}, must be between {} and {}",
+        rate,
+        MIN_BAUD,
+        BASE_BAUD
+    );
+    let (divisor, actual) = divisor_for(rate);
+    if actual != rate {
+        crate::log::warn!("serial: baud rate {} is not possible, using {} instead", rate, actual);
+    }
+
+    let mut line_control = Port::<u8>::new(SERIAL1_BASE + LINE_CONTROL_OFFSET);
+    let mut low = Port::<u8>::new(SERIAL1_BASE);
+    let mut high = Port::<u8>::new(SERIAL1_BASE + 1);
+    // While the DLAB is set, the serial interrupt handler would read the divisor instead of a
+    // received byte, since it does not take the lock. So we keep interrupts off until it is cleared.
+    interrupts::without_interrupts(|| {
+        let _serial = SERIAL1.lock();
+        unsafe {
+            let previous = line_control.read();
+            line_control.write(previous | LINE_CONTROL_DLAB);
+            let [divisor_low, divisor_high] = divisor.to_le_bytes();
+            low.write(divisor_low);
+            high.write(divisor_high);
+            line_control.write(previous & !LINE_CONTROL_DLAB);
+        }
+    });
+}
+
+/// Moves every byte the UART has received into the SERIAL_BUFFER. Bytes that do not fit are
+/// dropped.
+/// Note that we access the UART without locking SERIAL1, because the code we interrupted might be
+/// holding that lock, and then we would spin forever. This is fine, since reading a received byte
+/// does not interfere with sending bytes. Echoing to SERIAL2 does lock it, but everybody else only
+/// holds that lock with interrupts disabled, see write_fmt.
+extern "x86-interrupt" fn serial1_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    interrupts::count(InterruptIndex::Serial1.as_u8());
+    let mut line_status = Port::<u8>::new(SERIAL1_BASE + LINE_STATUS_OFFSET);
+    let mut data = Port::<u8>::new(SERIAL1_BASE);
+    {
+        let mut buffer = SERIAL_BUFFER.lock();
+        unsafe {
+            while line_status.read() & LINE_STATUS_DATA_READY != 0 {
+                receive(&mut buffer, data.read(), _print2);
+            }
+        }
+    }
+    RX_WAKER.wake();
+    pic::notify_end_of_interrupt(InterruptIndex::Serial1);
+}
+
+/// Polls SERIAL1's line status register until one of the bits in status is set, at most max_polls
+/// times. Returns whether that happened.
+fn poll_line_status(status: u8, max_polls: usize) -> bool {
+    let mut line_status = Port::<u8>::new(SERIAL1_BASE + LINE_STATUS_OFFSET);
+    for _ in 0..max_polls {
+        if unsafe { line_status.read() } & status != 0 {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+    return false;
+}
+
+/// Checks that there actually is a UART at SERIAL1's port, by putting it into loopback mode,
+/// sending a byte to itself, and checking that the byte arrives. Returns whether it did.
+/// Without a UART, writes to SERIAL1 silently go nowhere, which is easy to miss, since the serial
+/// port is where we would look for error messages in the first place.
+/// Whatever the UART already received is moved to the SERIAL_BUFFER first, so nothing is lost.
+pub fn self_test() -> bool {
+    let mut modem_control = Port::<u8>::new(SERIAL1_BASE + MODEM_CONTROL_OFFSET);
+    let mut data = Port::<u8>::new(SERIAL1_BASE);
+
+    // the serial interrupt handler would take the byte for itself, since it does not lock SERIAL1
+    let _interrupts = InterruptGuard::new();
+    let _serial = SERIAL1.lock();
+    if !poll_line_status(LINE_STATUS_TRANSMIT_EMPTY, SELF_TEST_MAX_POLLS) {
+        return false;
+    }
+    unsafe {
+        {
+            let mut buffer = SERIAL_BUFFER.lock();
+            while poll_line_status(LINE_STATUS_DATA_READY, 1) {
+                receive(&mut buffer, data.read(), _print2);
+            }
+        }
+
+        let previous = modem_control.read();
+        modem_control.write(previous | MODEM_CONTROL_LOOPBACK);
+        data.write(SELF_TEST_BYTE);
+        let received = poll_line_status(LINE_STATUS_DATA_READY, SELF_TEST_MAX_POLLS) && data.read() == SELF_TEST_BYTE;
+        modem_control.write(previous);
+        return received;
+    }
+}
+
+/// Writes the bytes to SERIAL1 straight through its ports, without locking SERIAL1, and even if
+/// serial output is turned off (see set_enabled). This is for the last words of a kernel that is
+/// about to die, e.g. in the double fault handler, where whoever holds the lock never gets to
+/// release it, and anything more complicated might fault again.
+/// If the UART does not take a byte in time, the byte is dropped instead of waiting forever.
+pub fn emergency_write(bytes: &[u8]) {
+    let mut data = Port::<u8>::new(SERIAL1_BASE);
+    for &byte in bytes {
+        if poll_line_status(LINE_STATUS_TRANSMIT_EMPTY, SELF_TEST_MAX_POLLS) {
+            unsafe { data.write(byte) };
+        }
+    }
+}
+
+/// Returns the oldest received byte that has not been read yet, or None if there is none.
+/// This never waits for the host to send something.
+pub fn try_read() -> Option<u8> {
+    // see SERIAL_BUFFER for why interrupts are disabled
+    let _interrupts = InterruptGuard::new();
+    return SERIAL_BUFFER.lock().pop();
+}
+
+/// Waits for the host to send a byte over SERIAL1 and returns it.
+/// With serial interrupts enabled, the bytes end up in the SERIAL_BUFFER, so we take them from
+/// there. Otherwise, we poll the UART's line status register until it reports a received byte, and
+/// echo it ourselves, see set_echo_hex.
+/// SERIAL1 is only locked for a single poll at a time, so that writers are not starved while we
+/// wait.
+pub fn read_byte() -> u8 {
+    let mut line_status = Port::<u8>::new(SERIAL1_BASE + LINE_STATUS_OFFSET);
+    let mut data = Port::<u8>::new(SERIAL1_BASE);
+    loop {
+        if let Some(byte) = try_read() {
+            return byte;
+        }
+        let received = {
+            // the lock makes sure nobody else touches the UART between our two port accesses, and
+            // with interrupts disabled, neither can the serial interrupt handler, which does not
+            // lock SERIAL1
+            let _interrupts = InterruptGuard::new();
+            let _serial = SERIAL1.lock();
+            unsafe {
+                if line_status.read() & LINE_STATUS_DATA_READY != 0 {
+                    Some(data.read())
+                } else {
+                    None
+                }
+            }
+        };
+        if let Some(byte) = received {
+            echo_hex(byte, _print2);
+            return byte;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Reads a line from SERIAL1 into buf, and returns the number of bytes written to buf.
+/// Every received character is echoed back, so the host can see what they are typing, and
+/// backspace (or DEL, which is what most terminals send instead) removes the last character.
+/// The line ends with either \n or \r, which is not written to buf. If the line is longer than
+/// buf, the remaining characters are dropped.
+pub fn read_line(buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    loop {
+        match read_byte() {
+            b'\n' | b'\r' => {
+                crate::serial_print!("\n");
+                return len;
+            },
+            0x08 | 0x7f => {
+                if len > 0 {
+                    len -= 1;
+                    // SerialPort turns this into "move back, write a blank, move back again", which
+                    // erases the character on the host's terminal
+                    crate::serial_print!("\x08");
+                }
+            },
+            byte => {
+                if len < buf.len() {
+                    buf[len] = byte;
+                    len += 1;
+                    crate::serial_print!("{}", byte as char);
+                }
+            },
+        }
+    }
 }
 
 /// Prints to the host using the first serial interface.
-/// Similar to our print implementation, but instead we use the _print function in this module to
-/// write to SERIAL1.
+/// Similar to our print implementation, but instead we use the write_fmt function in this module
+/// to write to SERIAL1.
 #[macro_export]
 macro_rules! serial_print {
-    ($($arg:tt)*) => ($crate::serial::_print(format_args!($($arg)*)));
+    ($($arg:tt)*) => ($crate::serial::write_fmt(format_args!($($arg)*)));
 }
 
 /// Prints to the host using the first serial interface, appending a newline.
@@ -43,3 +521,186 @@ macro_rules! serial_println {
     };
     ($fmt:expr, $($arg:tt)*) => ($crate::serial_print!(concat!($fmt, "\n"), $($arg)*));
 }
+
+/// Prints to the host using the second serial interface.
+#[macro_export]
+macro_rules! serial2_print {
+    ($($arg:tt)*) => ($crate::serial::_print2(format_args!($($arg)*)));
+}
+
+/// Prints to the host using the second serial interface, appending a newline.
+#[macro_export]
+macro_rules! serial2_println {
+    () => {
+        $crate::serial2_print!("\n")
+    };
+    ($fmt:expr) => {
+        $crate::serial2_print!(concat!($fmt, "\n"))
+    };
+    ($fmt:expr, $($arg:tt)*) => ($crate::serial2_print!(concat!($fmt, "\n"), $($arg)*));
+}
+
+/// Prints to the host's error stream, which is SERIAL2 with every line prefixed by "[ERROR] ".
+/// This lets the host tell errors apart from the normal output on SERIAL1, e.g. by sending them to
+/// its stderr, which is what the test runner's QEMU does (see Cargo.toml).
+#[macro_export]
+macro_rules! serial_eprint {
+    ($($arg:tt)*) => ($crate::serial::_eprint(format_args!($($arg)*)));
+}
+
+/// Prints to the host's error stream, appending a newline, see serial_eprint!
+#[macro_export]
+macro_rules! serial_eprintln {
+    () => {
+        $crate::serial_eprint!("\n")
+    };
+    ($fmt:expr) => {
+        $crate::serial_eprint!(concat!($fmt, "\n"))
+    };
+    ($fmt:expr, $($arg:tt)*) => ($crate::serial_eprint!(concat!($fmt, "\n"), $($arg)*));
+}
+
+// Just check that writing to the second serial port does not panic
+#[test_case]
+fn test_serial2_println() {
+    serial2_println!("test_serial2_println output");
+}
+
+// Reads a line typed by whoever is running the tests, so this is only compiled in with the
+// interactive-tests feature.
+#[cfg(feature = "interactive-tests")]
+#[test_case]
+fn test_read_line() {
+    serial_print!("\ntype hello and press enter: ");
+    let mut buf = [0; 16];
+    let len = read_line(&mut buf);
+    assert_eq!(&buf[..len], b"hello");
+}
+
+// Test the SERIAL_BUFFER's ring buffer logic on a local buffer, including wrapping around and
+// dropping bytes when it is full
+#[test_case]
+fn test_serial_buffer() {
+    let mut buffer = SerialBuffer::new();
+    assert_eq!(buffer.pop(), None);
+
+    for round in 0..3 {
+        for i in 0..SERIAL_BUFFER_SIZE {
+            assert!(buffer.push((i + round) as u8));
+        }
+        // the buffer is full, so the newest byte is dropped
+        assert!(!buffer.push(0xff));
+        for i in 0..SERIAL_BUFFER_SIZE {
+            assert_eq!(buffer.pop(), Some((i + round) as u8));
+        }
+        assert_eq!(buffer.pop(), None);
+    }
+}
+
+// Rates that divide 115200 are exact, all others are rounded to the closest divisor
+#[test_case]
+fn test_divisor_for() {
+    assert_eq!(divisor_for(115_200), (1, 115_200));
+    assert_eq!(divisor_for(38_400), (3, 38_400));
+    assert_eq!(divisor_for(9600), (12, 9600));
+    assert_eq!(divisor_for(7000), (16, 7200));
+    assert_eq!(divisor_for(MIN_BAUD), (57_600, 2));
+}
+
+// With echo_hex on, a received byte shows up as two hex digits, and still ends up in the buffer.
+// Off, nothing is echoed.
+#[test_case]
+fn test_echo_hex() {
+    use alloc::format;
+    use alloc::string::String;
+
+    let mut buffer = SerialBuffer::new();
+    let mut echoed = String::new();
+    set_echo_hex(true);
+    receive(&mut buffer, 0xAB, |args| echoed += &format!("{}", args));
+    receive(&mut buffer, 0x0A, |args| echoed += &format!("{}", args));
+    set_echo_hex(false);
+    receive(&mut buffer, 0x01, |args| echoed += &format!("{}", args));
+    assert_eq!(echoed, "AB0A");
+    assert_eq!(buffer.pop(), Some(0xAB));
+    assert_eq!(buffer.pop(), Some(0x0A));
+    assert_eq!(buffer.pop(), Some(0x01));
+}
+
+// QEMU's UART lets us read the divisor back, so we can check that set_baud actually wrote it
+#[test_case]
+fn test_set_baud() {
+    let before = baud();
+    set_baud(9600);
+    let divisor = interrupts::without_interrupts(|| {
+        let _serial = SERIAL1.lock();
+        return read_divisor();
+    });
+    assert_eq!(divisor, 12);
+    assert_eq!(baud(), 9600);
+    set_baud(before);
+    assert_eq!(baud(), before);
+}
+
+// Arguments that were put together somewhere else can be written as they are, just like
+// serial_print! does
+#[test_case]
+fn test_write_fmt() {
+    let args = format_args!("({} via {}) ", "written", "serial::write_fmt");
+    write_fmt(args);
+}
+
+// QEMU emulates a UART 16550 at SERIAL1's port, including its loopback mode
+#[test_case]
+fn test_self_test() {
+    assert!(self_test());
+}
+
+/// Vector of the software interrupt test_print_in_interrupt_handler uses
+#[cfg(test)]
+const PRINTING_TEST_VECTOR: u8 = 202;
+
+#[cfg(test)]
+static PRINTING_TEST_HANDLER_DONE: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+
+#[cfg(test)]
+extern "x86-interrupt" fn printing_test_handler(_stack_frame: InterruptStackFrame) {
+    serial_print!("(printed from an interrupt handler) ");
+    PRINTING_TEST_HANDLER_DONE.store(true, core::sync::atomic::Ordering::Relaxed);
+}
+
+// An interrupt handler can print, and printing leaves interrupts enabled afterwards. Note that a
+// software interrupt cannot fire while we hold the lock, since it only happens where we say so; the
+// deadlock it guards against needs a hardware interrupt at just the wrong time.
+#[test_case]
+fn test_print_in_interrupt_handler() {
+    interrupts::register(PRINTING_TEST_VECTOR, printing_test_handler);
+    unsafe {
+        core::arch::asm!("int {}", const PRINTING_TEST_VECTOR, options(nomem, nostack));
+    }
+    assert!(PRINTING_TEST_HANDLER_DONE.load(core::sync::atomic::Ordering::Relaxed));
+    assert!(x86_64::instructions::interrupts::are_enabled());
+}
+
+// Every line on the error stream is prefixed, even one that is written in pieces, and the error
+// stream only uses SERIAL2: we hold SERIAL1 while printing to it, so touching SERIAL1 would hang
+// until the test times out. Interrupts stay enabled for that, and no interrupt handler prints.
+#[test_case]
+fn test_serial_eprintln() {
+    use alloc::string::String;
+    use core::fmt::Write;
+
+    let mut text = String::new();
+    let mut prefixed = Prefixed {
+        out: &mut text,
+        at_line_start: true,
+    };
+    write!(prefixed, "first ").unwrap();
+    write!(prefixed, "line\nsecond line\n").unwrap();
+    assert!(prefixed.at_line_start);
+    assert_eq!(text, "[ERROR] first line\n[ERROR] second line\n");
+
+    let serial1 = SERIAL1.lock();
+    serial_eprintln!("test_serial_eprintln output");
+    drop(serial1);
+}
@@ -0,0 +1,220 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::future::poll_fn;
+use core::pin::Pin;
+use core::task::Poll;
+use futures_util::stream::Stream;
+
+use crate::keyboard::{self, ScancodeStream};
+use crate::{allocator, klog, pci, power, serial, time, vga_buffer};
+
+/// Lines longer than this are cut off
+const MAX_LINE_LENGTH: usize = 256;
+
+/// What the shell prints before every line the user types
+const PROMPT: &str = "> ";
+
+// The shell can be used from either the keyboard or the host's terminal, so everything it prints
+// goes to both the screen and SERIAL1.
+macro_rules! shell_print {
+    ($($arg:tt)*) => {{
+        crate::print!($($arg)*);
+        crate::serial_print!($($arg)*);
+    }};
+}
+
+macro_rules! shell_println {
+    ($($arg:tt)*) => {{
+        crate::println!($($arg)*);
+        crate::serial_println!($($arg)*);
+    }};
+}
+
+/// A built-in command; it gets the words of the line after the command's name
+pub type Command = fn(&[&str]);
+
+/// Every command the shell knows, by name
+pub static COMMANDS: &[(&str, Command)] = &[
+    ("help", help),
+    ("clear", clear),
+    ("echo", echo),
+    ("ticks", ticks),
+    ("meminfo", meminfo),
+    ("lspci", lspci),
+    ("dmesg", |_| klog::dump()),
+    ("reboot", |_| power::reboot()),
+    ("shutdown", |_| power::shutdown()),
+];
+
+fn help(_args: &[&str]) {
+    shell_print!("commands:");
+    for (name, _) in COMMANDS {
+        shell_print!(" {}", name);
+    }
+    shell_println!();
+}
+
+fn clear(_args: &[&str]) {
+    vga_buffer::clear_screen();
+}
+
+fn echo(args: &[&str]) {
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            shell_print!(" ");
+        }
+        shell_print!("{}", arg);
+    }
+    shell_println!();
+}
+
+fn ticks(_args: &[&str]) {
+    shell_println!("{}", time::ticks());
+}
+
+fn meminfo(_args: &[&str]) {
+    allocator::print_stats();
+}
+
+fn lspci(_args: &[&str]) {
+    for device in pci::enumerate() {
+        shell_println!("{}", device);
+        for (i, bar) in device.bars.iter().enumerate().filter(|(_, bar)| **bar != 0) {
+            shell_println!("  bar{} {:#010x}", i, bar);
+        }
+    }
+}
+
+/// Splits the line into words, and runs the command named by the first one with the rest as its
+/// arguments. Returns whether there was such a command; an empty line is fine, too.
+pub fn dispatch(line: &str) -> bool {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let Some((name, args)) = words.split_first() else {
+        return true;
+    };
+    match COMMANDS.iter().find(|(command, _)| command == name) {
+        Some((_, command)) => {
+            command(args);
+            return true;
+        },
+        None => {
+            shell_println!("unknown command: {} (try help)", name);
+            return false;
+        },
+    }
+}
+
+/// What a typed character did to the line, so that the shell knows what to show
+#[derive(Debug, Eq, PartialEq)]
+pub enum Edit {
+    /// The character was added to the line
+    Typed(char),
+    /// The last character of the line was removed
+    Erased,
+    /// The character did nothing, e.g. a backspace on an empty line
+    Ignored,
+    /// The line is done
+    Submitted(String),
+}
+
+/// Collects typed characters into a line. About as minimal as a line editor gets: there is no
+/// cursor to move around, only backspace (or DEL, which is what most terminals send instead).
+pub struct LineEditor {
+    line: String,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        return LineEditor { line: String::new() };
+    }
+
+    pub fn feed(&mut self, c: char) -> Edit {
+        return match c {
+            '\n' | '\r' => Edit::Submitted(core::mem::take(&mut self.line)),
+            '\x08' | '\x7f' => match self.line.pop() {
+                Some(_) => Edit::Erased,
+                None => Edit::Ignored,
+            },
+            c if c.is_control() || self.line.len() >= MAX_LINE_LENGTH => Edit::Ignored,
+            c => {
+                self.line.push(c);
+                Edit::Typed(c)
+            },
+        };
+    }
+}
+
+impl Default for LineEditor {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+/// Waits for the next character from either the keyboard or SERIAL1. Both of them wake us up when
+/// something arrives.
+async fn next_char(keyboard: &mut ScancodeStream) -> char {
+    return poll_fn(|context| {
+        serial::register_rx_waker(context.waker());
+        if let Some(byte) = serial::try_read() {
+            return Poll::Ready(char::from(byte));
+        }
+        // a single key press is several scancodes, so we keep going until one completes a character
+        // or we run out of scancodes
+        while let Poll::Ready(Some(scancode)) = Pin::new(&mut *keyboard).poll_next(context) {
+            if let Some(c) = keyboard::decode(scancode) {
+                return Poll::Ready(c);
+            }
+        }
+        return Poll::Pending;
+    })
+    .await;
+}
+
+/// The shell's read-eval loop: reads a line, runs it, and starts over. Never finishes, so spawn it
+/// as a Task.
+pub async fn run() {
+    let mut keyboard = ScancodeStream::new();
+    let mut editor = LineEditor::new();
+    shell_print!("{}", PROMPT);
+    loop {
+        match editor.feed(next_char(&mut keyboard).await) {
+            Edit::Typed(c) => shell_print!("{}", c),
+            // a backspace erases the character left of the cursor on the screen, but on the host's
+            // terminal, it only moves the cursor back, so the character is blanked explicitly
+            Edit::Erased => {
+                crate::print!("\x08");
+                crate::serial_print!("\x08 \x08");
+            },
+            Edit::Ignored => {},
+            Edit::Submitted(line) => {
+                shell_println!();
+                dispatch(&line);
+                shell_print!("{}", PROMPT);
+            },
+        }
+    }
+}
+
+// The line editor collects the line, including a typo that is erased again, and echo prints its
+// arguments
+#[test_case]
+fn test_echo() {
+    use crate::vga_buffer::{BUFFER_HEIGHT, WRITER};
+
+    let mut editor = LineEditor::new();
+    let mut line = None;
+    for c in "echo hx\x08i\n".chars() {
+        if let Edit::Submitted(submitted) = editor.feed(c) {
+            line = Some(submitted);
+        }
+    }
+    let line = line.unwrap();
+    assert_eq!(line, "echo hi");
+    assert!(dispatch(&line));
+
+    let writer = WRITER.lock();
+    for (i, c) in "hi ".chars().enumerate() {
+        let (character, _, _) = writer.read_char(BUFFER_HEIGHT - 2, i).unwrap();
+        assert_eq!(char::from(character), c);
+    }
+}
@@ -0,0 +1,131 @@
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::{Mutex, MutexGuard};
+
+/// A spin::Mutex that notices when it deadlocks.
+/// A spinlock spins until whoever holds it lets go, which never happens if the one holding it is
+/// the CPU that is spinning, e.g. when an interrupt handler prints while the code it interrupted
+/// holds the WRITER. A plain spin::Mutex just hangs forever in that case, without a word about
+/// why. In debug builds, a SpinLock instead panics with a message saying which lock it was.
+/// To tell the two cases apart, the lock remembers whether this CPU holds it. Only one CPU ever
+/// runs tdos, so that is a single flag for now; with more CPUs, this would need a flag per CPU
+/// (or the id of the CPU holding the lock).
+/// In release builds, the check is skipped, and a SpinLock spins just like a spin::Mutex.
+pub struct SpinLock<T> {
+    inner: Mutex<T>,
+    held_here: AtomicBool,
+}
+
+impl<T> SpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        return SpinLock {
+            inner: Mutex::new(value),
+            held_here: AtomicBool::new(false),
+        };
+    }
+
+    /// Locks the SpinLock, spinning until it is free. In debug builds, this panics instead if this
+    /// CPU holds the lock already, since then it is never going to be free.
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            if cfg!(debug_assertions) && self.held_here.load(Ordering::SeqCst) {
+                // we cannot unwind, so whoever holds the lock never runs again after the panic,
+                // and never unlocks it. Unlocking it here lets the panic handler (and the tests
+                // it runs after that) use it, which matters most if this is SERIAL1.
+                unsafe { self.force_unlock() };
+                panic!(
+                    "deadlock: {} is locked again by the CPU that already holds it",
+                    core::any::type_name::<Self>()
+                );
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Locks the SpinLock if it is free, and returns None otherwise. This never panics, so it is
+    /// what a panic handler should use.
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        // an interrupt between taking the lock and setting the flag would find the lock taken but
+        // not held by this CPU, and spin forever, just like a spin::Mutex would
+        let guard = crate::interrupts::without_interrupts(|| {
+            let guard = self.inner.try_lock()?;
+            self.held_here.store(true, Ordering::SeqCst);
+            return Some(guard);
+        })?;
+        return Some(SpinLockGuard {
+            guard: ManuallyDrop::new(guard),
+            held_here: &self.held_here,
+        });
+    }
+
+    /// Unlocks the SpinLock, no matter who holds it.
+    ///
+    /// # Safety
+    /// Whoever held the lock must never touch the value again, e.g. because they are never going
+    /// to run again after a panic.
+    pub unsafe fn force_unlock(&self) {
+        self.inner.force_unlock();
+        self.held_here.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Gives access to the value behind a SpinLock, and unlocks it when dropped
+pub struct SpinLockGuard<'a, T> {
+    guard: ManuallyDrop<MutexGuard<'a, T>>,
+    held_here: &'a AtomicBool,
+}
+
+impl<T> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        return &self.guard;
+    }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        return &mut self.guard;
+    }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        // unlocking before clearing the flag means that an interrupt in between finds the lock free,
+        // instead of taken but seemingly not by this CPU
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        self.held_here.store(false, Ordering::SeqCst);
+    }
+}
+
+// Locking a free SpinLock works any number of times, and try_lock on a taken one just fails
+#[test_case]
+fn test_lock() {
+    let lock = SpinLock::new(1);
+    *lock.lock() += 1;
+    let mut guard = lock.lock();
+    *guard += 1;
+    assert!(lock.try_lock().is_none());
+    drop(guard);
+    assert_eq!(*lock.try_lock().unwrap(), 3);
+}
+
+#[cfg(debug_assertions)]
+#[test_case]
+const TEST_REENTRANT_LOCK: crate::test_runner::ShouldPanic = crate::test_runner::ShouldPanic {
+    name: concat!(module_path!(), "::test_reentrant_lock"),
+    test: test_reentrant_lock,
+};
+
+// Locking a SpinLock we hold already would spin forever, so it panics instead. If it did spin, the
+// test would time out, which counts as a failure too.
+#[cfg(all(test, debug_assertions))]
+fn test_reentrant_lock() {
+    let lock = SpinLock::new(0);
+    let _guard = lock.lock();
+    let _again = lock.lock();
+}
@@ -0,0 +1,151 @@
+use core::arch::global_asm;
+use x86_64::{PrivilegeLevel, VirtAddr};
+
+use crate::interrupts;
+use crate::qemu::{exit_qemu, QemuExitCode};
+use crate::serial;
+
+/// The interrupt vector for syscalls, the same one that Linux used on 32-bit x86. Later on, the
+/// syscall instruction is going to be the faster way in, but int 0x80 works without any setup
+/// beyond the IDT, and from ring 0 just as well as from ring 3.
+pub const SYSCALL_VECTOR: u8 = 0x80;
+
+/// Syscall numbers, which go into rax. They are the same as on x86_64 Linux, just because those
+/// are as good as any.
+/// write(ptr, len): writes len bytes starting at ptr to SERIAL1, and returns len
+pub const SYS_WRITE: u64 = 1;
+/// exit(code): exits QEMU, with QemuExitCode::Success if the code is 0, and Failed otherwise.
+/// Never returns.
+pub const SYS_EXIT: u64 = 60;
+
+/// What a syscall returns in rax if there is no syscall with its number
+pub const UNKNOWN_SYSCALL: u64 = u64::MAX;
+
+// The entry point for int 0x80. The calling convention follows the one of Linux's syscall
+// instruction: the syscall number goes into rax, the arguments go into rdi and rsi (and rdx, once
+// a syscall needs a third one), and the result comes back in rax. Every other register is left as
+// it was.
+// An extern "x86-interrupt" fn cannot do this, since by the time its body runs, it has long since
+// used rax for something else. So this is a bit of assembly that saves the registers dispatch is
+// allowed to change according to the C calling convention, moves the syscall's registers to where
+// dispatch expects its arguments, and returns to the caller with iretq.
+// The CPU aligns the stack to 16 bytes before pushing its 5 qwords of interrupt stack frame, and
+// we push 8 more, so the stack is off by 8 from the 16 bytes that the calling convention wants at
+// a call, which is what the sub is for. cld is for the same reason: the calling convention says
+// that the direction flag is clear, and the caller might have set it. iretq restores the caller's
+// flags anyway.
+global_asm!(
+    ".global syscall_entry",
+    "syscall_entry:",
+    "push rcx",
+    "push rdx",
+    "push rsi",
+    "push rdi",
+    "push r8",
+    "push r9",
+    "push r10",
+    "push r11",
+    "mov rdx, rsi",
+    "mov rsi, rdi",
+    "mov rdi, rax",
+    "cld",
+    "sub rsp, 8",
+    "call {dispatch}",
+    "add rsp, 8",
+    "pop r11",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop rcx",
+    "iretq",
+    dispatch = sym dispatch,
+);
+
+extern "C" {
+    /// Not a function that can be called, only an address for the IDT, see the assembly above
+    fn syscall_entry();
+}
+
+/// Registers the entry point for int 0x80. Its privilege level is 3, so that user mode code can use
+/// it, which is the whole point of a syscall.
+pub fn init() {
+    // syscall_entry saves every register it touches, and returns with iretq
+    unsafe {
+        interrupts::register_raw(
+            SYSCALL_VECTOR,
+            VirtAddr::new(syscall_entry as unsafe extern "C" fn() as usize as u64),
+            PrivilegeLevel::Ring3,
+        );
+    }
+}
+
+/// Runs the syscall with the number, and returns what goes back into rax. Called by
+/// syscall_entry, with interrupts disabled, since vector 0x80 is an interrupt gate.
+extern "C" fn dispatch(number: u64, arg0: u64, arg1: u64) -> u64 {
+    interrupts::count(SYSCALL_VECTOR);
+    return match number {
+        SYS_WRITE => sys_write(arg0 as *const u8, arg1 as usize),
+        SYS_EXIT => sys_exit(arg0),
+        _ => UNKNOWN_SYSCALL,
+    };
+}
+
+/// Writes the len bytes at ptr to SERIAL1.
+/// NOTE: this trusts the pointer blindly, which is fine as long as only the kernel makes syscalls.
+/// Once user mode code does, it has to check that the bytes lie in memory that code may read, or
+/// any user program could have the kernel print (or fault on) whatever it likes.
+fn sys_write(ptr: *const u8, len: usize) -> u64 {
+    let bytes = unsafe { core::slice::from_raw_parts(ptr, len) };
+    serial::write_bytes(bytes);
+    return len as u64;
+}
+
+/// Exits QEMU. Outside of QEMU, there is nothing to exit to, so we halt instead.
+fn sys_exit(code: u64) -> ! {
+    exit_qemu(if code == 0 {
+        QemuExitCode::Success
+    } else {
+        QemuExitCode::Failed
+    });
+    crate::hlt_loop();
+}
+
+/// Makes a syscall through int 0x80, and returns its result
+///
+/// # Safety
+///
+/// The arguments have to be valid for the syscall, e.g. a pointer and length of readable memory for
+/// SYS_WRITE.
+pub unsafe fn syscall2(number: u64, arg0: u64, arg1: u64) -> u64 {
+    let result: u64;
+    // no nostack: int pushes the interrupt stack frame onto our stack
+    unsafe {
+        core::arch::asm!(
+            "int {vector}",
+            vector = const SYSCALL_VECTOR,
+            inout("rax") number => result,
+            in("rdi") arg0,
+            in("rsi") arg1,
+        );
+    }
+    return result;
+}
+
+// A write goes through the whole round trip: the message shows up in the test's serial output, and
+// the number of bytes written comes back in rax
+#[test_case]
+fn test_sys_write() {
+    let message = b"(written by sys_write) ";
+    let written = unsafe { syscall2(SYS_WRITE, message.as_ptr() as u64, message.len() as u64) };
+    assert_eq!(written, message.len() as u64);
+    assert!(x86_64::instructions::interrupts::are_enabled());
+}
+
+// A number without a syscall just fails, instead of taking the kernel down
+#[test_case]
+fn test_unknown_syscall() {
+    assert_eq!(unsafe { syscall2(12345, 0, 0) }, UNKNOWN_SYSCALL);
+}
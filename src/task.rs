@@ -0,0 +1,271 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::task::{Context, Poll, Waker};
+use crossbeam_queue::ArrayQueue;
+
+/// How many tasks can be woken before the Executor gets to run them, i.e. at most how many tasks
+/// there can be, since each of them is in the ready queue at most once
+const READY_QUEUE_SIZE: usize = 128;
+
+/// Tells the tasks apart, so that a Waker knows which task to wake. Every task gets a new one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct TaskId(u64);
+
+impl TaskId {
+    fn new() -> TaskId {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        return TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    }
+}
+
+/// A unit of work for the Executor: a future that runs until it is done, without a result.
+/// Futures can be moved around until they are first polled, after which they might point into
+/// themselves. So we pin them on the heap, where they never move again.
+pub struct Task {
+    id: TaskId,
+    future: Pin<Box<dyn Future<Output = ()>>>,
+    /// Whether the id is in the Executor's ready queue, so that waking the task again before it
+    /// runs does not queue it a second time. Shared with the task's Waker.
+    queued: Arc<AtomicBool>,
+}
+
+impl Task {
+    pub fn new(future: impl Future<Output = ()> + 'static) -> Task {
+        return Task {
+            id: TaskId::new(),
+            future: Box::pin(future),
+            queued: Arc::new(AtomicBool::new(false)),
+        };
+    }
+
+    fn poll(&mut self, context: &mut Context) -> Poll<()> {
+        return self.future.as_mut().poll(context);
+    }
+}
+
+/// What the Waker of a task does: it puts the task's id into the Executor's ready queue, unless it
+/// is in there already.
+/// Wakers are mostly woken from interrupt handlers, e.g. the keyboard's, which must not allocate,
+/// lock anything the code they interrupted might hold, or panic. Pushing to the ArrayQueue does
+/// none of that.
+struct TaskWaker {
+    id: TaskId,
+    ready: Arc<ArrayQueue<TaskId>>,
+    /// see Task::queued
+    queued: Arc<AtomicBool>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        // every task is in the queue at most once, and there are at most READY_QUEUE_SIZE tasks
+        // (see Executor::spawn), so the push cannot fail. If it did anyway, the task would just
+        // not be woken, which beats panicking in an interrupt handler.
+        if !self.queued.swap(true, Ordering::AcqRel) {
+            let _ = self.ready.push(self.id);
+        }
+    }
+}
+
+/// A single threaded, cooperative executor: it polls one task at a time, and a task runs until it
+/// returns Poll::Pending (i.e. at an .await that cannot go on yet). A task that never awaits
+/// anything keeps every other task from running.
+/// A pending task is only polled again once its Waker is woken, e.g. by the keyboard interrupt
+/// handler when a scancode arrives. While no task is ready, the CPU sleeps with hlt until the next
+/// interrupt, instead of polling tasks that cannot go on anyway, see sleep_if_idle.
+pub struct Executor {
+    tasks: BTreeMap<TaskId, Task>,
+    /// The ids of the tasks that were woken (or just spawned), and need to be polled
+    ready: Arc<ArrayQueue<TaskId>>,
+    /// Every task keeps its Waker, so that it is only created once
+    wakers: BTreeMap<TaskId, Waker>,
+}
+
+impl Executor {
+    pub fn new() -> Executor {
+        return Executor {
+            tasks: BTreeMap::new(),
+            ready: Arc::new(ArrayQueue::new(READY_QUEUE_SIZE)),
+            wakers: BTreeMap::new(),
+        };
+    }
+
+    /// Adds a task for the future. It does not run until run is called. Panics if there are
+    /// READY_QUEUE_SIZE tasks already, since then the ready queue could not hold all of them.
+    pub fn spawn(&mut self, future: impl Future<Output = ()> + 'static) {
+        assert!(self.tasks.len() < READY_QUEUE_SIZE, "the executor has too many tasks");
+        let task = Task::new(future);
+        let id = task.id;
+        task.queued.store(true, Ordering::Release);
+        self.tasks.insert(id, task);
+        self.ready.push(id).expect("the executor's ready queue is full");
+    }
+
+    /// Runs the tasks until all of them are done. Tasks that spawn more tasks are not a thing yet,
+    /// so this returns once the tasks spawned so far are done.
+    pub fn run(&mut self) {
+        while !self.tasks.is_empty() {
+            self.run_ready_tasks();
+            self.sleep_if_idle();
+        }
+    }
+
+    /// Polls every task in the ready queue once. A task that is done is dropped, along with its
+    /// Waker.
+    fn run_ready_tasks(&mut self) {
+        while let Some(id) = self.ready.pop() {
+            let Some(task) = self.tasks.get_mut(&id) else {
+                continue;
+            };
+            // cleared before the poll, so that a wake during the poll queues the task again
+            task.queued.store(false, Ordering::Release);
+            let waker = self.wakers.entry(id).or_insert_with(|| {
+                return Waker::from(Arc::new(TaskWaker {
+                    id,
+                    ready: self.ready.clone(),
+                    queued: task.queued.clone(),
+                }));
+            });
+            let mut context = Context::from_waker(waker);
+            if task.poll(&mut context).is_ready() {
+                self.tasks.remove(&id);
+                self.wakers.remove(&id);
+            }
+        }
+    }
+
+    /// Halts the CPU until the next interrupt if no task is ready. Interrupts are disabled while
+    /// we check, since an interrupt that wakes a task right after the check would otherwise go
+    /// unnoticed until the interrupt after that, which for the keyboard might be a long time.
+    /// enable_and_hlt enables them and halts in one go, so that nothing can arrive in between.
+    fn sleep_if_idle(&self) {
+        use x86_64::instructions::interrupts;
+
+        interrupts::disable();
+        if self.ready.is_empty() {
+            interrupts::enable_and_hlt();
+        } else {
+            interrupts::enable();
+        }
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+/// Returns Pending the first time it is polled, after waking its own task, so that the task goes
+/// back into the ready queue right away
+#[cfg(test)]
+struct YieldOnce(bool);
+
+#[cfg(test)]
+impl Future for YieldOnce {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        context.waker().wake_by_ref();
+        return Poll::Pending;
+    }
+}
+
+// Both tasks run to completion, even though one of them is not done the first time it is polled
+#[test_case]
+fn test_executor() {
+    use alloc::rc::Rc;
+    use core::cell::Cell;
+
+    let counter = Rc::new(Cell::new(0));
+    let mut executor = Executor::new();
+    let first = counter.clone();
+    executor.spawn(async move {
+        YieldOnce(false).await;
+        first.set(first.get() + 1);
+    });
+    let second = counter.clone();
+    executor.spawn(async move {
+        second.set(second.get() + 1);
+    });
+    executor.run();
+    assert_eq!(counter.get(), 2);
+}
+
+// A task that is not ready is not polled again until it is woken, no matter how often the other
+// tasks run in the meantime
+#[test_case]
+fn test_executor_waits_for_wake() {
+    use alloc::rc::Rc;
+    use core::cell::{Cell, RefCell};
+
+    let polls = Rc::new(Cell::new(0));
+    let woken = Rc::new(Cell::new(false));
+    let waker: Rc<RefCell<Option<Waker>>> = Rc::new(RefCell::new(None));
+    let mut executor = Executor::new();
+    let (task_polls, task_woken, task_waker) = (polls.clone(), woken.clone(), waker.clone());
+    executor.spawn(core::future::poll_fn(move |context| {
+        task_polls.set(task_polls.get() + 1);
+        if task_woken.get() {
+            return Poll::Ready(());
+        }
+        *task_waker.borrow_mut() = Some(context.waker().clone());
+        return Poll::Pending;
+    }));
+    executor.spawn(async move {
+        for _ in 0..3 {
+            YieldOnce(false).await;
+        }
+        woken.set(true);
+        waker
+            .borrow_mut()
+            .take()
+            .expect("the first task did not register its waker")
+            .wake();
+    });
+    executor.run();
+    assert_eq!(polls.get(), 2);
+}
+
+// Waking a task over and over before it runs queues it once, instead of filling up the ready queue
+#[test_case]
+fn test_repeated_wakes_queue_once() {
+    use alloc::rc::Rc;
+    use core::cell::{Cell, RefCell};
+
+    let polls = Rc::new(Cell::new(0));
+    let waker: Rc<RefCell<Option<Waker>>> = Rc::new(RefCell::new(None));
+    let mut executor = Executor::new();
+    let (task_polls, task_waker) = (polls.clone(), waker.clone());
+    executor.spawn(core::future::poll_fn(move |context| {
+        task_polls.set(task_polls.get() + 1);
+        if task_polls.get() > 1 {
+            return Poll::Ready(());
+        }
+        *task_waker.borrow_mut() = Some(context.waker().clone());
+        return Poll::Pending;
+    }));
+    executor.spawn(async move {
+        let waker = waker
+            .borrow_mut()
+            .take()
+            .expect("the first task did not register its waker");
+        for _ in 0..READY_QUEUE_SIZE * 2 {
+            waker.wake_by_ref();
+        }
+    });
+    executor.run();
+    assert_eq!(polls.get(), 2);
+}
@@ -0,0 +1,79 @@
+use core::fmt;
+use uart_16550::SerialPort;
+
+use crate::serial::{self, SERIAL1};
+use crate::vga_buffer::{self, Writer};
+
+/// Like print!, but to SERIAL1 as well, e.g. for boot messages that should be on the screen and in
+/// the host's log.
+#[macro_export]
+macro_rules! tee_print {
+    ($($arg:tt)*) => ($crate::tee::_print(format_args!($($arg)*)));
+}
+
+/// see tee_print!
+#[macro_export]
+macro_rules! tee_println {
+    () => ($crate::tee_print!("\n"));
+    ($($arg:tt)*) => ($crate::tee_print!("{}\n", format_args!($($arg)*)));
+}
+
+/// Writes everything to both the screen and SERIAL1, see with_tee
+pub struct Tee<'a> {
+    writer: &'a mut Writer,
+    // None if serial output is turned off, see serial::set_enabled
+    serial: Option<&'a mut SerialPort>,
+}
+
+impl fmt::Write for Tee<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.writer.write_str(s)?;
+        if let Some(serial) = &mut self.serial {
+            serial.write_str(s)?;
+        }
+        return Ok(());
+    }
+}
+
+/// Locks the WRITER and SERIAL1 with interrupts disabled, and runs f with a Tee to both of them.
+/// Both stay locked until f returns, so that nobody can print into the middle of what f writes,
+/// on either of them. Like vga_buffer::screenshot, this always locks the WRITER first, and
+/// SERIAL1 second: with interrupts disabled, nobody can take SERIAL1 from under us in between, and
+/// as long as everybody who needs both takes them in this order, nobody can end up holding SERIAL1
+/// while waiting for the WRITER we hold.
+pub fn with_tee<R>(f: impl FnOnce(&mut Tee) -> R) -> R {
+    return vga_buffer::with_writer(|writer| {
+        let mut serial = serial::enabled().then(|| SERIAL1.lock());
+        let mut tee = Tee {
+            writer,
+            serial: serial.as_deref_mut(),
+        };
+        return f(&mut tee);
+    });
+}
+
+/// _print for tee_print!. Without text mode, print! already sends its output somewhere else (see
+/// vga_buffer::text_mode): to SERIAL1 itself, unless there is a graphical console, in which case
+/// SERIAL1 still needs its own copy.
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    use core::fmt::Write;
+    if !vga_buffer::text_mode() {
+        crate::print!("{}", args);
+        if crate::gfx_console::CONSOLE.get().is_some() {
+            serial::write_fmt(args);
+        }
+        return;
+    }
+    with_tee(|tee| tee.write_fmt(args).unwrap());
+}
+
+// A tee_println! line ends up on the screen, and on SERIAL1, which we cannot read back, but which
+// shows up in the test's output
+#[test_case]
+fn test_tee_println() {
+    use crate::vga_buffer::BUFFER_HEIGHT;
+
+    crate::tee_println!("({} on the screen and on serial) ", "tee_println");
+    crate::assert_screen_contains!(BUFFER_HEIGHT - 2, "(tee_println on the screen and on serial)");
+}
@@ -1,10 +1,39 @@
+use core::fmt;
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use spin::{Mutex, Once};
 
+use crate::fmt_buffer::FmtBuffer;
 use crate::qemu::{exit_qemu, QemuExitCode};
-use crate::{serial_print, serial_println};
+use crate::time;
+use crate::{serial_eprintln, serial_print, serial_println};
+
+/// How many seconds a single test may take before it counts as hanging
+pub const TEST_TIMEOUT_SECONDS: u64 = 30;
+
+/// Only tests whose name contains this string are run, e.g. `TEST_FILTER=vga_buffer cargo test`
+/// only runs the tests in the vga_buffer module. This is read at compile time, since there is no
+/// way to pass arguments to the kernel at run time (yet).
+pub const TEST_FILTER: Option<&str> = option_env!("TEST_FILTER");
+
+/// Whether the test run goes on after a failed test, like with test_runner_continue, e.g.
+/// `TEST_CONTINUE=1 cargo test`. Like TEST_FILTER, this is read at compile time.
+pub const TEST_CONTINUE: bool = option_env!("TEST_CONTINUE").is_some();
+
+// Test results in green and red, using ANSI escape codes that the host's terminal understands
+const OK_MARK: &str = "\x1b[32m[ok]\x1b[0m";
+const FAILED_MARK: &str = "\x1b[31m[failed]\x1b[0m";
 
 pub trait Testable {
     fn run(&self) -> ();
+
+    /// The name printed in the test output
+    fn name(&self) -> &'static str;
+
+    /// Whether this test passes by panicking, see ShouldPanic
+    fn should_panic(&self) -> bool {
+        return false;
+    }
 }
 
 impl<T> Testable for T
@@ -12,29 +41,514 @@ where
     T: Fn(),
 {
     fn run(&self) {
-        // Prints the type name, because for functions the function name IS the type name, so this
-        // way we get the name of function we are testing in our test output.
-        serial_print!("{}...\t", core::any::type_name::<T>());
+        serial_print!("{}...\t", self.name());
         self();
-        serial_println!("[ok]");
+        serial_println!("{}", OK_MARK);
+    }
+
+    // Uses the type name, because for functions the function name IS the type name, so this way we
+    // get the name of function we are testing in our test output.
+    fn name(&self) -> &'static str {
+        return core::any::type_name::<T>();
+    }
+}
+
+/// Set while a ShouldPanic test runs, so that the panic handler knows that the panic means the
+/// test passed.
+static EXPECTING_PANIC: AtomicBool = AtomicBool::new(false);
+
+/// A test that passes if, and only if, it panics.
+/// Since we cannot unwind, there is no way to get back into the test after a panic; instead, the
+/// panic handler reports the result and runs the remaining tests itself, see test_panic_handler.
+///
+/// The name is needed, because we cannot use the type name of a fn pointer like we do for the
+/// other tests. Use it like this:
+///
+/// ```ignore
+/// #[test_case]
+/// const MY_TEST: ShouldPanic = ShouldPanic {
+///     name: concat!(module_path!(), "::my_test"),
+///     test: my_test,
+/// };
+/// ```
+pub struct ShouldPanic {
+    pub name: &'static str,
+    pub test: fn(),
+}
+
+impl Testable for ShouldPanic {
+    fn run(&self) {
+        serial_print!("{}...\t", self.name());
+        EXPECTING_PANIC.store(true, Ordering::SeqCst);
+        (self.test)();
+        // if we get here, the test did not panic, which we report like any other failure
+        EXPECTING_PANIC.store(false, Ordering::SeqCst);
+        panic!("test did not panic");
+    }
+
+    fn name(&self) -> &'static str {
+        return self.name;
+    }
+
+    fn should_panic(&self) -> bool {
+        return true;
+    }
+}
+
+/// The tick count at which the currently running test times out, or 0 if no test is running
+static DEADLINE: AtomicU64 = AtomicU64::new(0);
+
+/// Name of the currently running test, for the timeout report.
+/// The timer interrupt handler reads this, so it must only be locked with interrupts disabled.
+static CURRENT_TEST: Mutex<&'static str> = Mutex::new("");
+
+/// Set once a test timed out
+static TIMED_OUT: AtomicBool = AtomicBool::new(false);
+
+/// Runs a single test with a deadline of TEST_TIMEOUT_SECONDS, see check_timeout.
+/// The deadline is in ticks at the timer's rate when the test starts; a test that changes the rate
+/// (see time::set_frequency) moves its own deadline.
+fn run_with_timeout(test: &dyn Testable) {
+    crate::interrupts::without_interrupts(|| *CURRENT_TEST.lock() = test.name());
+    let timeout_ticks = TEST_TIMEOUT_SECONDS * u64::from(time::tick_hz());
+    DEADLINE.store(time::ticks() + timeout_ticks, Ordering::SeqCst);
+    test.run();
+    DEADLINE.store(0, Ordering::SeqCst);
+}
+
+/// Called by the timer interrupt handler: if the currently running test has passed its deadline,
+/// this reports the test as timed out and panics, which fails the test run.
+/// Since we only have a single thread, a hanging test never gives control back to the test runner,
+/// which is why this has to happen in the timer interrupt. Note that this can only catch tests
+/// that hang with interrupts enabled, and only after the timer interrupt has been set up (i.e.
+/// after crate::init).
+#[doc(hidden)]
+pub fn check_timeout() {
+    let deadline = DEADLINE.load(Ordering::SeqCst);
+    if deadline == 0 || time::ticks() < deadline {
+        return;
+    }
+    DEADLINE.store(0, Ordering::SeqCst);
+    TIMED_OUT.store(true, Ordering::SeqCst);
+    // a test that hangs is never a test that passed by panicking
+    EXPECTING_PANIC.store(false, Ordering::SeqCst);
+
+    // the test we interrupted might be holding SERIAL1 or SERIAL2, and it is never going to release
+    // them again
+    unsafe {
+        crate::serial::SERIAL1.force_unlock();
+        crate::serial::SERIAL2.force_unlock();
+    }
+    // nobody else locks CURRENT_TEST while interrupts are enabled, so this cannot fail
+    let name = *CURRENT_TEST.try_lock().expect("CURRENT_TEST is locked");
+    serial_println!("[timeout] {}", name);
+    panic!("test {} timed out after {} seconds", name, TEST_TIMEOUT_SECONDS);
+}
+
+/// Returns whether a test timed out, so that a test's panic handler can tell a timeout apart from
+/// other panics
+pub fn timed_out() -> bool {
+    return TIMED_OUT.load(Ordering::SeqCst);
+}
+
+/// Number of tests that passed, failed, and were skipped because of the TEST_FILTER (or the
+/// test selection, see select_tests) so far
+static PASSED: AtomicUsize = AtomicUsize::new(0);
+static FAILED: AtomicUsize = AtomicUsize::new(0);
+static SKIPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether the test run goes on after a failed test, see test_runner_continue
+static CONTINUE_AFTER_FAILURE: AtomicBool = AtomicBool::new(false);
+
+/// How many failed tests the end of the test run lists by name. Tests might run before there is a
+/// heap, so the names go into an array of fixed size, and any failures beyond that are only
+/// counted.
+const MAX_LISTED_FAILURES: usize = 32;
+
+/// The names of the tests that failed so far, in the order they failed. Only the first
+/// FAILED.min(MAX_LISTED_FAILURES) of them are set.
+static FAILED_TESTS: Mutex<[&str; MAX_LISTED_FAILURES]> = Mutex::new([""; MAX_LISTED_FAILURES]);
+
+// The tests of the current run, and the index of the next test to run. The panic handler needs
+// these to resume the test run, so they have to live in statics.
+// Note that the slice of tests actually lives on the stack of the function that called the test
+// runner. This is fine though, since that function never returns, and the panic handler runs on
+// top of that stack.
+static TESTS: AtomicPtr<&dyn Testable> = AtomicPtr::new(core::ptr::null_mut());
+static TEST_COUNT: AtomicUsize = AtomicUsize::new(0);
+static NEXT_TEST: AtomicUsize = AtomicUsize::new(0);
+
+/// The final line of a test run, e.g. "42 passed; 0 failed; 3 skipped"
+pub struct Summary {
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(
+            f,
+            "{} passed; {} failed; {} skipped",
+            self.passed, self.failed, self.skipped
+        );
+    }
+}
+
+/// Returns whether the test with this name passes the filter, i.e. whether it should run
+fn matches_filter(name: &str, filter: Option<&str>) -> bool {
+    return match filter {
+        Some(filter) => name.contains(filter),
+        None => true,
+    };
+}
+
+/// How many bytes of the answer to the test selection prompt we keep, see select_tests
+const SELECTION_SIZE: usize = 128;
+
+/// The answer to the test selection prompt, i.e. the numbers of the tests to run, or nothing to run
+/// all of them. Like TESTS, this has to live in a static for the panic handler.
+static SELECTION: Mutex<FmtBuffer<SELECTION_SIZE>> = Mutex::new(FmtBuffer::new());
+
+/// Parses the answer to the test selection prompt, e.g. "2 5,7", into the indices of those tests.
+/// The numbers start at 1, like in the list the prompt prints, so "2" is the test at index 1. A
+/// number that is not in the list comes out as None.
+fn parse_selection(line: &str, count: usize) -> impl Iterator<Item = Option<usize>> + '_ {
+    return line
+        .split(|c: char| c == ',' || c.is_ascii_whitespace())
+        .filter(|number| !number.is_empty())
+        .map(move |number| match number.parse::<usize>() {
+            Ok(number) if (1..=count).contains(&number) => Some(number - 1),
+            _ => None,
+        });
+}
+
+/// Returns whether the test at the index was selected at the prompt; if nothing was, every test is
+fn selected(index: usize) -> bool {
+    let selection = SELECTION.lock();
+    let mut indices = parse_selection(selection.as_str(), usize::MAX).peekable();
+    return indices.peek().is_none() || indices.any(|i| i == Some(index));
+}
+
+/// Prints the numbered list of tests over serial, and asks which of them to run, until the answer
+/// only contains numbers from the list. This waits for somebody to type on the host, which is why
+/// it only happens with the select-tests feature, and never in CI.
+fn select_tests(tests: &[&dyn Testable]) {
+    for (i, test) in tests.iter().enumerate() {
+        serial_println!("{:>4} {}", i + 1, test.name());
+    }
+    loop {
+        serial_print!("tests to run (e.g. \"2 5,7\", or nothing for all): ");
+        let mut line = [0; SELECTION_SIZE];
+        let len = crate::serial::read_line(&mut line);
+        let Ok(line) = core::str::from_utf8(&line[..len]) else {
+            serial_println!("that is not text, try again");
+            continue;
+        };
+        if parse_selection(line, tests.len()).any(|index| index.is_none()) {
+            serial_println!("only numbers from 1 to {}, try again", tests.len());
+            continue;
+        }
+        let mut selection = SELECTION.lock();
+        *selection = FmtBuffer::new();
+        // the line fits into the buffer, since both are SELECTION_SIZE bytes
+        let _ = fmt::Write::write_str(&mut *selection, line);
+        return;
     }
 }
 
 /// Custom test runner. Simply taskes the list of test functions collected, prints how many tests
 /// its running, and then calls all tests sequentially.
+/// The first failing test ends the test run, unless TEST_CONTINUE is set; see test_runner_continue
+/// for running every test.
+/// With the select-tests feature, it first asks over serial which tests to run, see select_tests.
 #[allow(dead_code)] // this code is only really used in tests, so cargo complains about dead code
                     // for non-test binaries
 pub fn test_runner(tests: &[&dyn Testable]) {
+    if TEST_CONTINUE {
+        CONTINUE_AFTER_FAILURE.store(true, Ordering::SeqCst);
+    }
+    if cfg!(feature = "select-tests") {
+        select_tests(tests);
+    }
     serial_println!("Running {} tests", tests.len());
-    for test in tests {
-        test.run();
+    TESTS.store(tests.as_ptr() as *mut _, Ordering::SeqCst);
+    TEST_COUNT.store(tests.len(), Ordering::SeqCst);
+    run_remaining_tests();
+}
+
+/// Returns whether a test run is in progress, i.e. whether we are a test binary that called
+/// test_runner, rather than the kernel or an integration test without the test runner
+pub fn running() -> bool {
+    return !TESTS.load(Ordering::SeqCst).is_null();
+}
+
+/// Like test_runner, but runs every test even if some of them fail, and lists all of the failed
+/// tests at the end. Use it with #![test_runner(tdos::test_runner::test_runner_continue)], or set
+/// TEST_CONTINUE to get the same for every test binary.
+/// Since a test fails by panicking, and we cannot unwind, whatever the failing test was doing is
+/// simply abandoned. Every lock it held stays locked, and every test after it that needs that lock
+/// hangs until it times out. The same goes for any other global state it was in the middle of
+/// changing, e.g. a half written screen or a color it never restored, so a failure can poison the
+/// tests after it, and only the first failure is guaranteed to be genuine. A test that times out
+/// always ends the test run, since we cannot resume from inside of the timer interrupt.
+pub fn test_runner_continue(tests: &[&dyn Testable]) {
+    CONTINUE_AFTER_FAILURE.store(true, Ordering::SeqCst);
+    test_runner(tests);
+}
+
+/// Runs every test that has not run yet, then prints the summary and exits QEMU
+fn run_remaining_tests() -> ! {
+    let tests = unsafe {
+        // see TESTS for why this is fine
+        let tests = TESTS.load(Ordering::SeqCst);
+        if tests.is_null() {
+            &[]
+        } else {
+            core::slice::from_raw_parts(tests, TEST_COUNT.load(Ordering::SeqCst))
+        }
+    };
+    loop {
+        let next = NEXT_TEST.fetch_add(1, Ordering::SeqCst);
+        if next >= tests.len() {
+            break;
+        }
+        if !matches_filter(tests[next].name(), TEST_FILTER) || !selected(next) {
+            serial_println!("{}...\t[skipped]", tests[next].name());
+            SKIPPED.fetch_add(1, Ordering::SeqCst);
+            continue;
+        }
+        run_with_timeout(tests[next]);
+        PASSED.fetch_add(1, Ordering::SeqCst);
+    }
+    finish();
+}
+
+/// Decides the exit code at the end of the test run, see set_finish_hook
+static FINISH_HOOK: Once<fn(&Summary, QemuExitCode) -> QemuExitCode> = Once::new();
+
+/// Makes the end of the test run call hook with the summary and the exit code the test run would
+/// exit with, after printing both the failures and the summary, and exit with whatever exit code
+/// the hook returns instead. This is for test binaries whose tests fail on purpose, e.g. to check
+/// how failures are reported: the hook checks that the right tests failed, and turns the expected
+/// QemuExitCode::Failed into QemuExitCode::Success. Only the first call has an effect.
+pub fn set_finish_hook(hook: fn(&Summary, QemuExitCode) -> QemuExitCode) {
+    FINISH_HOOK.call_once(|| hook);
+}
+
+/// Prints the summary and exits QEMU
+fn finish() -> ! {
+    let summary = Summary {
+        passed: PASSED.load(Ordering::SeqCst),
+        failed: FAILED.load(Ordering::SeqCst),
+        skipped: SKIPPED.load(Ordering::SeqCst),
+    };
+    if summary.failed > 0 {
+        serial_println!("failures:");
+        for name in failed_tests() {
+            serial_println!("    {}", name);
+        }
+        if summary.failed > MAX_LISTED_FAILURES {
+            serial_println!("    ... and {} more", summary.failed - MAX_LISTED_FAILURES);
+        }
     }
-    exit_qemu(QemuExitCode::Success);
+    serial_println!("{}", summary);
+    let exit_code = failure_exit_code().unwrap_or(if summary.failed == 0 {
+        QemuExitCode::Success
+    } else {
+        QemuExitCode::Failed
+    });
+    exit_qemu(match FINISH_HOOK.r#try() {
+        Some(hook) => hook(&summary, exit_code),
+        None => exit_code,
+    });
+    crate::hlt_loop();
+}
+
+/// Counts the currently running test as failed, and remembers its name for the end of the test run
+fn record_failure() {
+    let index = FAILED.fetch_add(1, Ordering::SeqCst);
+    if index >= MAX_LISTED_FAILURES {
+        return;
+    }
+    // see CURRENT_TEST for why interrupts are disabled. We might have panicked while holding
+    // either of the locks, so we must not wait for them.
+    crate::interrupts::without_interrupts(|| {
+        let name = CURRENT_TEST.try_lock().map_or("<unknown test>", |name| *name);
+        if let Some(mut failed_tests) = FAILED_TESTS.try_lock() {
+            failed_tests[index] = name;
+        }
+    });
+}
+
+/// Returns the names of the tests that failed so far, in the order they failed, but at most
+/// MAX_LISTED_FAILURES of them
+pub fn failed_tests() -> impl Iterator<Item = &'static str> {
+    let failed_tests = *FAILED_TESTS.lock();
+    let count = FAILED.load(Ordering::SeqCst).min(MAX_LISTED_FAILURES);
+    return failed_tests.into_iter().take(count);
 }
 
+/// Returns the exit code for the failures that are worse than a failed assertion, so that the host
+/// can tell them apart: a test timed out, or we ran out of memory.
+fn failure_exit_code() -> Option<QemuExitCode> {
+    if timed_out() {
+        return Some(QemuExitCode::Timeout);
+    }
+    if crate::allocator::out_of_memory() {
+        return Some(QemuExitCode::OutOfMemory);
+    }
+    return None;
+}
+
+/// Reports a panic during a test. The result goes to SERIAL1 like every other result, but the panic
+/// message goes to the error stream, see serial_eprint!. If a ShouldPanic test is running, the
+/// panic means the test passed, otherwise the test failed, which ends the test run unless we were
+/// asked to continue.
+/// Either way, we cannot get back into the test runner, so this runs the remaining tests itself.
+/// Every test that panics adds the panic handler's stack frame on top of the stack, which is fine
+/// as long as there are not thousands of them.
+/// A panic outside of any test (e.g. in an integration test without the test runner) ends the test
+/// run right away, with QemuExitCode::Panic.
 pub fn test_panic_handler(info: &PanicInfo) -> ! {
-    serial_println!("[failed]\n");
-    serial_println!("Error: {}\n", info);
-    exit_qemu(QemuExitCode::Failed);
-    loop {}
+    // check_timeout resets the deadline before it panics, so a timed out test counts as running
+    let in_test = DEADLINE.swap(0, Ordering::SeqCst) != 0 || timed_out();
+    if !in_test {
+        serial_println!("{}\n", FAILED_MARK);
+        serial_eprintln!("{}", crate::format_panic(info));
+        exit_qemu(failure_exit_code().unwrap_or(QemuExitCode::Panic));
+        crate::hlt_loop();
+    }
+    if EXPECTING_PANIC.swap(false, Ordering::SeqCst) {
+        serial_println!("{}", OK_MARK);
+        PASSED.fetch_add(1, Ordering::SeqCst);
+    } else {
+        serial_println!("{}\n", FAILED_MARK);
+        serial_eprintln!("{}", crate::format_panic(info));
+        record_failure();
+        if !CONTINUE_AFTER_FAILURE.load(Ordering::SeqCst) || timed_out() {
+            finish();
+        }
+    }
+    run_remaining_tests();
+}
+
+/// Asserts that the given row of the screen contains the text somewhere, e.g.
+/// `assert_screen_contains!(BUFFER_HEIGHT - 2, "Welcome to tdos!")` after printing that line,
+/// instead of comparing the row cell by cell with read_char. The text is translated to code page
+/// 437 just like printing it would, so it may contain non-ASCII characters. If the text is not
+/// there, the panic message shows the whole row, so the test's output on the serial port shows
+/// what was there instead.
+/// This locks the WRITER, so it must not be held while using the macro.
+#[macro_export]
+macro_rules! assert_screen_contains {
+    ($row:expr, $text:expr) => {
+        $crate::test_runner::_assert_screen_contains($row, $text)
+    };
+}
+
+/// The implementation of assert_screen_contains!. It tracks the caller, so that a failure points
+/// at the test using the macro instead of at this function.
+#[doc(hidden)]
+#[track_caller]
+pub fn _assert_screen_contains(row: usize, text: &str) {
+    use crate::vga_buffer::{cp437_to_ascii, unicode_to_cp437, BUFFER_HEIGHT, BUFFER_WIDTH, WRITER};
+
+    assert!(row < BUFFER_HEIGHT, "row {} is not on the screen", row);
+    let mut line = [0; BUFFER_WIDTH];
+    {
+        let writer = WRITER.lock();
+        for (col, byte) in line.iter_mut().enumerate() {
+            // the row and column are both on the screen, so there is always a character
+            *byte = writer.read_char(row, col).unwrap().0;
+        }
+    }
+
+    let len = text.chars().count();
+    let mut needle = [0; BUFFER_WIDTH];
+    for (byte, c) in needle.iter_mut().zip(text.chars()) {
+        *byte = unicode_to_cp437(c);
+    }
+    let found = len == 0 || (len <= BUFFER_WIDTH && line.windows(len).any(|window| window == &needle[..len]));
+    if !found {
+        let actual = line.map(|byte| cp437_to_ascii(byte) as u8);
+        panic!(
+            "row {} does not contain {:?}, it reads:\n{}",
+            row,
+            text,
+            // cp437_to_ascii only returns ASCII
+            core::str::from_utf8(&actual).unwrap()
+        );
+    }
+}
+
+#[test_case]
+const TEST_SHOULD_PANIC: ShouldPanic = ShouldPanic {
+    name: concat!(module_path!(), "::test_should_panic"),
+    test: test_should_panic,
+};
+
+// Example for a test that should panic; see ShouldPanic
+#[cfg(test)]
+fn test_should_panic() {
+    assert_eq!(0, 1);
+}
+
+#[test_case]
+fn test_summary() {
+    use alloc::format;
+    let summary = Summary {
+        passed: 42,
+        failed: 0,
+        skipped: 3,
+    };
+    assert_eq!(format!("{}", summary), "42 passed; 0 failed; 3 skipped");
+}
+
+#[test_case]
+fn test_matches_filter() {
+    let name = "tdos::vga_buffer::test_println_simple";
+    assert!(matches_filter(name, None));
+    assert!(matches_filter(name, Some("vga_buffer")));
+    assert!(matches_filter(name, Some("")));
+    assert!(!matches_filter(name, Some("serial")));
+    assert!(!matches_filter(name, Some("VGA_BUFFER")));
+}
+
+// The numbers in the answer start at 1, and anything that is not a test's number is rejected
+#[test_case]
+fn test_parse_selection() {
+    use alloc::vec::Vec;
+
+    let parse = |line| parse_selection(line, 5).collect::<Vec<_>>();
+    assert_eq!(parse("2"), [Some(1)]);
+    assert_eq!(parse(" 1, 5 3"), [Some(0), Some(4), Some(2)]);
+    assert!(parse("").is_empty());
+    assert_eq!(parse("0 6 x"), [None, None, None]);
+}
+
+// A line we just printed is found, including its non-ASCII characters, and so is any part of it
+#[test_case]
+fn test_assert_screen_contains() {
+    use crate::vga_buffer::BUFFER_HEIGHT;
+
+    crate::println!("assert_screen_contains: Grüße");
+    crate::assert_screen_contains!(BUFFER_HEIGHT - 2, "assert_screen_contains: Grüße");
+    crate::assert_screen_contains!(BUFFER_HEIGHT - 2, "Grü");
+    crate::assert_screen_contains!(BUFFER_HEIGHT - 2, "");
+}
+
+#[test_case]
+const TEST_SCREEN_DOES_NOT_CONTAIN: ShouldPanic = ShouldPanic {
+    name: concat!(module_path!(), "::test_screen_does_not_contain"),
+    test: test_screen_does_not_contain,
+};
+
+// No row can contain more characters than fit into a row
+#[cfg(test)]
+fn test_screen_does_not_contain() {
+    use crate::vga_buffer::BUFFER_HEIGHT;
+
+    let too_long = [b'x'; crate::vga_buffer::BUFFER_WIDTH + 1];
+    crate::assert_screen_contains!(BUFFER_HEIGHT - 2, core::str::from_utf8(&too_long).unwrap());
 }
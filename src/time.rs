@@ -0,0 +1,174 @@
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use x86_64::instructions::port::Port;
+use x86_64::structures::idt::InterruptStackFrame;
+
+use crate::interrupts;
+use crate::pic::{self, InterruptIndex};
+
+// Number of timer interrupts since interrupts were enabled. This is an atomic, because the timer
+// interrupt handler increments it while anything else might be reading it. We specifically need a
+// 64 bit atomic: reading a plain u64 might happen in two halves, and an interrupt in between would
+// let us read a torn value.
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// The frequency the PIT counts down at, in Hz. This goes all the way back to the original IBM PC,
+/// whose 14.31818 MHz crystal was divided by 12.
+pub const PIT_FREQUENCY: u32 = 1_193_182;
+
+/// IO ports of the PIT's command register, and of its channel 0, which is the one that drives the
+/// timer interrupt
+const PIT_COMMAND_PORT: u16 = 0x43;
+const PIT_CHANNEL0_PORT: u16 = 0x40;
+/// Select channel 0, send the divisor as low byte then high byte, mode 3 (a square wave, i.e. it
+/// fires periodically), and count in binary
+const PIT_CHANNEL0_PERIODIC: u8 = 0b0011_0110;
+
+/// How often the timer fires if nobody sets its frequency: the PIT's largest divisor, 65536, gives
+/// about 18.2 Hz
+pub const DEFAULT_TICK_HZ: u32 = 18;
+/// How often the timer fires once init set it up. Fast enough to sleep for a few milliseconds, and
+/// slow enough not to keep the CPU busy with interrupts.
+const INIT_TICK_HZ: u32 = 100;
+
+/// How often the timer fires, i.e. how many ticks there are per second, see set_frequency
+static TICK_HZ: AtomicU32 = AtomicU32::new(DEFAULT_TICK_HZ);
+
+/// Registers the timer interrupt handler, and makes the timer fire INIT_TICK_HZ times per second
+pub fn init() {
+    interrupts::register(InterruptIndex::Timer.as_u8(), timer_interrupt_handler);
+    set_frequency(INIT_TICK_HZ);
+}
+
+/// Returns how often the timer fires, i.e. how many ticks there are per second
+pub fn tick_hz() -> u32 {
+    return TICK_HZ.load(Ordering::Relaxed);
+}
+
+/// Returns the divisor that makes the PIT fire at hz. The PIT takes a 16 bit divisor, so hz must be
+/// at least 19, and at most PIT_FREQUENCY.
+fn divisor_for(hz: u32) -> u16 {
+    assert!(hz > 0, "the timer cannot fire at 0 Hz");
+    let divisor = PIT_FREQUENCY / hz;
+    assert!(
+        (1..=u32::from(u16::MAX)).contains(&divisor),
+        "the timer cannot fire at {} Hz, since its divisor {} does not fit into 16 bits",
+        hz,
+        divisor
+    );
+    return divisor as u16;
+}
+
+/// The (port, value) writes that program the PIT's channel 0 with the divisor, in order: first the
+/// command that says what comes next, then the low byte of the divisor, then the high byte
+fn pit_writes(divisor: u16) -> [(u16, u8); 3] {
+    let [low, high] = divisor.to_le_bytes();
+    return [
+        (PIT_COMMAND_PORT, PIT_CHANNEL0_PERIODIC),
+        (PIT_CHANNEL0_PORT, low),
+        (PIT_CHANNEL0_PORT, high),
+    ];
+}
+
+/// Makes the timer fire hz times per second. The PIT can only divide its own frequency by a whole
+/// number, so the actual rate is a bit off, e.g. 1000.15 Hz for 1000 Hz. Panics if hz is out of
+/// range, see divisor_for.
+/// Note that ticks that were counted at the old rate stay as they are, so tick counts from before
+/// and after the change cannot be compared.
+pub fn set_frequency(hz: u32) {
+    let divisor = divisor_for(hz);
+    // the PIT must not be interrupted between the command and the divisor
+    interrupts::without_interrupts(|| {
+        for (port, value) in pit_writes(divisor) {
+            unsafe { Port::<u8>::new(port).write(value) };
+        }
+        TICK_HZ.store(hz, Ordering::Relaxed);
+    });
+}
+
+/// The timer fires periodically, and every time it does, we count a tick, turn the heartbeat (see
+/// vga_buffer::set_heartbeat), and check whether a running test hangs. Note that forgetting the
+/// end of interrupt notification here would stop the timer (and every other hardware interrupt of
+/// lower priority, which is all of them) from ever firing again.
+extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    interrupts::count(InterruptIndex::Timer.as_u8());
+    let ticks = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+    crate::vga_buffer::heartbeat(ticks);
+    crate::test_runner::check_timeout();
+    pic::notify_end_of_interrupt(InterruptIndex::Timer);
+}
+
+/// Returns the number of timer interrupts since interrupts were enabled
+pub fn ticks() -> u64 {
+    return TICKS.load(Ordering::Relaxed);
+}
+
+/// Waits until the timer interrupt fired n more times.
+/// Note that this spins forever if interrupts are disabled.
+pub fn sleep_ticks(n: u64) {
+    let start = ticks();
+    while ticks() - start < n {
+        core::hint::spin_loop();
+    }
+}
+
+/// Returns how many ticks make up at least ms milliseconds at the current tick_hz, rounded up, so
+/// that a short sleep still waits for a tick instead of not at all
+pub fn ms_to_ticks(ms: u64) -> u64 {
+    return ms.saturating_mul(u64::from(tick_hz())).div_ceil(1000);
+}
+
+/// Waits for (about) ms milliseconds, by waiting for ms_to_ticks(ms) timer interrupts. Between the
+/// interrupts, the CPU sleeps in hlt instead of spinning.
+/// Note that the first tick might come right after we start waiting, so this can return up to a
+/// tick early, i.e. 10 ms at 100 Hz. Like sleep_ticks, this hangs forever if interrupts are
+/// disabled, since then hlt never wakes up (and the ticks never advance).
+pub fn sleep_ms(ms: u64) {
+    debug_assert!(
+        x86_64::instructions::interrupts::are_enabled(),
+        "sleep_ms with interrupts disabled would never return"
+    );
+    let n = ms_to_ticks(ms);
+    let start = ticks();
+    while ticks() - start < n {
+        x86_64::instructions::hlt();
+    }
+}
+
+// Test that the timer interrupt actually advances the tick counter
+#[test_case]
+fn test_sleep_ticks() {
+    let start = ticks();
+    sleep_ticks(3);
+    assert!(ticks() >= start + 3);
+}
+
+// 1000 Hz needs a divisor of 1193, i.e. 0x04a9, which goes out after the command, low byte first.
+// Both ends of the range work, and the timer still ticks at the new rate.
+#[test_case]
+fn test_set_frequency() {
+    assert_eq!(divisor_for(1000), 1193);
+    assert_eq!(pit_writes(1193), [(0x43, 0x36), (0x40, 0xa9), (0x40, 0x04)]);
+    assert_eq!(divisor_for(19), 62_799);
+    assert_eq!(divisor_for(PIT_FREQUENCY), 1);
+
+    let before = tick_hz();
+    set_frequency(1000);
+    assert_eq!(tick_hz(), 1000);
+    let start = ticks();
+    sleep_ticks(3);
+    assert!(ticks() >= start + 3);
+    set_frequency(before);
+    assert_eq!(tick_hz(), before);
+}
+
+#[test_case]
+const TEST_FREQUENCY_TOO_LOW: crate::test_runner::ShouldPanic = crate::test_runner::ShouldPanic {
+    name: concat!(module_path!(), "::test_frequency_too_low"),
+    test: test_frequency_too_low,
+};
+
+// 18 Hz would need a divisor of 66287
+#[cfg(test)]
+fn test_frequency_too_low() {
+    divisor_for(18);
+}
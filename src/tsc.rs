@@ -0,0 +1,134 @@
+use core::arch::asm;
+use spin::Once;
+use x86_64::instructions::port::Port;
+
+use crate::cpu::{self, Feature};
+use crate::time::PIT_FREQUENCY;
+
+/// IO ports of the PIT's command register, and of its channel 2, which is the one that used to
+/// drive the PC speaker. Unlike channel 0, nothing else uses it, so we can borrow it to calibrate.
+const PIT_COMMAND_PORT: u16 = 0x43;
+const PIT_CHANNEL2_PORT: u16 = 0x42;
+/// Select channel 2, send the count as low byte then high byte, mode 0 (the output goes high once
+/// the count reaches 0), and count in binary
+const PIT_CHANNEL2_ONE_SHOT: u8 = 0b1011_0000;
+/// The PC speaker's control port. Bit 0 is channel 2's gate, which has to be high for it to count,
+/// bit 1 connects channel 2 to the speaker, and bit 5 reads back channel 2's output.
+const SPEAKER_PORT: u16 = 0x61;
+const SPEAKER_GATE: u8 = 1;
+const SPEAKER_DATA: u8 = 1 << 1;
+const SPEAKER_CHANNEL2_OUTPUT: u8 = 1 << 5;
+
+/// How long the calibration measures, in milliseconds. Longer is more precise, but it blocks
+/// everything (including interrupts) for that long.
+const CALIBRATION_MS: u64 = 10;
+/// How often we poll the PIT during the calibration before giving up, in case there is no PIT.
+/// Reading a port takes about a microsecond, so this is way more than CALIBRATION_MS.
+const CALIBRATION_MAX_POLLS: usize = 1_000_000;
+
+/// The TSC's frequency in Hz, or None if it could not be calibrated, see frequency
+static FREQUENCY: Once<Option<u64>> = Once::new();
+
+/// Reads the time stamp counter, i.e. the number of CPU cycles since the CPU was reset. It counts
+/// at (roughly) the CPU's clock rate, so it has a far finer resolution than the timer ticks.
+/// rdtsc exists on every x86_64 CPU, even though cpuid has a flag for it (see Feature::Tsc).
+/// Note that on older CPUs, the TSC's rate changes along with the clock rate, e.g. when the CPU
+/// saves power, and it might stop entirely while the CPU sleeps in hlt. Newer CPUs have an
+/// "invariant" TSC that does not do that. We do not check for it, since QEMU does not report one
+/// either way, so treat everything derived from the TSC as approximate.
+pub fn now_cycles() -> u64 {
+    let low: u32;
+    let high: u32;
+    // only reads a counter
+    unsafe {
+        asm!("rdtsc", out("eax") low, out("edx") high, options(nomem, nostack));
+    }
+    return (u64::from(high) << 32) | u64::from(low);
+}
+
+/// Measures the TSC's frequency by counting its cycles while the PIT counts down CALIBRATION_MS,
+/// since the PIT's frequency is fixed and known. Returns None if the CPU says it has no TSC, or the
+/// PIT does not seem to count.
+/// Interrupts are disabled for the whole measurement, so that they do not delay either of the
+/// reads of the TSC.
+fn calibrate() -> Option<u64> {
+    if !cpu::has_feature(Feature::Tsc) {
+        return None;
+    }
+    let count = (u64::from(PIT_FREQUENCY) * CALIBRATION_MS / 1000) as u16;
+    let mut command = Port::<u8>::new(PIT_COMMAND_PORT);
+    let mut channel2 = Port::<u8>::new(PIT_CHANNEL2_PORT);
+    let mut speaker = Port::<u8>::new(SPEAKER_PORT);
+    return crate::interrupts::without_interrupts(|| unsafe {
+        // open the gate, but keep the speaker quiet
+        let previous = speaker.read();
+        speaker.write((previous & !SPEAKER_DATA) | SPEAKER_GATE);
+        command.write(PIT_CHANNEL2_ONE_SHOT);
+        channel2.write(count as u8);
+        // channel 2 starts counting as soon as the high byte is written
+        channel2.write((count >> 8) as u8);
+        let start = now_cycles();
+        let mut done = false;
+        for _ in 0..CALIBRATION_MAX_POLLS {
+            if speaker.read() & SPEAKER_CHANNEL2_OUTPUT != 0 {
+                done = true;
+                break;
+            }
+        }
+        let cycles = now_cycles() - start;
+        speaker.write(previous);
+        if !done {
+            return None;
+        }
+        return Some(cycles * 1000 / CALIBRATION_MS);
+    });
+}
+
+/// Returns the TSC's frequency in Hz, or None if it could not be measured. The first call measures
+/// it, which takes CALIBRATION_MS, see calibrate; init does this during boot.
+pub fn frequency() -> Option<u64> {
+    return *FREQUENCY.call_once(calibrate);
+}
+
+/// Measures the TSC's frequency, and logs it
+pub fn init() {
+    match frequency() {
+        Some(hz) => {
+            crate::log::info!("tsc: {}.{:03} GHz", hz / 1_000_000_000, hz / 1_000_000 % 1000);
+        },
+        None => {
+            crate::log::warn!("tsc: calibration failed, tsc::nanos is not available");
+        },
+    }
+}
+
+/// Converts a number of TSC cycles into (approximately) nanoseconds, or None if the frequency is
+/// unknown
+pub fn cycles_to_nanos(cycles: u64) -> Option<u64> {
+    let hz = frequency()?;
+    // cycles * 10^9 easily overflows a u64 after a few seconds
+    return Some((u128::from(cycles) * 1_000_000_000 / u128::from(hz)) as u64);
+}
+
+/// Returns (approximately) the nanoseconds since the CPU was reset, or None if the frequency is
+/// unknown. See now_cycles for why this is only approximate.
+pub fn nanos() -> Option<u64> {
+    return cycles_to_nanos(now_cycles());
+}
+
+// The TSC only ever counts up, and under QEMU, it runs at something that looks like a CPU's clock
+// rate
+#[test_case]
+fn test_tsc() {
+    let first = now_cycles();
+    let second = now_cycles();
+    assert!(second > first);
+
+    let hz = frequency().expect("the TSC could not be calibrated");
+    assert!(
+        (100_000_000..10_000_000_000).contains(&hz),
+        "implausible TSC frequency: {} Hz",
+        hz
+    );
+    assert_eq!(cycles_to_nanos(hz), Some(1_000_000_000));
+}
@@ -1,25 +1,113 @@
 use core::fmt;
-use lazy_static::lazy_static;
-use spin::Mutex;
+use core::sync::atomic::{AtomicBool, Ordering};
 use volatile::Volatile;
+use x86_64::instructions::port::Port;
 
-// Public static interface for interacting with the VGA buffer. This is defined as a lazy static,
-// because Rust must initialise regular statics at compile time, but it cannot initialise
-// references at compile time. The lazy static initialises itself when it is used for the first
-// time during run time, so that's when the reference to the VGA buffer will also be initialised.
+use crate::global::Global;
+
+// Public static interface for interacting with the VGA buffer. It is initialized by init (rather
+// than at compile time), because Rust cannot create a reference from a raw address in a static.
 //
 // We also wrap the writer in a spinlock mutex. An immutable writer would be useless, but in order
 // for writing to the buffer to be potentially async and safe, we need a locking mechanism.
 // Spinlocks are a primitive mutex that, when locked, just "spins" a tight loop till the lock is
 // released.
-lazy_static! {
-    pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
+pub static WRITER: Global<Writer> = Global::new("vga_buffer::WRITER");
+
+/// Initializes the WRITER, and wipes whatever the bootloader left on the screen.
+/// Panics if it is called twice, since that would create a second reference to the buffer.
+/// If the VGA is not in text mode (see detect_text_mode), the WRITER starts out buffered and stays
+/// that way, so that it never touches the text buffer, which is not there. print! and friends go
+/// elsewhere instead, see text_mode.
+pub fn init() {
+    let text_mode = detect_text_mode();
+    TEXT_MODE.store(text_mode, Ordering::Relaxed);
+    WRITER.init(Writer {
         column_position: 0,
-        color_code: ColorCode::new(Color::Yellow, Color::Black),
+        row_position: BUFFER_HEIGHT - 1,
+        color_code: ColorCode::new(DEFAULT_FOREGROUND, DEFAULT_BACKGROUND),
         buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
+        // Global::init only ever runs this once, so this is the only reference to SCROLLBACK
+        scrollback: unsafe { &mut *core::ptr::addr_of_mut!(SCROLLBACK) },
+        view_offset: 0,
+        ansi: AnsiParser::new(),
+        top_row: 0,
+        blink: false,
+        wrap: true,
+        // same as the SCROLLBACK
+        back_buffer: unsafe { &mut *core::ptr::addr_of_mut!(BACK_BUFFER) },
+        buffered: !text_mode,
+        scroll_top: 0,
+        scroll_bottom: BUFFER_HEIGHT - 1,
     });
+    clear_screen();
+}
+
+/// Whether the VGA is in text mode, i.e. whether there is a text buffer at 0xb8000 to print to
+static TEXT_MODE: AtomicBool = AtomicBool::new(true);
+/// Set once we warned that there is no text mode, so that we only do that once
+static WARNED_NO_TEXT_MODE: AtomicBool = AtomicBool::new(false);
+
+/// IO ports to select a register of the VGA's graphics controller, and to read it, like the CRTC's
+const GRAPHICS_INDEX_PORT: u16 = 0x3CE;
+const GRAPHICS_DATA_PORT: u16 = 0x3CF;
+/// The graphics controller's miscellaneous register, whose lowest bit is set in graphics modes
+const GRAPHICS_MISCELLANEOUS: u8 = 0x06;
+const GRAPHICS_MISCELLANEOUS_GRAPHICS_MODE: u8 = 1;
+
+/// Asks the VGA whether it is in text mode. Our bootloader always boots in text mode, and its
+/// BootInfo does not say anything about the screen, so we cannot take its word for it. But when
+/// booting through UEFI, the firmware sets up a linear framebuffer instead, and there might not even
+/// be a VGA. Reading a port that nothing answers to gives all ones, so that counts as graphics mode,
+/// too.
+fn detect_text_mode() -> bool {
+    let mut index_port = Port::<u8>::new(GRAPHICS_INDEX_PORT);
+    let mut data_port = Port::<u8>::new(GRAPHICS_DATA_PORT);
+    let miscellaneous = unsafe {
+        index_port.write(GRAPHICS_MISCELLANEOUS);
+        data_port.read()
+    };
+    return miscellaneous & GRAPHICS_MISCELLANEOUS_GRAPHICS_MODE == 0;
+}
+
+/// Returns whether the VGA is in text mode. If it is not, print! and friends go to the
+/// gfx_console::CONSOLE or SERIAL1 instead of the screen, and the WRITER only writes to its back
+/// buffer, see init.
+pub fn text_mode() -> bool {
+    return TEXT_MODE.load(Ordering::Relaxed);
+}
+
+/// Overrides what detect_text_mode found, so that the tests can pretend that there is no text mode
+#[cfg(test)]
+fn set_text_mode(text_mode: bool) {
+    TEXT_MODE.store(text_mode, Ordering::Relaxed);
+}
+
+/// Sends args somewhere else if there is no text mode, and returns whether it did: to the
+/// gfx_console::CONSOLE if there is one, and to SERIAL1 otherwise. Warns about the latter the first
+/// time, since otherwise nobody knows where the output went.
+fn print_without_text_mode(args: fmt::Arguments) -> bool {
+    use core::fmt::Write;
+
+    if text_mode() {
+        return false;
+    }
+    if let Some(console) = crate::gfx_console::CONSOLE.get() {
+        // see with_writer for why interrupts are disabled
+        crate::interrupts::without_interrupts(|| console.lock().write_fmt(args).unwrap());
+        return true;
+    }
+    if !WARNED_NO_TEXT_MODE.swap(true, Ordering::Relaxed) {
+        crate::serial_println!("vga_buffer: the VGA is not in text mode, printing to SERIAL1 instead");
+    }
+    crate::serial::write_fmt(args);
+    return true;
 }
 
+/// The colors the WRITER starts out with, and that an ANSI reset sequence goes back to
+pub const DEFAULT_FOREGROUND: Color = Color::Yellow;
+pub const DEFAULT_BACKGROUND: Color = Color::Black;
+
 /// our own print! macro, because we have to use a custom _print function that interacts with our
 /// WRITER.
 #[macro_export]
@@ -34,6 +122,48 @@ macro_rules! println {
     ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
 }
 
+/// Like println!, but the line is printed in the given foreground color, e.g.
+/// `colored_println!(Color::Red, "error: {}", e)`. The background color stays the same, and the
+/// previous foreground color is restored afterwards.
+#[macro_export]
+macro_rules! colored_println {
+    ($color:expr) => ($crate::vga_buffer::_print_colored($color, format_args!("\n")));
+    ($color:expr, $($arg:tt)*) => (
+        $crate::vga_buffer::_print_colored($color, format_args!("{}\n", format_args!($($arg)*)))
+    );
+}
+
+/// Like print!, but in the given foreground and background colors, e.g.
+/// `cprint!(Color::LightGreen, Color::Black, "{}", keyword)`. Unlike colored_println!, this does
+/// not end the line, so it can color single words, and the previous colors are restored afterwards.
+#[macro_export]
+macro_rules! cprint {
+    ($foreground:expr, $background:expr, $($arg:tt)*) => (
+        $crate::vga_buffer::_cprint($foreground, $background, format_args!($($arg)*))
+    );
+}
+
+/// Prints an error in white on red, so that it stands out, and mirrors it to the host's error stream
+/// (see serial_eprintln!). Use it like println!, for errors that are not worth a panic.
+#[macro_export]
+macro_rules! eprintln {
+    () => ($crate::vga_buffer::_eprintln(format_args!("")));
+    ($($arg:tt)*) => ($crate::vga_buffer::_eprintln(format_args!($($arg)*)));
+}
+
+/// The colors eprintln! prints in
+pub const ERROR_FOREGROUND: Color = Color::White;
+pub const ERROR_BACKGROUND: Color = Color::Red;
+
+/// Locks the WRITER with interrupts disabled, and runs f with it.
+/// Without disabling interrupts, an interrupt handler that prints while we are holding the lock
+/// would spin on it forever, since we cannot finish (and release it) until the handler returns.
+/// Interrupts that arrive in the meantime are only delayed, so keep f short, e.g. do any expensive
+/// formatting before.
+pub fn with_writer<R>(f: impl FnOnce(&mut Writer) -> R) -> R {
+    return crate::interrupts::without_interrupts(|| f(&mut WRITER.lock()));
+}
+
 /// custom _print function that uses our WRITER. The docs are hidden because this function is an
 /// implementation detail for our print macros, because our print macros are put at the crate root
 /// namespace in order to be available outside of this module. So, in order to make sure that the
@@ -41,7 +171,256 @@ macro_rules! println {
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
     use core::fmt::Write;
-    WRITER.lock().write_fmt(args).unwrap();
+    if print_without_text_mode(args) {
+        return;
+    }
+    with_writer(|writer| writer.write_fmt(args).unwrap());
+}
+
+/// _print for colored_println!. The color is swapped and restored while holding the same lock as
+/// the actual printing, so that no other writer can sneak in between and print in our color, or
+/// leave us with half a line in theirs.
+#[doc(hidden)]
+pub fn _print_colored(foreground: Color, args: fmt::Arguments) {
+    use core::fmt::Write;
+    if print_without_text_mode(args) {
+        return;
+    }
+    with_writer(|writer| {
+        let (previous_foreground, background) = writer.color();
+        writer.set_color(foreground, background);
+        writer.write_fmt(args).unwrap();
+        writer.set_color(previous_foreground, background);
+    });
+}
+
+/// _print for cprint!, see Writer::write_colored
+#[doc(hidden)]
+pub fn _cprint(foreground: Color, background: Color, args: fmt::Arguments) {
+    use core::fmt::Write;
+    if print_without_text_mode(args) {
+        return;
+    }
+    with_writer(|writer| writer.with_colors(foreground, background, |writer| writer.write_fmt(args).unwrap()));
+}
+
+/// _print for eprintln!. The colors are restored before the newline, since the new line is blanked
+/// in the current colors, and would otherwise be red all the way across.
+#[doc(hidden)]
+pub fn _eprintln(args: fmt::Arguments) {
+    use core::fmt::Write;
+    if text_mode() {
+        with_writer(|writer| {
+            writer.with_colors(ERROR_FOREGROUND, ERROR_BACKGROUND, |writer| {
+                writer.write_fmt(args).unwrap()
+            });
+            writer.write_byte(b'\n');
+        });
+    }
+    crate::serial_eprintln!("{}", args);
+}
+
+/// Writes the string to the screen in the given colors through the WRITER, see
+/// Writer::write_colored
+pub fn write_colored(s: &str, foreground: Color, background: Color) {
+    with_writer(|writer| writer.write_colored(s, foreground, background));
+}
+
+/// Prints every line of the string centered through the WRITER, see Writer::write_centered
+pub fn println_centered(s: &str) {
+    if print_without_text_mode(format_args!("{}\n", s)) {
+        return;
+    }
+    with_writer(|writer| writer.write_centered(s));
+}
+
+/// Writes the bytes to the screen verbatim through the WRITER, see Writer::write_raw
+pub fn write_raw(bytes: &[u8]) {
+    with_writer(|writer| writer.write_raw(bytes));
+}
+
+/// Draws a box through the WRITER, see Writer::draw_box
+pub fn draw_box(row: usize, col: usize, width: usize, height: usize) {
+    with_writer(|writer| writer.draw_box(row, col, width, height));
+}
+
+/// Draws a horizontal line through the WRITER, see Writer::draw_hline
+pub fn draw_hline(row: usize, col: usize, len: usize) {
+    with_writer(|writer| writer.draw_hline(row, col, len));
+}
+
+/// Draws a vertical line through the WRITER, see Writer::draw_vline
+pub fn draw_vline(row: usize, col: usize, len: usize) {
+    with_writer(|writer| writer.draw_vline(row, col, len));
+}
+
+/// Turns the WRITER's back buffer on or off, see Writer::set_buffered
+pub fn set_buffered(buffered: bool) {
+    with_writer(|writer| writer.set_buffered(buffered));
+}
+
+/// Shows what was written to the WRITER's back buffer on the screen, see Writer::flush
+pub fn flush() {
+    with_writer(|writer| writer.flush());
+}
+
+/// What the start and the end of a screenshot look like on SERIAL1
+pub const SCREENSHOT_BANNER: &str = "=== SCREEN ===";
+pub const SCREENSHOT_END: &str = "=== END SCREEN ===";
+
+/// Writes everything on the screen to SERIAL1 as text, between SCREENSHOT_BANNER and
+/// SCREENSHOT_END, so that e.g. an integration test or somebody looking at the host's log can see
+/// what the screen looked like, see Writer::write_screenshot.
+/// Both the WRITER and SERIAL1 stay locked until the whole screen is written, so nobody can change
+/// the screen in between, or print into the middle of the screenshot.
+pub fn screenshot() {
+    with_writer(|writer| {
+        // writing to the serial port does not fail
+        let _ = writer.write_screenshot(&mut *crate::serial::SERIAL1.lock());
+    });
+}
+
+/// Restricts the WRITER's scrolling to some of the rows, see Writer::set_scroll_region
+pub fn set_scroll_region(top: usize, bottom: usize) {
+    with_writer(|writer| writer.set_scroll_region(top, bottom));
+}
+
+/// Whether the timer interrupt shows a spinner in the top right corner, see set_heartbeat
+static HEARTBEAT: AtomicBool = AtomicBool::new(false);
+/// The spinner's frames, one after the other
+const HEARTBEAT_FRAMES: [u8; 4] = *b"|/-\\";
+/// How many ticks each frame of the spinner is shown for
+const HEARTBEAT_INTERVAL_TICKS: u64 = 25;
+
+/// Turns the heartbeat on or off: while it is on, the timer interrupt turns a spinner (|/-\) in the
+/// top right corner of the screen every HEARTBEAT_INTERVAL_TICKS, so that we can see that the
+/// timer interrupt still fires, and the kernel is not stuck with interrupts disabled.
+/// Turning it off leaves the last frame on the screen.
+pub fn set_heartbeat(heartbeat: bool) {
+    HEARTBEAT.store(heartbeat, Ordering::Relaxed);
+}
+
+/// Called by the timer interrupt handler with the current tick count, shows the next frame of the
+/// heartbeat every HEARTBEAT_INTERVAL_TICKS, see set_heartbeat.
+/// The code we interrupted might hold the WRITER, so we only try to lock it, and skip the frame if
+/// that fails.
+#[doc(hidden)]
+pub fn heartbeat(ticks: u64) {
+    if !HEARTBEAT.load(Ordering::Relaxed) || !ticks.is_multiple_of(HEARTBEAT_INTERVAL_TICKS) || !text_mode() {
+        return;
+    }
+    let frame = HEARTBEAT_FRAMES[(ticks / HEARTBEAT_INTERVAL_TICKS % HEARTBEAT_FRAMES.len() as u64) as usize];
+    if let Some(mut writer) = WRITER.try_lock() {
+        writer.draw_heartbeat(frame);
+    }
+}
+
+/// Moves the WRITER's cursor, see Writer::set_position
+pub fn set_cursor_position(row: usize, col: usize) {
+    with_writer(|writer| writer.set_position(row, col));
+}
+
+/// Blanks the whole screen using the WRITER's current colors.
+pub fn clear_screen() {
+    with_writer(|writer| writer.clear_screen());
+}
+
+/// IO port of the VGA's attribute controller. Unlike the CRTC, it uses a single port for both the
+/// index and the data, see set_blink_mode.
+const ATTRIBUTE_CONTROLLER_PORT: u16 = 0x3C0;
+/// IO port to read a register of the attribute controller from, after selecting it through
+/// ATTRIBUTE_CONTROLLER_PORT
+const ATTRIBUTE_CONTROLLER_READ_PORT: u16 = 0x3C1;
+/// Reading the VGA's input status register resets the attribute controller's flip-flop
+const INPUT_STATUS_PORT: u16 = 0x3DA;
+/// The attribute controller's mode control register, and its bit that turns on blinking
+const ATTRIBUTE_MODE_CONTROL: u8 = 0x10;
+const ATTRIBUTE_MODE_BLINK: u8 = 1 << 3;
+/// Set in the index we write to the attribute controller to keep the screen on. With it cleared,
+/// the attribute controller lets go of its palette, and the screen goes blank.
+const ATTRIBUTE_PALETTE_ADDRESS_SOURCE: u8 = 1 << 5;
+
+/// Switches the meaning of the top bit of the color codes between "blink" (the default) and "bright
+/// background". In blink mode, only the 8 darker colors work as backgrounds, and a bright one
+/// makes the character blink on the darker version instead.
+/// The attribute controller takes both the index of a register and the value to write to it on the
+/// same port, and keeps track of which one comes next with a flip-flop. We do not know what state
+/// it is in, but reading the input status register resets it to "index". So to change a register,
+/// we reset the flip-flop, write the index, read the register, reset the flip-flop again (reading
+/// the register does not flip it, but better safe than sorry), write the index again, and then the
+/// value. All of that with interrupts disabled, since an interrupt in the middle of it could leave
+/// the flip-flop in the wrong state.
+pub fn set_blink_mode(blink: bool) {
+    let mut input_status = Port::<u8>::new(INPUT_STATUS_PORT);
+    let mut attribute_controller = Port::<u8>::new(ATTRIBUTE_CONTROLLER_PORT);
+    let mut attribute_read = Port::<u8>::new(ATTRIBUTE_CONTROLLER_READ_PORT);
+    let index = ATTRIBUTE_MODE_CONTROL | ATTRIBUTE_PALETTE_ADDRESS_SOURCE;
+    crate::interrupts::without_interrupts(|| unsafe {
+        input_status.read();
+        attribute_controller.write(index);
+        let mode = attribute_read.read();
+        let mode = if blink {
+            mode | ATTRIBUTE_MODE_BLINK
+        } else {
+            mode & !ATTRIBUTE_MODE_BLINK
+        };
+        input_status.read();
+        attribute_controller.write(index);
+        attribute_controller.write(mode);
+    });
+}
+
+/// IO port used to select a register of the VGA's CRT controller (CRTC)
+const CRTC_INDEX_PORT: u16 = 0x3D4;
+
+/// IO port used to read or write the CRTC register selected through CRTC_INDEX_PORT
+const CRTC_DATA_PORT: u16 = 0x3D5;
+
+/// CRTC registers controlling the shape of the cursor, i.e. which scanlines of a character cell
+/// it covers. Bit 5 of the start register turns the cursor off.
+const CRTC_CURSOR_START: u8 = 0x0A;
+const CRTC_CURSOR_END: u8 = 0x0B;
+
+/// The "cursor disable" bit of CRTC_CURSOR_START
+const CURSOR_DISABLE: u8 = 0x20;
+
+/// CRTC registers holding the high and low byte of the cursor's linear position in the buffer
+const CRTC_CURSOR_LOCATION_HIGH: u8 = 0x0E;
+const CRTC_CURSOR_LOCATION_LOW: u8 = 0x0F;
+
+/// CRTC registers holding the high and low byte of the linear position in the buffer that is
+/// displayed in the top left corner of the screen
+const CRTC_START_ADDRESS_HIGH: u8 = 0x0C;
+const CRTC_START_ADDRESS_LOW: u8 = 0x0D;
+
+/// CRTC register holding the lower 8 bits of the line compare value, i.e. the scanline after which
+/// the VGA continues displaying from the very start of the buffer. Bit 8 of that value is bit 4 of
+/// the overflow register, and bit 9 is bit 6 of the maximum scan line register. The lower 5 bits
+/// of the maximum scan line register hold the height of a character cell in scanlines minus 1.
+const CRTC_LINE_COMPARE: u8 = 0x18;
+const CRTC_OVERFLOW: u8 = 0x07;
+const CRTC_MAXIMUM_SCAN_LINE: u8 = 0x09;
+
+/// Writes value into the CRTC register at index. The CRTC registers are not mapped into memory,
+/// instead we first write the index of the register we want to the index port, and then the value
+/// to the data port.
+fn crtc_write(index: u8, value: u8) {
+    let mut index_port = Port::new(CRTC_INDEX_PORT);
+    let mut data_port = Port::new(CRTC_DATA_PORT);
+    unsafe {
+        index_port.write(index);
+        data_port.write(value);
+    }
+}
+
+/// Reads the CRTC register at index, see crtc_write
+fn crtc_read(index: u8) -> u8 {
+    let mut index_port = Port::new(CRTC_INDEX_PORT);
+    let mut data_port = Port::new(CRTC_DATA_PORT);
+    unsafe {
+        index_port.write(index);
+        return data_port.read();
+    }
 }
 
 /// Enum to represent the 4 bits declaring the color of a code page 437 character used in the VGA
@@ -50,7 +429,6 @@ pub fn _print(args: fmt::Arguments) {
 /// Generally, those 4 bits consist of 3 bits for the base color + 1 bit for whether it's bright or
 /// not. For example, the binary 0000 stands for black, and 0001 for blue, and then 1000 is "bright
 /// black" (or dark gray) and 1001 is light blue.
-#[allow(dead_code)]
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 #[repr(u8)]
 pub enum Color {
@@ -72,6 +450,241 @@ pub enum Color {
     White = 15,
 }
 
+impl Color {
+    /// Every color, in the order of their values, so that Color::ALL[n] has the value n, e.g. for
+    /// cycling through the colors
+    pub const ALL: [Color; 16] = [
+        Color::Black,
+        Color::Blue,
+        Color::Green,
+        Color::Cyan,
+        Color::Red,
+        Color::Magenta,
+        Color::Brown,
+        Color::LightGray,
+        Color::DarkGray,
+        Color::LightBlue,
+        Color::LightGreen,
+        Color::LightCyan,
+        Color::LightRed,
+        Color::Pink,
+        Color::Yellow,
+        Color::White,
+    ];
+
+    /// Turns a value back into its Color, or None if no color has that value, i.e. if it is 16 or
+    /// more
+    pub fn from_u8(n: u8) -> Option<Color> {
+        return Color::ALL.get(usize::from(n)).copied();
+    }
+
+    /// Turns the lower 4 bits of a byte back into a Color. The upper 4 bits are simply ignored,
+    /// which means this function cannot fail, because every 4 bit value maps to a color.
+    fn from_nibble(nibble: u8) -> Color {
+        return Color::ALL[usize::from(nibble & 0x0f)];
+    }
+}
+
+/// Translates a unicode character into its code page 437 byte, which is what the VGA text buffer
+/// actually displays. Printable ASCII maps onto itself, and so do the upper 128 bytes and the
+/// glyphs code page 437 shows for the ASCII control bytes (like the smileys and arrows), as long
+/// as the character has a code page 437 equivalent. Everything else (including the actual control
+/// characters) becomes the block character 0xfe.
+pub const fn unicode_to_cp437(c: char) -> u8 {
+    return match c {
+        ' '..='~' => c as u8,
+        '☺' => 0x01,
+        '☻' => 0x02,
+        '♥' => 0x03,
+        '♦' => 0x04,
+        '♣' => 0x05,
+        '♠' => 0x06,
+        '•' => 0x07,
+        '◘' => 0x08,
+        '○' => 0x09,
+        '◙' => 0x0a,
+        '♂' => 0x0b,
+        '♀' => 0x0c,
+        '♪' => 0x0d,
+        '♫' => 0x0e,
+        '☼' => 0x0f,
+        '►' => 0x10,
+        '◄' => 0x11,
+        '↕' => 0x12,
+        '‼' => 0x13,
+        '¶' => 0x14,
+        '§' => 0x15,
+        '▬' => 0x16,
+        '↨' => 0x17,
+        '↑' => 0x18,
+        '↓' => 0x19,
+        '→' => 0x1a,
+        '←' => 0x1b,
+        '∟' => 0x1c,
+        '↔' => 0x1d,
+        '▲' => 0x1e,
+        '▼' => 0x1f,
+        '⌂' => 0x7f,
+        'Ç' => 0x80,
+        'ü' => 0x81,
+        'é' => 0x82,
+        'â' => 0x83,
+        'ä' => 0x84,
+        'à' => 0x85,
+        'å' => 0x86,
+        'ç' => 0x87,
+        'ê' => 0x88,
+        'ë' => 0x89,
+        'è' => 0x8a,
+        'ï' => 0x8b,
+        'î' => 0x8c,
+        'ì' => 0x8d,
+        'Ä' => 0x8e,
+        'Å' => 0x8f,
+        'É' => 0x90,
+        'æ' => 0x91,
+        'Æ' => 0x92,
+        'ô' => 0x93,
+        'ö' => 0x94,
+        'ò' => 0x95,
+        'û' => 0x96,
+        'ù' => 0x97,
+        'ÿ' => 0x98,
+        'Ö' => 0x99,
+        'Ü' => 0x9a,
+        '¢' => 0x9b,
+        '£' => 0x9c,
+        '¥' => 0x9d,
+        '₧' => 0x9e,
+        'ƒ' => 0x9f,
+        'á' => 0xa0,
+        'í' => 0xa1,
+        'ó' => 0xa2,
+        'ú' => 0xa3,
+        'ñ' => 0xa4,
+        'Ñ' => 0xa5,
+        'ª' => 0xa6,
+        'º' => 0xa7,
+        '¿' => 0xa8,
+        '⌐' => 0xa9,
+        '¬' => 0xaa,
+        '½' => 0xab,
+        '¼' => 0xac,
+        '¡' => 0xad,
+        '«' => 0xae,
+        '»' => 0xaf,
+        '░' => 0xb0,
+        '▒' => 0xb1,
+        '▓' => 0xb2,
+        '│' => 0xb3,
+        '┤' => 0xb4,
+        '╡' => 0xb5,
+        '╢' => 0xb6,
+        '╖' => 0xb7,
+        '╕' => 0xb8,
+        '╣' => 0xb9,
+        '║' => 0xba,
+        '╗' => 0xbb,
+        '╝' => 0xbc,
+        '╜' => 0xbd,
+        '╛' => 0xbe,
+        '┐' => 0xbf,
+        '└' => 0xc0,
+        '┴' => 0xc1,
+        '┬' => 0xc2,
+        '├' => 0xc3,
+        '─' => 0xc4,
+        '┼' => 0xc5,
+        '╞' => 0xc6,
+        '╟' => 0xc7,
+        '╚' => 0xc8,
+        '╔' => 0xc9,
+        '╩' => 0xca,
+        '╦' => 0xcb,
+        '╠' => 0xcc,
+        '═' => 0xcd,
+        '╬' => 0xce,
+        '╧' => 0xcf,
+        '╨' => 0xd0,
+        '╤' => 0xd1,
+        '╥' => 0xd2,
+        '╙' => 0xd3,
+        '╘' => 0xd4,
+        '╒' => 0xd5,
+        '╓' => 0xd6,
+        '╫' => 0xd7,
+        '╪' => 0xd8,
+        '┘' => 0xd9,
+        '┌' => 0xda,
+        '█' => 0xdb,
+        '▄' => 0xdc,
+        '▌' => 0xdd,
+        '▐' => 0xde,
+        '▀' => 0xdf,
+        'α' => 0xe0,
+        'ß' => 0xe1,
+        'Γ' => 0xe2,
+        'π' => 0xe3,
+        'Σ' => 0xe4,
+        'σ' => 0xe5,
+        'µ' => 0xe6,
+        'τ' => 0xe7,
+        'Φ' => 0xe8,
+        'Θ' => 0xe9,
+        'Ω' => 0xea,
+        'δ' => 0xeb,
+        '∞' => 0xec,
+        'φ' => 0xed,
+        'ε' => 0xee,
+        '∩' => 0xef,
+        '≡' => 0xf0,
+        '±' => 0xf1,
+        '≥' => 0xf2,
+        '≤' => 0xf3,
+        '⌠' => 0xf4,
+        '⌡' => 0xf5,
+        '÷' => 0xf6,
+        '≈' => 0xf7,
+        '°' => 0xf8,
+        '∙' => 0xf9,
+        '·' => 0xfa,
+        '√' => 0xfb,
+        'ⁿ' => 0xfc,
+        '²' => 0xfd,
+        '■' => 0xfe,
+        '\u{a0}' => 0xff,
+        _ => 0xfe,
+    };
+}
+
+/// Translates a code page 437 byte back into ASCII, for when the screen has to be shown somewhere
+/// else, e.g. in screenshot. Only printable ASCII survives, everything else becomes a dot.
+pub const fn cp437_to_ascii(byte: u8) -> char {
+    return match byte {
+        b' '..=b'~' => byte as char,
+        _ => '.',
+    };
+}
+
+/// Maps the 8 standard ANSI colors (the n in the SGR codes 30+n and 40+n) onto our colors.
+/// The ANSI order differs from the VGA one, and ANSI's "yellow" and "white" are VGA's brown and
+/// light gray, since the bright variants are separate codes in ANSI.
+fn ansi_color(n: u8) -> Color {
+    return match n {
+        0 => Color::Black,
+        1 => Color::Red,
+        2 => Color::Green,
+        3 => Color::Brown,
+        4 => Color::Blue,
+        5 => Color::Magenta,
+        6 => Color::Cyan,
+        _ => Color::LightGray,
+    };
+}
+
+/// The top bit of a color code, see ColorCode::with_blink
+const BLINK_BIT: u8 = 1 << 7;
+
 /// Repesents the full color code (foreground + background). It is transparently represented by a
 /// u8, but we can give it new methods and stuff like that (kind of like distinct types in nim and
 /// odin).
@@ -86,6 +699,27 @@ impl ColorCode {
         // byte left over after the left shift.
         return ColorCode((background as u8) << 4 | (foreground as u8));
     }
+
+    /// Returns the color code with the top bit set, which makes the character blink, or, if the
+    /// VGA is not in blink mode, brightens the background (see set_blink_mode)
+    fn with_blink(self) -> Self {
+        return ColorCode(self.0 | BLINK_BIT);
+    }
+
+    /// The foreground color lives in the lower 4 bits
+    fn foreground(self) -> Color {
+        return Color::from_nibble(self.0);
+    }
+
+    /// The background color lives in the upper 4 bits
+    fn background(self) -> Color {
+        return Color::from_nibble(self.0 >> 4);
+    }
+
+    /// Decodes the color code back into its (foreground, background) pair
+    fn colors(self) -> (Color, Color) {
+        return (self.foreground(), self.background());
+    }
 }
 
 /// Represents a character in the VGA text buffer, consisting of a code page 437 character and its
@@ -101,10 +735,60 @@ struct ScreenChar {
 }
 
 /// Number of rows in the VGA buffer
-const BUFFER_HEIGHT: usize = 25;
+pub const BUFFER_HEIGHT: usize = 25;
 
 /// Number of columns in the VGA buffer
-const BUFFER_WIDTH: usize = 80;
+pub const BUFFER_WIDTH: usize = 80;
+
+/// Distance between two tab stops; a tab advances the cursor to the next multiple of this
+pub const TAB_WIDTH: usize = 8;
+
+/// The code page 437 glyphs for a box with a double border, see Writer::draw_box
+pub const BOX_TOP_LEFT: u8 = 0xc9;
+pub const BOX_TOP_RIGHT: u8 = 0xbb;
+pub const BOX_BOTTOM_LEFT: u8 = 0xc8;
+pub const BOX_BOTTOM_RIGHT: u8 = 0xbc;
+pub const BOX_HORIZONTAL: u8 = 0xcd;
+pub const BOX_VERTICAL: u8 = 0xba;
+
+/// The code page 437 glyphs for the filled (█) and the empty (░) part of a ProgressBar
+pub const PROGRESS_FILLED: u8 = 0xdb;
+pub const PROGRESS_EMPTY: u8 = 0xb0;
+
+/// A bar at a fixed position on the screen that shows how far along a long operation is, e.g.
+/// ████░░░░░░ for 40%. Every set redraws the whole bar in place with write_raw_at, so it never
+/// scrolls the screen or moves the cursor, no matter how often it is updated.
+#[derive(Debug, Clone, Copy)]
+pub struct ProgressBar {
+    row: usize,
+    col: usize,
+    width: usize,
+}
+
+impl ProgressBar {
+    /// A bar of width cells, starting at the given row and column. Like write_raw_at, out of range
+    /// coordinates are clamped to the last row/column, and the bar is cut off at the end of the row.
+    /// Nothing shows up on the screen until the first set.
+    pub fn new(row: usize, col: usize, width: usize) -> Self {
+        let row = row.min(BUFFER_HEIGHT - 1);
+        let col = col.min(BUFFER_WIDTH - 1);
+        return ProgressBar {
+            row,
+            col,
+            width: width.min(BUFFER_WIDTH - col),
+        };
+    }
+
+    /// Shows the fraction (from 0.0 to 1.0) as done, by filling that part of the bar, rounded down
+    /// to whole cells, so that the bar is only full once we are done. Fractions outside of that
+    /// range are clamped.
+    pub fn set(&self, fraction: f32) {
+        let filled = (self.width as f32 * fraction.clamp(0.0, 1.0)) as usize;
+        let mut cells = [PROGRESS_EMPTY; BUFFER_WIDTH];
+        cells[..filled].fill(PROGRESS_FILLED);
+        with_writer(|writer| writer.write_raw_at(self.row, self.col, &cells[..self.width]));
+    }
+}
 
 /// The VGA buffer, which is basically just an array of an array of ScreenChar, representing the
 /// matrix of characters being stored in the VGA buffer.
@@ -117,81 +801,909 @@ struct Buffer {
     chars: [[Volatile<ScreenChar>; BUFFER_WIDTH]; BUFFER_HEIGHT],
 }
 
+/// Maximum number of parameters of an ANSI escape sequence we keep track of. Further parameters
+/// are dropped.
+const ANSI_MAX_PARAMS: usize = 4;
+
+/// States of the AnsiParser
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum AnsiState {
+    /// regular text
+    Ground,
+    /// we have seen an ESC byte
+    Escape,
+    /// we are inside a control sequence, i.e. we have seen ESC [
+    ControlSequence,
+}
+
+/// What the Writer needs to do after feeding a character to the AnsiParser
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum AnsiAction {
+    /// the character is regular text and needs to be printed
+    Print(char),
+    /// a complete SGR ("select graphic rendition") sequence, i.e. ESC [ <params> m, with its
+    /// parameters; the sequence ESC [ m has no parameters at all
+    SelectGraphicRendition([u8; ANSI_MAX_PARAMS], usize),
+    /// the character was swallowed by an escape sequence
+    None,
+}
+
+/// Small state machine recognising ANSI escape sequences in the text written to the VGA buffer.
+/// It is fed one character at a time and keeps its state in between, so an escape sequence may be
+/// split across several write_string calls.
+/// We only act on SGR sequences, every other or malformed sequence is swallowed silently, so that
+/// it does not show up as garbage on the screen.
+struct AnsiParser {
+    state: AnsiState,
+    params: [u8; ANSI_MAX_PARAMS],
+    param_count: usize,
+    // set when the control sequence contains bytes we do not support, so that we ignore it
+    malformed: bool,
+}
+
+impl AnsiParser {
+    const fn new() -> Self {
+        return AnsiParser {
+            state: AnsiState::Ground,
+            params: [0; ANSI_MAX_PARAMS],
+            param_count: 0,
+            malformed: false,
+        };
+    }
+
+    /// Feeds the next character into the parser and returns what needs to be done with it
+    fn advance(&mut self, c: char) -> AnsiAction {
+        match self.state {
+            AnsiState::Ground => {
+                if c == '\x1b' {
+                    self.state = AnsiState::Escape;
+                    return AnsiAction::None;
+                }
+                return AnsiAction::Print(c);
+            },
+            AnsiState::Escape => {
+                // ESC followed by anything but [ is some other escape sequence, which we drop
+                self.state = AnsiState::Ground;
+                if c == '[' {
+                    self.state = AnsiState::ControlSequence;
+                    self.params = [0; ANSI_MAX_PARAMS];
+                    self.param_count = 0;
+                    self.malformed = false;
+                }
+                return AnsiAction::None;
+            },
+            AnsiState::ControlSequence => match c {
+                '0'..='9' => {
+                    if self.param_count == 0 {
+                        self.param_count = 1;
+                    }
+                    if self.param_count <= ANSI_MAX_PARAMS {
+                        let param = &mut self.params[self.param_count - 1];
+                        *param = param.saturating_mul(10).saturating_add(c as u8 - b'0');
+                    }
+                    return AnsiAction::None;
+                },
+                ';' => {
+                    // an empty parameter counts as 0, so ESC [ ; 31 m has the parameters 0 and 31
+                    self.param_count = self.param_count.max(1) + 1;
+                    return AnsiAction::None;
+                },
+                // other parameter and intermediate bytes, which we do not support
+                '\x20'..='\x3f' => {
+                    self.malformed = true;
+                    return AnsiAction::None;
+                },
+                // the final byte ends the sequence
+                '\x40'..='\x7e' => {
+                    self.state = AnsiState::Ground;
+                    if c == 'm' && !self.malformed {
+                        return AnsiAction::SelectGraphicRendition(self.params, self.param_count.min(ANSI_MAX_PARAMS));
+                    }
+                    return AnsiAction::None;
+                },
+                // anything else cannot be part of a control sequence, so we abort it
+                _ => {
+                    self.state = AnsiState::Ground;
+                    return AnsiAction::None;
+                },
+            },
+        }
+    }
+}
+
+/// Number of rows that scrolled off the top of the screen, which we keep around so they can be
+/// scrolled back into view.
+pub const SCROLLBACK_LINES: usize = 100;
+
+/// A blank cell used to initialise the scrollback buffer at compile time
+const BLANK: ScreenChar = ScreenChar {
+    character: b' ',
+    color_code: ColorCode(0),
+};
+
+/// History of the rows that were scrolled off the top of the screen.
+/// The rows are stored in a ring buffer: head is the index the next row is written to, and once
+/// the buffer is full, the oldest row is overwritten. Because we do not have a heap, this lives
+/// in a static (see SCROLLBACK) that is sized at compile time.
+struct Scrollback {
+    rows: [[ScreenChar; BUFFER_WIDTH]; SCROLLBACK_LINES],
+    head: usize,
+    len: usize,
+    // Copy of the visible screen, taken when the view leaves the bottom, because repainting the
+    // screen from the history overwrites the rows we are currently writing to.
+    live: [[ScreenChar; BUFFER_WIDTH]; BUFFER_HEIGHT],
+}
+
+impl Scrollback {
+    /// Appends a row to the history, dropping the oldest row if the history is full
+    fn push(&mut self, row: [ScreenChar; BUFFER_WIDTH]) {
+        self.rows[self.head] = row;
+        self.head = (self.head + 1) % SCROLLBACK_LINES;
+        self.len = (self.len + 1).min(SCROLLBACK_LINES);
+    }
+
+    /// Returns the i-th row of the history, counting from the oldest row we still have
+    fn get(&self, i: usize) -> &[ScreenChar; BUFFER_WIDTH] {
+        return &self.rows[(self.head + SCROLLBACK_LINES - self.len + i) % SCROLLBACK_LINES];
+    }
+}
+
+/// Backing storage for the WRITER's scrollback. Only ever accessed through the WRITER, which
+/// takes the only reference to it during its initialisation.
+static mut SCROLLBACK: Scrollback = Scrollback {
+    rows: [[BLANK; BUFFER_WIDTH]; SCROLLBACK_LINES],
+    head: 0,
+    len: 0,
+    live: [[BLANK; BUFFER_WIDTH]; BUFFER_HEIGHT],
+};
+
+/// A copy of the VGA buffer that the Writer writes to instead while it is buffered, so that a
+/// redraw of the whole screen shows up all at once on flush, instead of flickering through every
+/// step in between. Its rows are laid out like the buffer's, see Writer.
+struct BackBuffer {
+    chars: [[ScreenChar; BUFFER_WIDTH]; BUFFER_HEIGHT],
+    // which rows changed since the last flush, so that flush only copies those
+    dirty: [bool; BUFFER_HEIGHT],
+}
+
+/// Backing storage for the WRITER's back buffer. Only ever accessed through the WRITER, which
+/// takes the only reference to it during its initialisation.
+static mut BACK_BUFFER: BackBuffer = BackBuffer {
+    chars: [[BLANK; BUFFER_WIDTH]; BUFFER_HEIGHT],
+    dirty: [false; BUFFER_HEIGHT],
+};
+
 /// Public facing object responsible for writing to the VGA buffer. The way it is going to write to
 /// is to write to the bottom line, and when that line is full or it hits a line break, all lines
-/// are shifted one row up, with the top most row being lost.
+/// are shifted one row up, with the top most row moving into the scrollback.
 /// While writing to a row, it keeps track of the column it would be writing to next as well as the
-/// current color code.
+/// current color code. The row is usually the last one, see set_position for writing elsewhere.
+///
+/// The rows of the buffer are used as a ring: top_row is the row of the buffer that is displayed
+/// at the top of the screen, and the row displayed at row r of the screen is stored in buffer row
+/// (top_row + r) % BUFFER_HEIGHT. Shifting all lines up then just means clearing the old top row
+/// and making it the new bottom row by incrementing top_row, instead of copying every single row.
+/// For the screen to actually show the rows in this order, we tell the VGA to start displaying at
+/// top_row and to wrap around to the start of the buffer after the last row (see
+/// update_display_start).
+/// All methods take rows as they appear on screen, and translate them with physical_row.
 pub struct Writer {
     column_position: usize,
+    // the row we write to, as it appears on screen
+    row_position: usize,
     color_code: ColorCode,
     // Note that the life time for this reference is static, because the VGA buffer is supposed to
     // live for the full run time of program (aka the kernel)
     buffer: &'static mut Buffer,
+    scrollback: &'static mut Scrollback,
+    // how many rows the view is currently scrolled up into the scrollback; 0 means we are
+    // looking at the live screen
+    view_offset: usize,
+    ansi: AnsiParser,
+    top_row: usize,
+    // whether the characters we write blink, see set_blink
+    blink: bool,
+    // whether lines that are too long continue on the next line, see set_wrap
+    wrap: bool,
+    back_buffer: &'static mut BackBuffer,
+    // whether we write to the back_buffer instead of the buffer, see set_buffered
+    buffered: bool,
+    // the first and the last row (as they appear on screen) that new_line scrolls, see
+    // set_scroll_region
+    scroll_top: usize,
+    scroll_bottom: usize,
 }
 
 impl Writer {
-    /// writes a single byte to the last row at self.column_position, and advance column_position.
+    /// Sets the foreground and background color used for every character written after this call.
+    /// Characters that are already in the buffer keep their color.
+    pub fn set_color(&mut self, foreground: Color, background: Color) {
+        self.color_code = ColorCode::new(foreground, background);
+    }
+
+    /// Returns the current (foreground, background) color pair, so that callers can save it before
+    /// calling set_color and restore it afterwards.
+    pub fn color(&self) -> (Color, Color) {
+        return self.color_code.colors();
+    }
+
+    /// Makes the characters written after this call blink (or not), e.g. for alerts that must not
+    /// be missed. This only blinks if the VGA is in blink mode, which is the default, see
+    /// set_blink_mode. Otherwise the characters get the bright version of their background color.
+    /// Note that either way, read_char reports the bright version of the background color for
+    /// blinking characters, since the VGA uses the same bit for both.
+    pub fn set_blink(&mut self, blink: bool) {
+        self.blink = blink;
+    }
+
+    /// Sets whether lines that are too long continue on the next line (the default), or are cut off
+    /// at the end of the row, e.g. for a status bar that must not spill into the next row. Without
+    /// wrapping, everything past the end of the row is dropped until the next newline.
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+    }
+
+    /// The color code for the characters we write, i.e. the current colors plus the blink bit
+    fn glyph_color_code(&self) -> ColorCode {
+        if self.blink {
+            return self.color_code.with_blink();
+        }
+        return self.color_code;
+    }
+
+    /// Writes the string in the given colors, and then goes back to the previous ones, so that it
+    /// can be used for a few words in the middle of a line without saving and restoring the colors
+    /// around it.
+    pub fn write_colored(&mut self, s: &str, foreground: Color, background: Color) {
+        self.with_colors(foreground, background, |writer| writer.write_string(s));
+    }
+
+    /// Runs f with the given colors, and then goes back to the previous ones, see write_colored
+    pub fn with_colors<R>(&mut self, foreground: Color, background: Color, f: impl FnOnce(&mut Self) -> R) -> R {
+        let previous = self.color_code;
+        self.color_code = ColorCode::new(foreground, background);
+        let result = f(self);
+        self.color_code = previous;
+        return result;
+    }
+
+    /// Writes every line of the string centered on its own row, followed by a newline, e.g. for a
+    /// splash screen. Each line is padded with spaces on the left by half of the room it leaves on
+    /// the screen, and a line that is too wide for the screen is written left aligned instead.
+    /// The padding counts chars, since each of them takes up a single cell, and does not know about
+    /// ANSI escape sequences, so those throw the centering off. It also assumes that we start at the
+    /// beginning of a row.
+    pub fn write_centered(&mut self, s: &str) {
+        for line in s.split('\n') {
+            let padding = BUFFER_WIDTH.saturating_sub(line.chars().count()) / 2;
+            for _ in 0..padding {
+                self.write_byte(b' ');
+            }
+            self.write_string(line);
+            self.write_byte(b'\n');
+        }
+    }
+
+    /// Reads back the cell at the given row and column, returning its code page 437 byte and its
+    /// (foreground, background) colors, or None if the position lies outside of the buffer.
+    pub fn read_char(&self, row: usize, col: usize) -> Option<(u8, Color, Color)> {
+        if row >= BUFFER_HEIGHT || col >= BUFFER_WIDTH {
+            return None;
+        }
+        let screen_char = self.read_cell(row, col);
+        let (foreground, background) = screen_char.color_code.colors();
+        return Some((screen_char.character, foreground, background));
+    }
+
+    /// Writes the characters on the screen to out as lines of text, with colors left out and every
+    /// character translated with cp437_to_ascii, between SCREENSHOT_BANNER and SCREENSHOT_END.
+    /// Every line is the full width of the screen, so that columns stay where they are. Rows are
+    /// written one by one from a buffer on the stack, so this does not need the heap.
+    pub fn write_screenshot(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        writeln!(out, "{}", SCREENSHOT_BANNER)?;
+        let mut line = [0u8; BUFFER_WIDTH];
+        for row in 0..BUFFER_HEIGHT {
+            for (col, byte) in line.iter_mut().enumerate() {
+                // cp437_to_ascii only returns ASCII, which is a single byte
+                *byte = cp437_to_ascii(self.read_cell(row, col).character) as u8;
+            }
+            writeln!(out, "{}", core::str::from_utf8(&line).unwrap())?;
+        }
+        return writeln!(out, "{}", SCREENSHOT_END);
+    }
+
+    /// Moves the cursor to the given row and column, so that everything written from now on goes
+    /// there, e.g. for redrawing parts of a full screen program. Unlike write_at, the position
+    /// sticks: the next character goes right after the last one, a newline moves to the start of
+    /// the next row, and only a newline in the last row scrolls the screen. Out of range coordinates
+    /// are clamped to the last row/column.
+    pub fn set_position(&mut self, row: usize, col: usize) {
+        self.snap_to_bottom();
+        self.row_position = row.min(BUFFER_HEIGHT - 1);
+        self.column_position = col.min(BUFFER_WIDTH - 1);
+        self.update_cursor();
+    }
+
+    /// Restricts scrolling to the rows from top to bottom (both included), so that the rows above
+    /// and below them stay put, e.g. for a status bar in the first or the last row. Like a
+    /// terminal's scrolling region, a newline in the bottom row of the region scrolls only the
+    /// region, and the cursor moves to the start of its bottom row, where printing continues.
+    /// The whole screen is the default region. A bottom beyond the screen is clamped to the last
+    /// row; panics if top is not above bottom.
+    /// Only rows that scroll off the top of the screen go into the scrollback, so a region that
+    /// does not start at the top row has none.
+    pub fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+        let bottom = bottom.min(BUFFER_HEIGHT - 1);
+        assert!(
+            top < bottom,
+            "the scroll region {}..={} has less than two rows",
+            top,
+            bottom
+        );
+        self.snap_to_bottom();
+        self.scroll_top = top;
+        self.scroll_bottom = bottom;
+        self.row_position = bottom;
+        self.column_position = 0;
+        self.update_cursor();
+    }
+
+    /// Returns the first and the last row of the scroll region, see set_scroll_region
+    pub fn scroll_region(&self) -> (usize, usize) {
+        return (self.scroll_top, self.scroll_bottom);
+    }
+
+    /// Returns the (row, column) the next character is written to, see set_position
+    pub fn position(&self) -> (usize, usize) {
+        return (self.row_position, self.column_position);
+    }
+
+    /// writes a single byte to the current row at self.column_position, and advance column_position.
     /// In case the line is full, or the byte is a newline, we write a new line first.
+    /// A backspace (0x08) erases the character left of the cursor instead, and a tab advances the
+    /// cursor to the next tab stop. A carriage return moves the cursor back to the start of the
+    /// line without clearing it, so that the following characters overwrite the line.
     pub fn write_byte(&mut self, byte: u8) {
+        self.snap_to_bottom();
         match byte {
             b'\n' => self.new_line(),
-            byte => {
-                if self.column_position >= BUFFER_WIDTH {
-                    self.new_line();
-                }
-                let row = BUFFER_HEIGHT - 1;
-                let col = self.column_position;
-
-                let color_code = self.color_code;
-                self.buffer.chars[row][col].write(ScreenChar {
-                    character: byte,
-                    color_code,
-                });
-                self.column_position += 1;
+            b'\r' => self.column_position = 0,
+            0x08 => self.backspace(),
+            b'\t' => self.tab(),
+            byte => self.write_glyph(byte),
+        }
+        self.update_cursor();
+    }
+
+    /// Writes the byte's glyph to the current row at self.column_position, and advances
+    /// column_position, starting a new line first if the current one is full (or dropping the byte,
+    /// if we do not wrap). Unlike write_byte, this never treats the byte as a control character.
+    fn write_glyph(&mut self, byte: u8) {
+        if self.column_position >= BUFFER_WIDTH {
+            if !self.wrap {
+                return;
+            }
+            self.new_line();
+        }
+        let row = self.row_position;
+        let col = self.column_position;
+
+        let color_code = self.glyph_color_code();
+        self.write_cell(
+            row,
+            col,
+            ScreenChar {
+                character: byte,
+                color_code,
             },
+        );
+        self.column_position += 1;
+    }
+
+    /// Writes the bytes to the buffer exactly as they are, as code page 437 glyphs, e.g. 0xC9, 0xCD
+    /// and 0xBB for the top of a box with a double border. Only a newline still starts a new line,
+    /// and lines that are too long still wrap.
+    /// Careful: every other byte is shown as its glyph, without any of the handling write_byte and
+    /// write_string do. A backspace does not erase anything, a tab is not expanded, a \r shows up
+    /// as a note symbol, and ANSI escape sequences end up on the screen.
+    pub fn write_raw(&mut self, bytes: &[u8]) {
+        self.snap_to_bottom();
+        for &byte in bytes {
+            match byte {
+                b'\n' => self.new_line(),
+                byte => self.write_glyph(byte),
+            }
         }
+        self.update_cursor();
     }
 
-    /// Write a string into the buffer, which just means we write each byte of the string byte by
-    /// byte.
+    /// Write a string into the buffer, which means we write each character of the string as its
+    /// code page 437 byte.
+    /// ANSI escape sequences are filtered out of the string, and SGR color sequences change the
+    /// writer's color.
+    /// Most strings are nothing but printable ASCII and newlines, which come out the same way from
+    /// write_raw, only a lot faster: no character needs translating, and the cursor is only moved
+    /// once at the end, instead of after every single byte. Anything else takes the slow path,
+    /// character by character, see write_chars.
     pub fn write_string(&mut self, s: &str) {
-        for byte in s.bytes() {
-            match byte {
-                // code page 437 character => write that byte
-                0x20..=0x7e | b'\n' => self.write_byte(byte),
+        if self.ascii_fast_path(s) {
+            self.write_raw(s.as_bytes());
+            return;
+        }
+        self.write_chars(s);
+    }
+
+    /// Whether write_raw writes s exactly like write_chars would: every byte is printable ASCII
+    /// (which is its own code page 437 byte) or a newline, and we are not in the middle of an ANSI
+    /// escape sequence, which would swallow some of them. Checking this is a single pass over the
+    /// bytes, which is cheap compared to writing them.
+    fn ascii_fast_path(&self, s: &str) -> bool {
+        return self.ansi.state == AnsiState::Ground
+            && s.bytes().all(|byte| byte == b'\n' || (b' '..=b'~').contains(&byte));
+    }
+
+    /// The slow path of write_string, which feeds every character through the AnsiParser and
+    /// handles it on its own
+    fn write_chars(&mut self, s: &str) {
+        // Note that we iterate over the chars and not over the bytes of the string, because a
+        // character outside of ASCII takes up multiple bytes in UTF-8, but only a single cell on
+        // the screen.
+        for c in s.chars() {
+            let c = match self.ansi.advance(c) {
+                AnsiAction::Print(c) => c,
+                AnsiAction::SelectGraphicRendition(params, count) => {
+                    self.select_graphic_rendition(&params[..count]);
+                    continue;
+                },
+                AnsiAction::None => continue,
+            };
+            match c {
+                // control characters we handle => write them as is
+                '\n' | '\r' | '\t' | '\x08' => self.write_byte(c as u8),
 
-                // byte outside of the code page 437 range, for example characters with an umlaut
-                //  => write the block character
-                _ => self.write_byte(0xfe),
+                // everything else is translated, and characters without a code page 437
+                // equivalent end up as the block character. The translation goes straight to
+                // write_glyph, since some glyphs share their byte with a control character, e.g.
+                // '◙' is 0x0a, which write_byte would take for a newline.
+                _ => {
+                    self.snap_to_bottom();
+                    self.write_glyph(unicode_to_cp437(c));
+                    self.update_cursor();
+                },
             };
         }
     }
 
-    /// Take every row, starting at the second from the top, and write to the row above it, thus
-    /// shifting the content one row upwards
-    fn new_line(&mut self) {
-        // start at row 1 instead of row 0, because row 0 is being overwritten by row 1
-        for row in 1..BUFFER_HEIGHT {
+    /// Applies the parameters of an ANSI SGR sequence: 0 resets the colors, 30 to 37 set the
+    /// foreground and 40 to 47 the background color. No parameters at all also means reset, and
+    /// every other parameter is ignored.
+    fn select_graphic_rendition(&mut self, params: &[u8]) {
+        if params.is_empty() {
+            self.set_color(DEFAULT_FOREGROUND, DEFAULT_BACKGROUND);
+        }
+        for &param in params {
+            let (foreground, background) = self.color();
+            match param {
+                0 => self.set_color(DEFAULT_FOREGROUND, DEFAULT_BACKGROUND),
+                30..=37 => self.set_color(ansi_color(param - 30), background),
+                40..=47 => self.set_color(foreground, ansi_color(param - 40)),
+                _ => {},
+            }
+        }
+    }
+
+    /// Shifts the rows of a scroll region that is smaller than the screen one row upwards, and
+    /// blanks its bottom row. The rows outside of it have to stay where they are, so unlike for the
+    /// whole screen, we cannot just move the start of the display, and copy every row instead.
+    fn scroll_region_up(&mut self) {
+        if self.scroll_top == 0 {
+            let mut top = [BLANK; BUFFER_WIDTH];
+            for (col, screen_char) in top.iter_mut().enumerate() {
+                *screen_char = self.read_cell(0, col);
+            }
+            self.scrollback.push(top);
+        }
+        for row in self.scroll_top..self.scroll_bottom {
             for col in 0..BUFFER_WIDTH {
-                // take the character the current position [row][col], and write it to the same
-                // column in the row above it.
-                self.buffer.chars[row - 1][col].write(self.buffer.chars[row][col].read());
+                let screen_char = self.read_cell(row + 1, col);
+                self.write_cell(row, col, screen_char);
+            }
+        }
+        self.clear_row(self.scroll_bottom);
+        self.column_position = 0;
+        self.update_cursor();
+    }
+
+    /// Blanks every row of the buffer and puts the cursor back into the leftmost column of the last
+    /// row of the scroll region. The blank cells use the current color code, so a colored background
+    /// fills the whole screen.
+    pub fn clear_screen(&mut self) {
+        self.snap_to_bottom();
+        for row in 0..BUFFER_HEIGHT {
+            self.clear_row(row);
+        }
+        self.row_position = self.scroll_bottom;
+        self.column_position = 0;
+    }
+
+    /// Moves the cursor one column to the left and blanks the cell it lands on. At the start of a
+    /// line this does nothing, i.e. we do not wrap back into the previous line (yet).
+    fn backspace(&mut self) {
+        if self.column_position == 0 {
+            return;
+        }
+        self.column_position -= 1;
+        let blank = ScreenChar {
+            character: b' ',
+            color_code: self.color_code,
+        };
+        self.write_cell(self.row_position, self.column_position, blank);
+    }
+
+    /// Advances the cursor to the next multiple of TAB_WIDTH by writing blanks, so that the skipped
+    /// cells do not keep stale characters around. If the next tab stop lies beyond the end of the
+    /// row, we just start a new line, or, if we do not wrap, go to the end of the row.
+    fn tab(&mut self) {
+        let next_stop = (self.column_position / TAB_WIDTH + 1) * TAB_WIDTH;
+        if next_stop > BUFFER_WIDTH {
+            if self.wrap {
+                self.new_line();
+            } else {
+                self.column_position = BUFFER_WIDTH;
             }
+            return;
+        }
+        while self.column_position < next_stop {
+            self.write_byte(b' ');
+        }
+    }
+
+    /// Writes a string starting at the given row and column, e.g. for status bars or menus.
+    /// This does not touch column_position, so regular printing continues where it left off.
+    /// Out of range coordinates are clamped to the last row/column, and anything that would run
+    /// past the end of the row is cut off. Since the string is placed at a fixed position, control
+    /// characters like newlines are not interpreted and show up as the block character.
+    pub fn write_at(&mut self, row: usize, col: usize, s: &str) {
+        self.snap_to_bottom();
+        let row = row.min(BUFFER_HEIGHT - 1);
+        let col = col.min(BUFFER_WIDTH - 1);
+        for (i, c) in s.chars().take(BUFFER_WIDTH - col).enumerate() {
+            let screen_char = ScreenChar {
+                character: unicode_to_cp437(c),
+                color_code: self.glyph_color_code(),
+            };
+            self.write_cell(row, col + i, screen_char);
+        }
+    }
+
+    /// Writes the bytes verbatim starting at the given row and column, the way write_raw does, but
+    /// like write_at, without touching the position regular printing continues at. Out of range
+    /// coordinates are clamped to the last row/column, anything past the end of the row is cut off,
+    /// and even a newline shows up as its glyph.
+    pub fn write_raw_at(&mut self, row: usize, col: usize, bytes: &[u8]) {
+        self.snap_to_bottom();
+        let row = row.min(BUFFER_HEIGHT - 1);
+        let col = col.min(BUFFER_WIDTH - 1);
+        for (i, &byte) in bytes.iter().take(BUFFER_WIDTH - col).enumerate() {
+            let screen_char = ScreenChar {
+                character: byte,
+                color_code: self.glyph_color_code(),
+            };
+            self.write_cell(row, col + i, screen_char);
+        }
+    }
+
+    /// Draws a horizontal line of len cells, starting at the given row and column and going right.
+    /// Like write_raw_at, it is clamped to the screen.
+    pub fn draw_hline(&mut self, row: usize, col: usize, len: usize) {
+        let line = [BOX_HORIZONTAL; BUFFER_WIDTH];
+        self.write_raw_at(row, col, &line[..len.min(BUFFER_WIDTH)]);
+    }
+
+    /// Draws a vertical line of len cells, starting at the given row and column and going down.
+    /// Like write_raw_at, it is clamped to the screen.
+    pub fn draw_vline(&mut self, row: usize, col: usize, len: usize) {
+        let row = row.min(BUFFER_HEIGHT - 1);
+        for row in row..row.saturating_add(len).min(BUFFER_HEIGHT) {
+            self.write_raw_at(row, col, &[BOX_VERTICAL]);
+        }
+    }
+
+    /// Draws the border of a box with a double line, e.g. for a menu, with its top left corner at
+    /// the given row and column. width and height include the border, so the smallest box that
+    /// still looks like one is 2x2, and a 3x3 box has a single cell inside. The inside is left
+    /// alone, so draw the box first and its contents after.
+    /// Out of range coordinates are clamped to the last row/column, and a box that does not fit on
+    /// the screen is shrunk until it does, so that all four corners stay visible.
+    pub fn draw_box(&mut self, row: usize, col: usize, width: usize, height: usize) {
+        let row = row.min(BUFFER_HEIGHT - 1);
+        let col = col.min(BUFFER_WIDTH - 1);
+        let width = width.min(BUFFER_WIDTH - col);
+        let height = height.min(BUFFER_HEIGHT - row);
+        if width == 0 || height == 0 {
+            return;
         }
+        let right = col + width - 1;
+        let bottom = row + height - 1;
+        self.draw_hline(row, col, width);
+        self.draw_hline(bottom, col, width);
+        self.draw_vline(row, col, height);
+        self.draw_vline(row, right, height);
+        // the lines overlap in the corners, so the corners go last
+        self.write_raw_at(row, col, &[BOX_TOP_LEFT]);
+        self.write_raw_at(row, right, &[BOX_TOP_RIGHT]);
+        self.write_raw_at(bottom, col, &[BOX_BOTTOM_LEFT]);
+        self.write_raw_at(bottom, right, &[BOX_BOTTOM_RIGHT]);
+    }
+
+    /// Puts the heartbeat's frame into the top right cell, see set_heartbeat. Unlike write_raw_at,
+    /// this does not snap the view back to the live screen, since the timer should not take away
+    /// the history somebody is reading; while the view is scrolled up, the frame is just skipped.
+    fn draw_heartbeat(&mut self, frame: u8) {
+        if self.view_offset != 0 {
+            return;
+        }
+        let screen_char = ScreenChar {
+            character: frame,
+            color_code: self.color_code,
+        };
+        self.write_cell(0, BUFFER_WIDTH - 1, screen_char);
+    }
+
+    /// Moves the cursor to the start of the next row. In the last row of the scroll region, this
+    /// shifts the region's content one row upwards instead, see scroll_region_up. Below the region,
+    /// the last row of the screen stays the last row, and nothing scrolls.
+    /// Note that the next row is not cleared, so that a full screen program can move through its
+    /// rows without wiping them.
+    fn new_line(&mut self) {
+        if self.row_position != self.scroll_bottom {
+            self.row_position = (self.row_position + 1).min(BUFFER_HEIGHT - 1);
+            self.column_position = 0;
+            self.update_cursor();
+            return;
+        }
+        if (self.scroll_top, self.scroll_bottom) != (0, BUFFER_HEIGHT - 1) {
+            self.scroll_region_up();
+            return;
+        }
+        // save the top row before it is overwritten
+        let mut top = [BLANK; BUFFER_WIDTH];
+        for (col, screen_char) in top.iter_mut().enumerate() {
+            *screen_char = self.read_cell(0, col);
+        }
+        self.scrollback.push(top);
+
+        // every row moves up by one, and the former top row is now at the bottom
+        self.top_row = (self.top_row + 1) % BUFFER_HEIGHT;
+        self.update_display_start();
 
         // empty the bottom most row and put the cursor in the leftmost position
         self.clear_row(BUFFER_HEIGHT - 1);
         self.column_position = 0;
+        self.update_cursor();
+    }
+
+    /// Maps a row as it appears on the screen to the row of the buffer it is stored in
+    fn physical_row(&self, row: usize) -> usize {
+        return (self.top_row + row) % BUFFER_HEIGHT;
+    }
+
+    /// Reads the cell displayed at the given row and column
+    fn read_cell(&self, row: usize, col: usize) -> ScreenChar {
+        return self.read_physical(self.physical_row(row), col);
+    }
+
+    /// Writes the cell displayed at the given row and column
+    fn write_cell(&mut self, row: usize, col: usize, screen_char: ScreenChar) {
+        self.write_physical(self.physical_row(row), col, screen_char);
+    }
+
+    /// Reads the cell at the given row of the buffer, or of the back buffer if we are buffered
+    fn read_physical(&self, physical_row: usize, col: usize) -> ScreenChar {
+        if self.buffered {
+            return self.back_buffer.chars[physical_row][col];
+        }
+        return self.buffer.chars[physical_row][col].read();
+    }
+
+    /// Writes the cell at the given row of the buffer, or of the back buffer if we are buffered
+    fn write_physical(&mut self, physical_row: usize, col: usize, screen_char: ScreenChar) {
+        if self.buffered {
+            self.back_buffer.chars[physical_row][col] = screen_char;
+            self.back_buffer.dirty[physical_row] = true;
+        } else {
+            self.buffer.chars[physical_row][col].write(screen_char);
+        }
+    }
+
+    /// Switches between writing straight to the screen (the default), and writing to a back buffer
+    /// that only shows up on the screen on flush. Use the latter to redraw large parts of the
+    /// screen without flickering, but do not forget to flush.
+    /// Turning buffering off flushes whatever is still in the back buffer.
+    /// Without text mode, there is no screen to flush to, so we stay buffered for good.
+    pub fn set_buffered(&mut self, buffered: bool) {
+        if buffered == self.buffered || !text_mode() {
+            return;
+        }
+        if buffered {
+            // start out with what is on the screen, so that flush only has to copy what changed
+            for physical_row in 0..BUFFER_HEIGHT {
+                for col in 0..BUFFER_WIDTH {
+                    self.back_buffer.chars[physical_row][col] = self.buffer.chars[physical_row][col].read();
+                }
+                self.back_buffer.dirty[physical_row] = false;
+            }
+            self.buffered = true;
+        } else {
+            self.flush();
+            self.buffered = false;
+        }
+    }
+
+    /// Copies the rows of the back buffer that changed since the last flush to the screen, along
+    /// with where the screen starts and where the cursor is, which are held back while we are
+    /// buffered, too. Does nothing if we are not buffered.
+    pub fn flush(&mut self) {
+        if !self.buffered || !text_mode() {
+            return;
+        }
+        for physical_row in 0..BUFFER_HEIGHT {
+            if !self.back_buffer.dirty[physical_row] {
+                continue;
+            }
+            for col in 0..BUFFER_WIDTH {
+                self.buffer.chars[physical_row][col].write(self.back_buffer.chars[physical_row][col]);
+            }
+            self.back_buffer.dirty[physical_row] = false;
+        }
+        self.program_display_start();
+        self.program_cursor();
+    }
+
+    /// Tells the VGA to display the buffer starting at top_row, and to wrap around to the start of
+    /// the buffer after displaying its last row.
+    /// The start address simply is the linear position of the first cell of top_row. For the
+    /// wrap around, we use the line compare value, which is meant for split screens: once the VGA
+    /// finishes drawing that scanline, it continues drawing from the start of the buffer. Since
+    /// the line compare value counts scanlines and not rows, we need the height of a character
+    /// cell for that. When top_row is 0 there is nothing to wrap, so we set the largest possible
+    /// value, which lies beyond the bottom of the screen.
+    /// While we are buffered, this waits for the flush, since the rows of the screen would
+    /// otherwise move before their new content shows up.
+    fn update_display_start(&self) {
+        if !self.buffered {
+            self.program_display_start();
+        }
+    }
+
+    /// Does the actual work for update_display_start
+    fn program_display_start(&self) {
+        let start = (self.top_row * BUFFER_WIDTH) as u16;
+        crtc_write(CRTC_START_ADDRESS_HIGH, (start >> 8) as u8);
+        crtc_write(CRTC_START_ADDRESS_LOW, (start & 0xff) as u8);
+
+        let maximum_scan_line = crtc_read(CRTC_MAXIMUM_SCAN_LINE);
+        let char_height = (maximum_scan_line & 0x1f) as usize + 1;
+        let line_compare = match self.top_row {
+            0 => 0x3ff,
+            top_row => (BUFFER_HEIGHT - top_row) * char_height - 1,
+        };
+        crtc_write(CRTC_LINE_COMPARE, (line_compare & 0xff) as u8);
+        crtc_write(
+            CRTC_OVERFLOW,
+            (crtc_read(CRTC_OVERFLOW) & !0x10) | ((line_compare >> 4) & 0x10) as u8,
+        );
+        crtc_write(
+            CRTC_MAXIMUM_SCAN_LINE,
+            (maximum_scan_line & !0x40) | ((line_compare >> 3) & 0x40) as u8,
+        );
+    }
+
+    /// Scrolls the view up by the given number of rows into the scrollback, i.e. towards older
+    /// output. We cannot scroll further up than the oldest row we kept.
+    pub fn scroll_up(&mut self, lines: usize) {
+        if self.view_offset == 0 {
+            for row in 0..BUFFER_HEIGHT {
+                for col in 0..BUFFER_WIDTH {
+                    self.scrollback.live[row][col] = self.read_cell(row, col);
+                }
+            }
+        }
+        self.view_offset = (self.view_offset + lines).min(self.scrollback.len);
+        self.repaint();
+    }
+
+    /// Scrolls the view down by the given number of rows, i.e. back towards the live screen
+    pub fn scroll_down(&mut self, lines: usize) {
+        if self.view_offset == 0 {
+            return;
+        }
+        self.view_offset = self.view_offset.saturating_sub(lines);
+        self.repaint();
+    }
+
+    /// Returns to the live screen if the view is scrolled up. Every write calls this first, so that
+    /// we never write into a screen showing the history.
+    fn snap_to_bottom(&mut self) {
+        if self.view_offset != 0 {
+            self.view_offset = 0;
+            self.repaint();
+        }
+    }
+
+    /// Redraws the screen for the current view_offset. Think of the history and the live screen as
+    /// one long list of rows, with the history first; the screen shows BUFFER_HEIGHT consecutive
+    /// rows of that list, ending view_offset rows before its end.
+    fn repaint(&mut self) {
+        let first = self.scrollback.len - self.view_offset;
+        for row in 0..BUFFER_HEIGHT {
+            let i = first + row;
+            let source = if i < self.scrollback.len {
+                self.scrollback.get(i)
+            } else {
+                &self.scrollback.live[i - self.scrollback.len]
+            };
+            // we cannot use write_cell here, since source still borrows the scrollback
+            let physical_row = self.physical_row(row);
+            for (col, screen_char) in source.iter().enumerate() {
+                if self.buffered {
+                    self.back_buffer.chars[physical_row][col] = *screen_char;
+                } else {
+                    self.buffer.chars[physical_row][col].write(*screen_char);
+                }
+            }
+            self.back_buffer.dirty[physical_row] |= self.buffered;
+        }
+    }
+
+    /// Moves the blinking hardware cursor to the cell we would be writing to next. The VGA does not
+    /// know anything about our Writer, so we have to tell its CRT controller the linear position
+    /// (row * BUFFER_WIDTH + col) of that cell in the buffer.
+    /// While we are buffered, the cursor only moves on flush.
+    pub fn update_cursor(&self) {
+        if !self.buffered {
+            self.program_cursor();
+        }
+    }
+
+    /// Does the actual work for update_cursor
+    fn program_cursor(&self) {
+        // once a line is full, the cursor would sit just past the last column, so we keep it in
+        // the last column until the next character wraps the line
+        let col = self.column_position.min(BUFFER_WIDTH - 1);
+        let position = (self.physical_row(self.row_position) * BUFFER_WIDTH + col) as u16;
+        crtc_write(CRTC_CURSOR_LOCATION_HIGH, (position >> 8) as u8);
+        crtc_write(CRTC_CURSOR_LOCATION_LOW, (position & 0xff) as u8);
+    }
+
+    /// Turns on the hardware cursor and sets its shape, where start and end are the first and last
+    /// scanline (0 to 15, counted from the top of the cell) that the cursor covers.
+    /// For example, (14, 15) gives the classic underline cursor and (0, 15) a full block.
+    pub fn enable_cursor(&mut self, start: u8, end: u8) {
+        // the upper bits of these registers control other things, so we only replace the bits
+        // holding the scanlines
+        crtc_write(
+            CRTC_CURSOR_START,
+            (crtc_read(CRTC_CURSOR_START) & 0xc0) | (start & 0x1f),
+        );
+        crtc_write(CRTC_CURSOR_END, (crtc_read(CRTC_CURSOR_END) & 0xe0) | (end & 0x1f));
+        self.update_cursor();
+    }
+
+    /// Turns off the hardware cursor by setting the "cursor disable" bit. The rest of the register
+    /// is left alone, so the cursor keeps its shape for when it is turned back on.
+    pub fn disable_cursor(&mut self) {
+        crtc_write(CRTC_CURSOR_START, crtc_read(CRTC_CURSOR_START) | CURSOR_DISABLE);
     }
 
     /// Overwrite the characters in a given row with the blank character
     fn clear_row(&mut self, row: usize) {
+        let blank = ScreenChar {
+            character: b' ',
+            color_code: self.color_code,
+        };
         for col in 0..BUFFER_WIDTH {
-            self.buffer.chars[row][col].write(ScreenChar {
-                character: b' ',
-                color_code: self.color_code,
-            });
+            self.write_cell(row, col, blank);
         }
     }
 }
@@ -249,7 +1761,733 @@ fn test_println_output() {
     for (i, c) in s.chars().enumerate() {
         // read the buffer and check, character for character, that it actually equals the
         // character in our test string
-        let screen_char = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 2][i].read();
+        let (character, _, _) = WRITER.lock().read_char(BUFFER_HEIGHT - 2, i).unwrap();
+        assert_eq!(char::from(character), c);
+    }
+}
+
+// Test that changing the color only affects the characters written after the change
+#[test_case]
+fn test_set_color() {
+    let mut writer = WRITER.lock();
+    let (foreground, background) = writer.color();
+
+    // start on a fresh line, so we know that our characters land in columns 0 and 1
+    writer.write_byte(b'\n');
+    writer.write_byte(b'a');
+    writer.set_color(Color::Red, Color::Black);
+    writer.write_byte(b'b');
+
+    // restore the previous color so the following tests are not affected
+    writer.set_color(foreground, background);
+    assert_eq!(writer.color(), (foreground, background));
+
+    let first = writer.read_cell(BUFFER_HEIGHT - 1, 0);
+    let second = writer.read_cell(BUFFER_HEIGHT - 1, 1);
+    assert_eq!(first.color_code, ColorCode::new(foreground, background));
+    assert_eq!(second.color_code, ColorCode::new(Color::Red, Color::Black));
+    assert_ne!(first.color_code, second.color_code);
+}
+
+// Test that clearing the screen leaves nothing but blank cells behind
+#[test_case]
+fn test_clear_screen() {
+    println!("some text that should disappear");
+    clear_screen();
+
+    let writer = WRITER.lock();
+    assert_eq!(writer.column_position, 0);
+    for row in 0..BUFFER_HEIGHT {
+        for col in 0..BUFFER_WIDTH {
+            assert_eq!(writer.read_cell(row, col).character, b' ');
+        }
+    }
+}
+
+// Test that a backspace erases the previous character, so the next one takes its place
+#[test_case]
+fn test_backspace() {
+    let mut writer = WRITER.lock();
+    writer.write_string("\nabc\x08X");
+    for (i, c) in "abX ".chars().enumerate() {
+        let screen_char = writer.read_cell(BUFFER_HEIGHT - 1, i);
+        assert_eq!(char::from(screen_char.character), c);
+    }
+
+    // backspacing at the start of a line must not underflow the column
+    writer.write_string("\n\x08");
+    assert_eq!(writer.column_position, 0);
+}
+
+// Test that a tab moves the cursor to the next tab stop
+#[test_case]
+fn test_tab() {
+    let mut writer = WRITER.lock();
+    writer.write_string("\na\tb");
+    let screen_char = writer.read_cell(BUFFER_HEIGHT - 1, TAB_WIDTH);
+    assert_eq!(char::from(screen_char.character), 'b');
+    for col in 1..TAB_WIDTH {
+        let screen_char = writer.read_cell(BUFFER_HEIGHT - 1, col);
+        assert_eq!(char::from(screen_char.character), ' ');
+    }
+}
+
+// Test that the hardware cursor follows the column we write to next
+#[test_case]
+fn test_update_cursor() {
+    let mut writer = WRITER.lock();
+    writer.write_string("\nabc");
+    let position = (crtc_read(CRTC_CURSOR_LOCATION_HIGH) as usize) << 8 | crtc_read(CRTC_CURSOR_LOCATION_LOW) as usize;
+    assert_eq!(position, writer.physical_row(BUFFER_HEIGHT - 1) * BUFFER_WIDTH + 3);
+}
+
+// Test that rows scrolled off the top can be scrolled back into view, and that writing snaps the
+// view back to the live screen
+#[test_case]
+fn test_scrollback() {
+    use core::fmt::Write;
+
+    // reads the number printed at the start of a row
+    fn number_in_row(writer: &Writer, row: usize) -> usize {
+        let mut n = 0;
+        for col in 0..BUFFER_WIDTH {
+            match writer.read_cell(row, col).character {
+                digit @ b'0'..=b'9' => n = n * 10 + (digit - b'0') as usize,
+                _ => break,
+            }
+        }
+        return n;
+    }
+
+    let mut writer = WRITER.lock();
+    for i in 0..200 {
+        writeln!(writer, "{}", i).unwrap();
+    }
+    // the bottom row is empty after the last newline, so the top row shows line 200 - 24
+    assert_eq!(number_in_row(&writer, 0), 200 - (BUFFER_HEIGHT - 1));
+
+    writer.scroll_up(50);
+    assert_eq!(number_in_row(&writer, 0), 200 - (BUFFER_HEIGHT - 1) - 50);
+    assert_eq!(number_in_row(&writer, BUFFER_HEIGHT - 1), 200 - 50);
+
+    writer.scroll_down(20);
+    assert_eq!(number_in_row(&writer, 0), 200 - (BUFFER_HEIGHT - 1) - 30);
+
+    writer.write_byte(b'\n');
+    assert_eq!(writer.view_offset, 0);
+    assert_eq!(number_in_row(&writer, BUFFER_HEIGHT - 3), 199);
+}
+
+// Test that positioned output lands where we asked for it and does not move the writer's column
+#[test_case]
+fn test_write_at() {
+    let mut writer = WRITER.lock();
+    writer.write_string("\nab");
+    writer.write_at(0, 10, "status");
+    assert_eq!(writer.column_position, 2);
+    for (i, c) in "status".chars().enumerate() {
+        let screen_char = writer.read_cell(0, 10 + i);
         assert_eq!(char::from(screen_char.character), c);
     }
+
+    // starting past the right edge is clamped into the last column, and the rest is cut off
+    writer.write_at(BUFFER_HEIGHT + 5, BUFFER_WIDTH + 5, "xyz");
+    let screen_char = writer.read_cell(BUFFER_HEIGHT - 1, BUFFER_WIDTH - 1);
+    assert_eq!(char::from(screen_char.character), 'x');
+    assert_eq!(writer.column_position, 2);
+}
+
+// A 3x3 box has its corners in the corners, double lines in between, and leaves the cell inside
+// alone. A box that sticks out of the screen is shrunk to fit.
+#[test_case]
+fn test_draw_box() {
+    let mut writer = WRITER.lock();
+    let column_position = writer.column_position;
+    writer.write_at(1, 1, "x");
+    writer.draw_box(0, 0, 3, 3);
+    let character = |writer: &Writer, row, col| writer.read_char(row, col).unwrap().0;
+    assert_eq!(character(&writer, 0, 0), BOX_TOP_LEFT);
+    assert_eq!(character(&writer, 0, 2), BOX_TOP_RIGHT);
+    assert_eq!(character(&writer, 2, 0), BOX_BOTTOM_LEFT);
+    assert_eq!(character(&writer, 2, 2), BOX_BOTTOM_RIGHT);
+    assert_eq!(character(&writer, 0, 1), BOX_HORIZONTAL);
+    assert_eq!(character(&writer, 2, 1), BOX_HORIZONTAL);
+    assert_eq!(character(&writer, 1, 0), BOX_VERTICAL);
+    assert_eq!(character(&writer, 1, 2), BOX_VERTICAL);
+    assert_eq!(character(&writer, 1, 1), b'x');
+    assert_eq!(writer.column_position, column_position);
+
+    writer.draw_box(0, BUFFER_WIDTH - 2, 10, 3);
+    assert_eq!(character(&writer, 0, BUFFER_WIDTH - 2), BOX_TOP_LEFT);
+    assert_eq!(character(&writer, 0, BUFFER_WIDTH - 1), BOX_TOP_RIGHT);
+    assert_eq!(character(&writer, 2, BUFFER_WIDTH - 1), BOX_BOTTOM_RIGHT);
+}
+
+// Every line gets half of the room it leaves on the screen as padding, and a line that is too wide
+// gets none
+#[test_case]
+fn test_write_centered() {
+    let mut writer = WRITER.lock();
+    writer.write_byte(b'\n');
+    writer.write_centered("hello\nab");
+    let line = |writer: &Writer, row| {
+        let mut line = [0; BUFFER_WIDTH];
+        for (col, byte) in line.iter_mut().enumerate() {
+            *byte = writer.read_char(row, col).unwrap().0;
+        }
+        return line;
+    };
+    let hello = line(&writer, BUFFER_HEIGHT - 3);
+    assert!(hello[..37].iter().all(|byte| *byte == b' '));
+    assert_eq!(&hello[37..42], b"hello");
+    let ab = line(&writer, BUFFER_HEIGHT - 2);
+    assert!(ab[..39].iter().all(|byte| *byte == b' '));
+    assert_eq!(&ab[39..41], b"ab");
+    assert_eq!(writer.position(), (BUFFER_HEIGHT - 1, 0));
+
+    let too_wide = [b'x'; BUFFER_WIDTH + 2];
+    writer.write_centered(core::str::from_utf8(&too_wide).unwrap());
+    assert_eq!(line(&writer, BUFFER_HEIGHT - 3), [b'x'; BUFFER_WIDTH]);
+}
+
+// Half of a 10 cell bar is 5 filled cells, and setting it again redraws the same cells instead of
+// writing more of them
+#[test_case]
+fn test_progress_bar() {
+    let cells = || {
+        let writer = WRITER.lock();
+        let mut cells = [0; 12];
+        for (i, cell) in cells.iter_mut().enumerate() {
+            *cell = writer.read_char(0, 19 + i).unwrap().0;
+        }
+        return (cells, writer.position());
+    };
+    let bar = ProgressBar::new(0, 20, 10);
+    WRITER.lock().write_at(0, 19, "<          >");
+    let (_, position) = cells();
+
+    bar.set(0.8);
+    bar.set(0.5);
+    let mut expected = [PROGRESS_EMPTY; 12];
+    expected[1..6].fill(PROGRESS_FILLED);
+    expected[0] = b'<';
+    expected[11] = b'>';
+    assert_eq!(cells(), (expected, position));
+
+    bar.set(1.5);
+    expected[1..11].fill(PROGRESS_FILLED);
+    assert_eq!(cells(), (expected, position));
+}
+
+// Every color survives the trip to its value and back, and there is no 17th color
+#[test_case]
+fn test_color_from_u8() {
+    for (n, color) in Color::ALL.iter().enumerate() {
+        assert_eq!(*color as usize, n);
+        assert_eq!(Color::from_u8(n as u8), Some(*color));
+    }
+    assert_eq!(Color::from_u8(16), None);
+    assert_eq!(Color::from_u8(u8::MAX), None);
+    assert_eq!(Color::from_nibble(0x1f), Color::White);
+}
+
+// Test that colored_println! writes its line in the requested color and restores the old one
+#[test_case]
+fn test_colored_println() {
+    let s = "colored";
+    let (foreground, background) = WRITER.lock().color();
+    colored_println!(Color::Red, "{}", s);
+
+    let writer = WRITER.lock();
+    assert_eq!(writer.color(), (foreground, background));
+    for (i, c) in s.chars().enumerate() {
+        let screen_char = writer.read_cell(BUFFER_HEIGHT - 2, i);
+        assert_eq!(char::from(screen_char.character), c);
+        assert_eq!(screen_char.color_code, ColorCode::new(Color::Red, background));
+    }
+}
+
+// Test that two differently colored pieces of one line keep their own colors, and that the
+// writer's colors are the same as before afterwards
+#[test_case]
+fn test_write_colored() {
+    let before = WRITER.lock().color();
+    write_colored("\nab", Color::Red, Color::Black);
+    cprint!(Color::Green, Color::Blue, "{}", "cd");
+
+    let writer = WRITER.lock();
+    assert_eq!(writer.color(), before);
+    for (i, (c, foreground, background)) in [
+        ('a', Color::Red, Color::Black),
+        ('b', Color::Red, Color::Black),
+        ('c', Color::Green, Color::Blue),
+        ('d', Color::Green, Color::Blue),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        assert_eq!(
+            writer.read_char(BUFFER_HEIGHT - 1, i),
+            Some((c as u8, foreground, background))
+        );
+    }
+}
+
+// Test that ANSI SGR sequences change the color instead of being printed, that a reset restores
+// the default colors, and that sequences may be split across writes
+#[test_case]
+fn test_ansi_colors() {
+    let mut writer = WRITER.lock();
+    let read = |writer: &Writer, col: usize| writer.read_cell(BUFFER_HEIGHT - 1, col);
+
+    writer.write_string("\n\x1b[31mr\x1b[0md");
+    assert_eq!(read(&writer, 0).character, b'r');
+    assert_eq!(
+        read(&writer, 0).color_code,
+        ColorCode::new(Color::Red, DEFAULT_BACKGROUND)
+    );
+    assert_eq!(read(&writer, 1).character, b'd');
+    assert_eq!(
+        read(&writer, 1).color_code,
+        ColorCode::new(DEFAULT_FOREGROUND, DEFAULT_BACKGROUND)
+    );
+
+    // a sequence split across two writes still applies
+    writer.write_string("\x1b[4");
+    writer.write_string("2mg\x1b[m");
+    assert_eq!(read(&writer, 2).character, b'g');
+    assert_eq!(
+        read(&writer, 2).color_code,
+        ColorCode::new(DEFAULT_FOREGROUND, Color::Green)
+    );
+
+    // a truncated sequence ending in something other than m is dropped entirely
+    writer.write_string("\n\x1b[31");
+    writer.write_string("xy");
+    assert_eq!(writer.column_position, 1);
+    assert_eq!(read(&writer, 0).character, b'y');
+    assert_eq!(
+        read(&writer, 0).color_code,
+        ColorCode::new(DEFAULT_FOREGROUND, DEFAULT_BACKGROUND)
+    );
+}
+
+// Test that unicode characters are translated into their code page 437 bytes, including
+// characters that take up more than one byte in UTF-8
+#[test_case]
+fn test_unicode_to_cp437() {
+    assert_eq!(unicode_to_cp437('A'), b'A');
+    assert_eq!(unicode_to_cp437('ä'), 0x84);
+    assert_eq!(unicode_to_cp437('é'), 0x82);
+    assert_eq!(unicode_to_cp437('£'), 0x9c);
+    assert_eq!(unicode_to_cp437('°'), 0xf8);
+    assert_eq!(unicode_to_cp437('╔'), 0xc9);
+    assert_eq!(unicode_to_cp437('♥'), 0x03);
+    assert_eq!(unicode_to_cp437('€'), 0xfe);
+    assert_eq!(unicode_to_cp437('\x07'), 0xfe);
+
+    let mut writer = WRITER.lock();
+    writer.write_string("\nö═x");
+    assert_eq!(writer.column_position, 3);
+    for (i, byte) in [0x94, 0xcd, b'x'].iter().enumerate() {
+        assert_eq!(writer.read_cell(BUFFER_HEIGHT - 1, i).character, *byte);
+    }
+}
+
+// Test that read_char returns the character and both colors of a cell, and None outside of the
+// buffer
+#[test_case]
+fn test_read_char() {
+    let mut writer = WRITER.lock();
+    let (foreground, background) = writer.color();
+    writer.set_color(Color::LightGreen, Color::Blue);
+    writer.write_string("\nq");
+    writer.set_color(foreground, background);
+
+    assert_eq!(
+        writer.read_char(BUFFER_HEIGHT - 1, 0),
+        Some((b'q', Color::LightGreen, Color::Blue))
+    );
+    assert_eq!(writer.read_char(BUFFER_HEIGHT, 0), None);
+    assert_eq!(writer.read_char(0, BUFFER_WIDTH), None);
+}
+
+// Test that scrolling through the row ring keeps every line at the logical position it would have
+// had when copying the rows, and that the VGA is told where the top row is stored
+#[test_case]
+fn test_new_line_ring() {
+    use core::fmt::Write;
+
+    let mut writer = WRITER.lock();
+    for i in 0..1000 {
+        writeln!(writer, "{:04}", i).unwrap();
+    }
+    let row_text = |writer: &Writer, row: usize| {
+        let mut text = [0; 4];
+        for (col, character) in text.iter_mut().enumerate() {
+            *character = writer.read_cell(row, col).character;
+        }
+        text
+    };
+    assert_eq!(&row_text(&writer, BUFFER_HEIGHT - 2), b"0999");
+    assert_eq!(&row_text(&writer, 0), b"0976");
+    assert_eq!(writer.read_cell(BUFFER_HEIGHT - 1, 0).character, b' ');
+
+    let start = (crtc_read(CRTC_START_ADDRESS_HIGH) as usize) << 8 | crtc_read(CRTC_START_ADDRESS_LOW) as usize;
+    assert_eq!(start, writer.physical_row(0) * BUFFER_WIDTH);
+}
+
+// Test that a carriage return lets us overwrite the current line, and that CR LF is just a newline
+#[test_case]
+fn test_carriage_return() {
+    let mut writer = WRITER.lock();
+    writer.write_string("\n1234\r");
+    writer.write_string("ab");
+    for (i, c) in "ab34".chars().enumerate() {
+        assert_eq!(char::from(writer.read_cell(BUFFER_HEIGHT - 1, i).character), c);
+    }
+
+    writer.write_string("\r\nx");
+    assert_eq!(writer.column_position, 1);
+    assert_eq!(writer.read_cell(BUFFER_HEIGHT - 2, 0).character, b'a');
+    assert_eq!(writer.read_cell(BUFFER_HEIGHT - 1, 0).character, b'x');
+}
+
+// Glyphs whose code page 437 byte is also a control character are drawn, instead of acting like
+// that control character: '◙' is 0x0a (newline), and '♪' is 0x0d (carriage return)
+#[test_case]
+fn test_control_glyphs() {
+    let mut writer = WRITER.lock();
+    writer.write_string("\n◙♪");
+    assert_eq!(writer.read_cell(BUFFER_HEIGHT - 1, 0).character, 0x0a);
+    assert_eq!(writer.read_cell(BUFFER_HEIGHT - 1, 1).character, 0x0d);
+    assert_eq!(writer.position(), (BUFFER_HEIGHT - 1, 2));
+}
+
+// Test that write_raw puts box drawing and control bytes on the screen unchanged, where
+// write_string would translate them, and that a newline still starts a new line
+#[test_case]
+fn test_write_raw() {
+    let mut writer = WRITER.lock();
+    writer.write_raw(b"\n\xc9\xcd\xbb\x08\r\nx");
+    for (i, byte) in [0xc9, 0xcd, 0xbb, 0x08, b'\r'].iter().enumerate() {
+        assert_eq!(writer.read_cell(BUFFER_HEIGHT - 2, i).character, *byte);
+    }
+    assert_eq!(writer.read_cell(BUFFER_HEIGHT - 1, 0).character, b'x');
+    assert_eq!(writer.column_position, 1);
+}
+
+// Strings that take write_string's fast path come out exactly like they do character by character,
+// and everything else does not take it
+#[test_case]
+fn test_write_string_fast_path() {
+    let strings = [
+        "plain ASCII",
+        "two\nlines",
+        "Grüße ½ ♥",
+        "tab\tand back\x08space",
+        "\x1b[31mred\x1b[0m and not",
+        "crlf\r\n",
+    ];
+    let mut writer = WRITER.lock();
+    // the last three rows, the position and the color after writing s, starting from a blank line
+    let mut render = |s: &str, fast: bool| {
+        writer.write_string("\n\n\n");
+        if fast {
+            writer.write_string(s);
+        } else {
+            writer.write_chars(s);
+        }
+        let mut rows = [[BLANK; BUFFER_WIDTH]; 3];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (col, cell) in row.iter_mut().enumerate() {
+                *cell = writer.read_cell(BUFFER_HEIGHT - 3 + i, col);
+            }
+        }
+        return (rows, writer.position(), writer.color());
+    };
+    for s in strings {
+        assert!(render(s, true) == render(s, false), "write_string differs for {:?}", s);
+    }
+    drop(render);
+
+    assert!(writer.ascii_fast_path("plain ASCII\n"));
+    assert!(!writer.ascii_fast_path("Grüße"));
+    assert!(!writer.ascii_fast_path("tab\t"));
+    assert!(!writer.ascii_fast_path("\x1b[31m"));
+    // an escape sequence that is split across two writes still needs the parser for its rest
+    writer.write_string("\x1b[3");
+    assert!(!writer.ascii_fast_path("1mred"));
+    writer.write_string("1mred\x1b[0m");
+    assert_eq!(writer.color(), (DEFAULT_FOREGROUND, DEFAULT_BACKGROUND));
+}
+
+/// Vector of the software interrupt test_println_in_interrupt_handler uses
+#[cfg(test)]
+const PRINTING_TEST_VECTOR: u8 = 203;
+
+#[cfg(test)]
+extern "x86-interrupt" fn printing_test_handler(_stack_frame: x86_64::structures::idt::InterruptStackFrame) {
+    println!("printed from an interrupt handler");
+}
+
+/// Formats as nothing, but remembers whether interrupts were enabled while it was being formatted,
+/// i.e. in the middle of printing, while the WRITER is locked
+#[cfg(test)]
+struct InterruptsDuringPrint<'a>(&'a core::cell::Cell<bool>);
+
+#[cfg(test)]
+impl fmt::Display for InterruptsDuringPrint<'_> {
+    fn fmt(&self, _f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.set(x86_64::instructions::interrupts::are_enabled());
+        return Ok(());
+    }
+}
+
+// No (hardware) interrupt can arrive in the middle of printing, so no interrupt handler can find
+// the WRITER locked, and a handler can print itself. Note that we cannot test this by printing
+// from a handler for a software interrupt raised mid-print: disabling interrupts does not stop
+// software interrupts, so that would deadlock.
+#[test_case]
+fn test_println_in_interrupt_handler() {
+    let enabled = core::cell::Cell::new(true);
+    println!("mid-print{}", InterruptsDuringPrint(&enabled));
+    assert!(!enabled.get());
+    assert!(x86_64::instructions::interrupts::are_enabled());
+
+    crate::interrupts::register(PRINTING_TEST_VECTOR, printing_test_handler);
+    unsafe {
+        core::arch::asm!("int {}", const PRINTING_TEST_VECTOR, options(nomem, nostack));
+    }
+    let expected = "printed from an interrupt handler";
+    for (i, c) in expected.chars().enumerate() {
+        let (character, _, _) = WRITER.lock().read_char(BUFFER_HEIGHT - 2, i).unwrap();
+        assert_eq!(char::from(character), c);
+    }
+}
+
+// Writing while buffered leaves the screen alone until the flush, and reading back sees what was
+// written, not what is on the screen
+#[test_case]
+fn test_buffered() {
+    let text = "buffered";
+    let mut writer = WRITER.lock();
+    writer.set_buffered(true);
+    // the newline turns the top row into the new bottom row
+    let physical_row = writer.physical_row(0);
+    let mut on_screen = [BLANK; BUFFER_WIDTH];
+    for (col, screen_char) in on_screen.iter_mut().enumerate() {
+        *screen_char = writer.buffer.chars[physical_row][col].read();
+    }
+
+    writer.write_string("\n");
+    writer.write_string(text);
+    assert_eq!(writer.physical_row(BUFFER_HEIGHT - 1), physical_row);
+    for (col, c) in text.bytes().enumerate() {
+        assert_eq!(writer.read_cell(BUFFER_HEIGHT - 1, col).character, c);
+        assert_eq!(writer.buffer.chars[physical_row][col].read(), on_screen[col]);
+    }
+
+    writer.flush();
+    for (col, c) in text.bytes().enumerate() {
+        assert_eq!(writer.buffer.chars[physical_row][col].read().character, c);
+    }
+
+    // without buffering, writes show up right away again
+    writer.set_buffered(false);
+    writer.write_string("!");
+    assert_eq!(writer.buffer.chars[physical_row][text.len()].read().character, b'!');
+}
+
+// A blinking character has the top bit of its color code set, and the colors are otherwise the same
+#[test_case]
+fn test_set_blink() {
+    let mut writer = WRITER.lock();
+    writer.write_string("\n");
+    writer.set_blink(true);
+    writer.write_string("!");
+    writer.set_blink(false);
+    writer.write_string("?");
+
+    let blinking = writer.read_cell(BUFFER_HEIGHT - 1, 0).color_code;
+    let steady = writer.read_cell(BUFFER_HEIGHT - 1, 1).color_code;
+    assert_eq!(blinking.0 & BLINK_BIT, BLINK_BIT);
+    assert_eq!(blinking, writer.color_code.with_blink());
+    assert_eq!(steady, writer.color_code);
+}
+
+// A screenshot starts with the banner, and has one line per row, with the last line we printed in
+// the second to last row and code page 437 glyphs as dots
+#[test_case]
+fn test_screenshot() {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    println!("test_screenshot \u{263a} line");
+    let mut text = String::new();
+    WRITER.lock().write_screenshot(&mut text).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), BUFFER_HEIGHT + 2);
+    assert_eq!(lines[0], SCREENSHOT_BANNER);
+    assert_eq!(lines[BUFFER_HEIGHT + 1], SCREENSHOT_END);
+    assert!(lines[1..=BUFFER_HEIGHT].iter().all(|line| line.len() == BUFFER_WIDTH));
+    assert!(lines[BUFFER_HEIGHT - 1].starts_with("test_screenshot . line "));
+
+    // the same goes to SERIAL1, which we cannot read back, but which shows up in the test's output
+    screenshot();
+}
+
+// Without text mode, println! leaves the screen alone, and warns (once) that it prints to SERIAL1
+// instead
+#[test_case]
+fn test_no_text_mode() {
+    let last_row = |writer: &Writer| -> [ScreenChar; BUFFER_WIDTH] {
+        return core::array::from_fn(|col| writer.read_cell(BUFFER_HEIGHT - 1, col));
+    };
+    println!();
+    let before = last_row(&WRITER.lock());
+
+    set_text_mode(false);
+    println!("test_no_text_mode: this goes to SERIAL1");
+    set_text_mode(true);
+
+    assert_eq!(last_row(&WRITER.lock()), before);
+    assert!(WARNED_NO_TEXT_MODE.load(Ordering::Relaxed));
+}
+
+// After set_position, characters go to that position, and a newline moves to the next row without
+// scrolling
+#[test_case]
+fn test_set_position() {
+    let mut writer = WRITER.lock();
+    writer.set_position(5, 10);
+    assert_eq!(writer.position(), (5, 10));
+    let top_row = writer.top_row;
+    writer.write_string("x\ny");
+    assert_eq!(writer.read_char(5, 10).unwrap().0, b'x');
+    assert_eq!(writer.read_char(6, 0).unwrap().0, b'y');
+    assert_eq!(writer.position(), (6, 1));
+    assert_eq!(writer.top_row, top_row);
+
+    // every other test expects to print to a fresh last row
+    writer.set_position(BUFFER_HEIGHT, 0);
+    assert_eq!(writer.position(), (BUFFER_HEIGHT - 1, 0));
+    writer.write_byte(b'\n');
+}
+
+// With the scroll region set to all rows but the first and the last one, printing more lines than
+// fit into it scrolls only the region, and the header and footer stay where they are
+#[test_case]
+fn test_set_scroll_region() {
+    use core::fmt::Write;
+
+    let mut writer = WRITER.lock();
+    writer.write_at(0, 0, "header");
+    writer.write_at(BUFFER_HEIGHT - 1, 0, "footer");
+    let top_row = writer.top_row;
+    writer.set_scroll_region(1, BUFFER_HEIGHT - 2);
+    assert_eq!(writer.scroll_region(), (1, BUFFER_HEIGHT - 2));
+    assert_eq!(writer.position(), (BUFFER_HEIGHT - 2, 0));
+    for i in 0..BUFFER_HEIGHT + 5 {
+        write!(writer, "\nline {}", i).unwrap();
+    }
+    let character = |writer: &Writer, row, col| writer.read_char(row, col).unwrap().0;
+    for (i, byte) in b"header".iter().enumerate() {
+        assert_eq!(character(&writer, 0, i), *byte);
+    }
+    for (i, byte) in b"footer".iter().enumerate() {
+        assert_eq!(character(&writer, BUFFER_HEIGHT - 1, i), *byte);
+    }
+    assert_eq!(character(&writer, BUFFER_HEIGHT - 2, 6), b'9');
+    assert_eq!(character(&writer, BUFFER_HEIGHT - 3, 6), b'8');
+    assert_eq!(character(&writer, 1, 5), b'7');
+    assert_eq!(writer.top_row, top_row);
+
+    // every other test expects the whole screen to scroll, and to print to a fresh last row
+    writer.set_scroll_region(0, BUFFER_HEIGHT);
+    assert_eq!(writer.scroll_region(), (0, BUFFER_HEIGHT - 1));
+    writer.write_byte(b'\n');
+}
+
+// Every HEARTBEAT_INTERVAL_TICKS, the top right cell shows the next frame, and nothing else moves.
+// Interrupts are disabled, so that the actual timer cannot draw a frame in between.
+#[test_case]
+fn test_heartbeat() {
+    let corner = || WRITER.lock().read_char(0, BUFFER_WIDTH - 1).unwrap().0;
+    crate::interrupts::without_interrupts(|| {
+        let position = WRITER.lock().position();
+        set_heartbeat(true);
+        heartbeat(4 * HEARTBEAT_INTERVAL_TICKS);
+        assert_eq!(corner(), b'|');
+        heartbeat(4 * HEARTBEAT_INTERVAL_TICKS + 1);
+        assert_eq!(corner(), b'|');
+        heartbeat(5 * HEARTBEAT_INTERVAL_TICKS);
+        assert_eq!(corner(), b'/');
+        heartbeat(7 * HEARTBEAT_INTERVAL_TICKS);
+        assert_eq!(corner(), b'\\');
+        set_heartbeat(false);
+        heartbeat(8 * HEARTBEAT_INTERVAL_TICKS);
+        assert_eq!(corner(), b'\\');
+        assert_eq!(WRITER.lock().position(), position);
+    });
+}
+
+// Without wrapping, a line that is too long is cut off at the end of the row, and the next line
+// starts with the next newline
+#[test_case]
+fn test_set_wrap() {
+    let mut writer = WRITER.lock();
+    writer.write_byte(b'\n');
+    let top_row = writer.top_row;
+    writer.set_wrap(false);
+    for i in 0..90 {
+        writer.write_byte(b'0' + (i % 10) as u8);
+    }
+    assert_eq!(writer.top_row, top_row);
+    assert_eq!(writer.column_position, BUFFER_WIDTH);
+    for col in 0..BUFFER_WIDTH {
+        assert_eq!(
+            writer.read_char(BUFFER_HEIGHT - 1, col).unwrap().0,
+            b'0' + (col % 10) as u8
+        );
+    }
+
+    writer.write_string("\nnext");
+    writer.set_wrap(true);
+    assert_eq!(writer.top_row, (top_row + 1) % BUFFER_HEIGHT);
+    assert_eq!(writer.read_char(BUFFER_HEIGHT - 1, 0).unwrap().0, b'n');
+}
+
+// eprintln! prints in white on red, and leaves the colors as they were, including those of the new
+// line. What it mirrors to SERIAL2 cannot be read back, so that shows up in the test's output only.
+#[test_case]
+fn test_eprintln() {
+    let s = "test_eprintln";
+    let color_code = WRITER.lock().color_code;
+    eprintln!("{}", s);
+
+    let writer = WRITER.lock();
+    assert_eq!(writer.color_code, color_code);
+    for (i, c) in s.chars().enumerate() {
+        let screen_char = writer.read_cell(BUFFER_HEIGHT - 2, i);
+        assert_eq!(char::from(screen_char.character), c);
+        assert_eq!(
+            screen_char.color_code,
+            ColorCode::new(ERROR_FOREGROUND, ERROR_BACKGROUND)
+        );
+    }
+    assert_eq!(writer.read_cell(BUFFER_HEIGHT - 1, 0).color_code, color_code);
+}
+
+// Disabling the cursor only sets the disable bit, and keeps the shape it had
+#[test_case]
+fn test_disable_cursor_keeps_shape() {
+    let mut writer = WRITER.lock();
+    writer.enable_cursor(14, 15);
+    let start = crtc_read(CRTC_CURSOR_START);
+    assert_eq!(start & CURSOR_DISABLE, 0);
+    writer.disable_cursor();
+    assert_eq!(crtc_read(CRTC_CURSOR_START), start | CURSOR_DISABLE);
+    writer.enable_cursor(14, 15);
+    assert_eq!(crtc_read(CRTC_CURSOR_START), start);
 }
@@ -6,12 +6,14 @@
 
 use core::format_args;
 use core::panic::PanicInfo;
-use tdos::println;
+use tdos::vga_buffer::BUFFER_HEIGHT;
+use tdos::{assert_screen_contains, println};
 
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
+    tdos::init_output();
     test_main();
-    loop {}
+    tdos::hlt_loop();
 }
 
 #[panic_handler]
@@ -23,3 +25,10 @@ fn panic(info: &PanicInfo) -> ! {
 fn test_println() {
     println!("testing println output");
 }
+
+// The line println! wrote is on the screen, right above the new line it started
+#[test_case]
+fn test_println_on_screen() {
+    println!("testing println on the screen");
+    assert_screen_contains!(BUFFER_HEIGHT - 2, "testing println on the screen");
+}
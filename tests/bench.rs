@@ -0,0 +1,77 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(tdos::test_runner::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use tdos::bench::{black_box, Bench};
+use tdos::vga_buffer::{unicode_to_cp437, BUFFER_WIDTH, WRITER};
+
+// Benchmarks measure time in TSC cycles, and tdos::init measures how long a cycle is, so that they
+// can also report nanoseconds. So we need tdos::init, and the boot info for tdos::init.
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    tdos::init(boot_info);
+    test_main();
+    tdos::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    tdos::test_runner::test_panic_handler(info)
+}
+
+/// A line that fills the whole row. The writer only moves to the next row once another character
+/// is written, so every write_string after the first one starts with a new_line.
+const FULL_LINE: &str = "01234567890123456789012345678901234567890123456789012345678901234567890123456789";
+
+/// FULL_LINE, but with an ü in the middle
+const FULL_LINE_NON_ASCII: &str = "0123456789012345678901234567890123456789ü123456789012345678901234567890123456789";
+
+#[test_case]
+const BENCH_WRITE_STRING: Bench = Bench(&bench_write_string);
+
+fn bench_write_string() {
+    WRITER.lock().write_string(black_box(FULL_LINE));
+}
+
+// The same line with a single non-ASCII character, which takes write_string's slow path, to compare
+// against BENCH_WRITE_STRING, which takes the fast path
+#[test_case]
+const BENCH_WRITE_STRING_NON_ASCII: Bench = Bench(&bench_write_string_non_ascii);
+
+fn bench_write_string_non_ascii() {
+    WRITER.lock().write_string(black_box(FULL_LINE_NON_ASCII));
+}
+
+#[test_case]
+const BENCH_UNICODE_TO_CP437: Bench = Bench(&bench_unicode_to_cp437);
+
+fn bench_unicode_to_cp437() {
+    for c in black_box("Grüße, ½ ░▒▓ ♥ ≈ π").chars() {
+        black_box(unicode_to_cp437(c));
+    }
+}
+
+// Allocates and frees a few boxes, to compare the allocators: run this once as it is, and once each
+// with `--features bump-allocator` and `--features fixed-size-block-allocator`
+#[test_case]
+const BENCH_ALLOC: Bench = Bench(&bench_alloc);
+
+fn bench_alloc() {
+    let boxes = [(); 8].map(|_| black_box(Box::new(black_box(42u64))));
+    drop(black_box(boxes));
+}
+
+// Make sure that FULL_LINE and FULL_LINE_NON_ASCII really fill the row
+#[test_case]
+fn test_full_line() {
+    assert_eq!(FULL_LINE.len(), BUFFER_WIDTH);
+    assert_eq!(FULL_LINE_NON_ASCII.chars().count(), BUFFER_WIDTH);
+}
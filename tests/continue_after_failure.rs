@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(tdos::test_runner::test_runner_continue)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use tdos::{
+    qemu::QemuExitCode,
+    serial_print, serial_println,
+    test_runner::{self, Summary},
+};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    tdos::init_output();
+    test_runner::set_finish_hook(check_failures);
+    test_main();
+    tdos::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    tdos::test_runner::test_panic_handler(info)
+}
+
+// Two independent failures, neither of which ends the test run
+
+#[test_case]
+fn first_failure() {
+    assert_eq!(1, 2);
+}
+
+#[test_case]
+fn second_failure() {
+    assert_eq!(2 + 2, 5, "the second failure");
+}
+
+/// Runs once the test run is over, and the failures and the summary are printed. The test run as a
+/// whole failed, which is the point, so once this made sure that both failures were counted and
+/// listed, it turns the Failed exit code into Success.
+fn check_failures(summary: &Summary, exit_code: QemuExitCode) -> QemuExitCode {
+    serial_print!("continue_after_failure::both_failures_reported...\t");
+    assert_eq!((summary.passed, summary.failed, summary.skipped), (0, 2, 0));
+    assert_eq!(exit_code, QemuExitCode::Failed);
+    let mut failed = test_runner::failed_tests();
+    assert_eq!(failed.next(), Some("continue_after_failure::first_failure"));
+    assert_eq!(failed.next(), Some("continue_after_failure::second_failure"));
+    assert_eq!(failed.next(), None);
+    serial_println!("[ok]");
+    return QemuExitCode::Success;
+}
@@ -0,0 +1,70 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use tdos::{
+    fmt_buffer::FmtBuffer,
+    interrupts::{self, DivideError, DIVIDE_ERROR_VECTOR},
+    qemu::{exit_qemu, QemuExitCode},
+    serial_print, serial_println,
+};
+
+/// The encoding of the div rcx below
+const DIV_RCX: [u8; 3] = [0x48, 0xf7, 0xf1];
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    tdos::init_output();
+    serial_print!("divide_error::divide_by_zero...\t");
+    tdos::gdt::init();
+    interrupts::init_dt();
+    interrupts::set_fault_exit(divide_error_exit);
+    // dividing with / would check for zero and panic instead of faulting, even for a zero that is
+    // only known at run time
+    let zero = core::hint::black_box(0u64);
+    unsafe {
+        asm!(
+            "div rcx",
+            in("rcx") zero,
+            inout("rax") 1u64 => _,
+            inout("rdx") 0u64 => _,
+            options(nomem, nostack),
+        );
+    }
+    panic!("Execution continued after dividing by zero");
+}
+
+/// Called by the kernel's divide error handler after its report (see interrupts::DivideError),
+/// instead of halting. The handler remembered where the division was, so we can check that it
+/// points at our div rcx, and what the report said about it.
+fn divide_error_exit() -> ! {
+    assert_eq!(interrupts::count_of(DIVIDE_ERROR_VECTOR), 1);
+    let rip = interrupts::last_divide_error().expect("the divide error handler did not record the division");
+    let instruction = unsafe { (rip.as_u64() as *const [u8; 3]).read_volatile() };
+    assert_eq!(instruction, DIV_RCX);
+
+    let mut report = FmtBuffer::<256>::new();
+    write!(report, "{}", DivideError(rip)).unwrap();
+    let mut expected = FmtBuffer::<128>::new();
+    write!(
+        expected,
+        "EXCEPTION: DIVIDE ERROR (division by zero or quotient overflow) at {:#x}: 48 f7 f1",
+        rip.as_u64()
+    )
+    .unwrap();
+    assert!(
+        report.as_str().starts_with(expected.as_str()),
+        "unexpected report {:?}",
+        report.as_str()
+    );
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    tdos::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    tdos::test_runner::test_panic_handler(info)
+}
@@ -0,0 +1,46 @@
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use tdos::interrupts::{self, DOUBLE_FAULT_MAGIC};
+use tdos::qemu::{exit_qemu, QemuExitCode};
+use tdos::{serial_print, serial_println};
+
+// Unlike the stack_overflow test, this uses the kernel's own double fault handler, which writes its
+// report to SERIAL1 and then panics, so that we end up in our panic handler
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    tdos::init_output();
+    serial_print!("double_fault::double_fault...\t");
+    // the report goes into the middle of our output line, so it gets its own lines
+    serial_println!();
+    tdos::gdt::init();
+    interrupts::init_dt();
+    stack_overflow();
+    serial_println!("[test did not double fault]");
+    exit_qemu(QemuExitCode::Failed);
+    tdos::hlt_loop();
+}
+
+#[allow(unconditional_recursion)]
+fn stack_overflow() {
+    stack_overflow(); // for each recursion, the return address is pushed
+    volatile::Volatile::new(0).read(); // prevent tail rec optimisations
+}
+
+/// The double fault handler left its record behind before it panicked, and the report with the
+/// DOUBLE_FAULT_MARKER went out on SERIAL1 right before this, where the host can see it
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    match interrupts::double_fault_record() {
+        Some(record) if record.magic == DOUBLE_FAULT_MAGIC && record.instruction_pointer != 0 => {
+            serial_println!("[ok]");
+            exit_qemu(QemuExitCode::Success);
+        },
+        _ => {
+            serial_println!("[failed]\n{}", info);
+            exit_qemu(QemuExitCode::Failed);
+        },
+    }
+    tdos::hlt_loop();
+}
@@ -0,0 +1,54 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+use core::panic::PanicInfo;
+use tdos::{
+    interrupts::{self, BREAKPOINT_VECTOR, INVALID_OPCODE_VECTOR},
+    qemu::{exit_qemu, QemuExitCode},
+    serial_print, serial_println,
+};
+
+// Runs the kernel's own exception handlers, from the kernel's IDT, rather than handlers of the
+// test's own: first a breakpoint, whose handler returns, and then an invalid opcode, whose handler
+// does not. That one has to come last, since nothing runs after it but the fault exit (see
+// interrupts::set_fault_exit), which checks that the kernel's handler got there.
+// The other exceptions we cannot resume from have a binary of their own each: divide_error,
+// page_fault_handler for a page fault at an address that is not mapped at all, and heap_guard for
+// one in a guard page.
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    tdos::init_output();
+    tdos::gdt::init();
+    interrupts::init_dt();
+    interrupts::set_fault_exit(invalid_opcode_exit);
+
+    serial_print!("exceptions::breakpoint...\t");
+    let before = interrupts::count_of(BREAKPOINT_VECTOR);
+    x86_64::instructions::interrupts::int3();
+    assert_eq!(interrupts::count_of(BREAKPOINT_VECTOR), before + 1);
+    // the handler remembers the address right after the int3, which is a single byte
+    let int3 = unsafe { ((interrupts::last_breakpoint() - 1) as *const u8).read_volatile() };
+    assert_eq!(int3, 0xcc);
+    serial_println!("[ok]");
+
+    serial_print!("exceptions::invalid_opcode...\t");
+    unsafe {
+        asm!("ud2", options(nomem, nostack));
+    }
+    panic!("Execution continued after invalid opcode");
+}
+
+/// Called by the kernel's invalid opcode handler after its report, instead of halting
+fn invalid_opcode_exit() -> ! {
+    assert_eq!(interrupts::count_of(INVALID_OPCODE_VECTOR), 1);
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    tdos::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    tdos::test_runner::test_panic_handler(info)
+}
@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use tdos::{
+    memory::BootInfoFrameAllocator,
+    qemu::{exit_qemu, QemuExitCode},
+    serial_print, serial_println,
+};
+use x86_64::structures::paging::{FrameAllocator, PhysFrame};
+
+/// How many frames the test allocates
+const FRAME_COUNT: usize = 32;
+
+// The frame allocator needs the memory map from the boot info, which only the bootloader can give
+// us, so this test needs its own entry point.
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    tdos::init_output();
+    serial_print!("frame_allocator::allocate_frames...\t");
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+
+    let mut frames = [None::<PhysFrame>; FRAME_COUNT];
+    for i in 0..FRAME_COUNT {
+        let frame = frame_allocator.allocate_frame().expect("ran out of frames");
+        assert!(frame.start_address().is_aligned(4096u64));
+        // every frame must be new
+        assert!(!frames[..i].contains(&Some(frame)));
+        frames[i] = Some(frame);
+    }
+
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    tdos::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    tdos::test_runner::test_panic_handler(info)
+}
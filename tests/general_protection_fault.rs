@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use core::arch::asm;
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use tdos::{
+    interrupts::SelectorErrorCode,
+    qemu::{exit_qemu, QemuExitCode},
+    serial_print, serial_println,
+};
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+/// A selector whose index lies far beyond the end of our GDT
+const INVALID_SELECTOR: u16 = 0x1230;
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        unsafe {
+            idt.general_protection_fault
+                .set_handler_fn(test_general_protection_fault_handler)
+                .set_stack_index(tdos::gdt::GENERAL_PROTECTION_FAULT_IST_INDEX);
+        }
+        idt
+    };
+}
+
+pub fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    tdos::init_output();
+    serial_print!("general_protection_fault::load_invalid_segment...\t");
+    tdos::gdt::init();
+    init_test_idt();
+    unsafe {
+        asm!("mov ds, {0:x}", in(reg) INVALID_SELECTOR, options(nostack));
+    }
+    panic!("Execution continued after general protection fault");
+}
+
+// The kernel's own handler halts, so we use our own one that checks what the CPU reported
+extern "x86-interrupt" fn test_general_protection_fault_handler(_stack_frame: InterruptStackFrame, error_code: u64) {
+    assert_eq!(error_code, INVALID_SELECTOR as u64);
+    serial_print!("({}) ", SelectorErrorCode(error_code));
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    tdos::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    tdos::test_runner::test_panic_handler(info)
+}
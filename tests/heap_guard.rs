@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use tdos::{
+    allocator::{self, HEAP_START},
+    interrupts::{self, PAGE_FAULT_VECTOR},
+    memory::{self, BootInfoFrameAllocator, GuardPage},
+    qemu::{exit_qemu, QemuExitCode},
+    serial_print, serial_println,
+};
+use x86_64::VirtAddr;
+
+/// The last 8 bytes below the heap, i.e. in the guard page right below it
+const BELOW_HEAP: u64 = HEAP_START as u64 - 8;
+
+// The heap needs the physical memory offset and the memory map from the boot info, so this test
+// needs its own entry point.
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    tdos::init_output();
+    serial_print!("heap_guard::write_below_heap...\t");
+    tdos::gdt::init();
+    interrupts::init_dt();
+    interrupts::set_fault_exit(page_fault_exit);
+
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    // the heap itself works...
+    assert_eq!(*Box::new(42), 42);
+    // ...but the page right below it is not mapped
+    unsafe {
+        (BELOW_HEAP as *mut u64).write_volatile(42);
+    }
+    panic!("Execution continued after writing below the heap");
+}
+
+/// Called by the kernel's page fault handler after its report, instead of halting. The handler
+/// recognized the heap's guard page if it remembered it for last_guard_page, which is also what it
+/// bases its "heap underflow" diagnostic on.
+fn page_fault_exit() -> ! {
+    assert_eq!(interrupts::count_of(PAGE_FAULT_VECTOR), 1);
+    assert_eq!(interrupts::last_guard_page(), Some(GuardPage::BelowHeap));
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    tdos::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    tdos::test_runner::test_panic_handler(info)
+}
@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use core::arch::asm;
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use tdos::{
+    interrupts::InstructionBytes,
+    qemu::{exit_qemu, QemuExitCode},
+    serial_print, serial_println,
+};
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.invalid_opcode.set_handler_fn(test_invalid_opcode_handler);
+        idt
+    };
+}
+
+pub fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    tdos::init_output();
+    serial_print!("invalid_opcode::ud2...\t");
+    tdos::gdt::init();
+    init_test_idt();
+    unsafe {
+        asm!("ud2", options(nomem, nostack));
+    }
+    panic!("Execution continued after invalid opcode");
+}
+
+// The kernel's own handler halts, so we use our own one that checks what the CPU reported
+extern "x86-interrupt" fn test_invalid_opcode_handler(stack_frame: InterruptStackFrame) {
+    let rip = stack_frame.instruction_pointer;
+    assert_ne!(rip.as_u64(), 0);
+    // ud2 is encoded as 0f 0b
+    let ud2 = unsafe { (rip.as_u64() as *const [u8; 2]).read_volatile() };
+    assert_eq!(ud2, [0x0f, 0x0b]);
+    serial_print!("(at {:#x}: {}) ", rip.as_u64(), InstructionBytes(rip));
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    tdos::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    tdos::test_runner::test_panic_handler(info)
+}
@@ -0,0 +1,57 @@
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use tdos::{
+    interrupts::PageFaultCause,
+    qemu::{exit_qemu, QemuExitCode},
+    serial_print, serial_println,
+};
+use x86_64::registers::control::Cr2;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use x86_64::VirtAddr;
+
+/// An address that is not mapped by the bootloader
+const UNMAPPED_ADDRESS: u64 = 0xdeadbeaf000;
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.page_fault.set_handler_fn(test_page_fault_handler);
+        idt
+    };
+}
+
+pub fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    tdos::init_output();
+    serial_print!("page_fault::page_fault...\t");
+    tdos::gdt::init();
+    init_test_idt();
+    unsafe {
+        (UNMAPPED_ADDRESS as *mut u64).write_volatile(42);
+    }
+    panic!("Execution continued after page fault");
+}
+
+// The kernel's own handler halts, so we use our own one that checks what the CPU reported
+extern "x86-interrupt" fn test_page_fault_handler(_stack_frame: InterruptStackFrame, error_code: PageFaultErrorCode) {
+    assert_eq!(Cr2::read(), VirtAddr::new(UNMAPPED_ADDRESS));
+    assert!(error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE));
+    assert!(!error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION));
+    serial_print!("({}) ", PageFaultCause(error_code));
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    tdos::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    tdos::test_runner::test_panic_handler(info)
+}
@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use tdos::{
+    interrupts::{self, PAGE_FAULT_VECTOR},
+    qemu::{exit_qemu, QemuExitCode},
+    serial_print, serial_println,
+};
+use x86_64::registers::control::Cr2;
+use x86_64::VirtAddr;
+
+/// An address that is not mapped by the bootloader, and not in any of our guard pages either
+const UNMAPPED_ADDRESS: u64 = 0xdeadbeaf000;
+
+// Unlike page_fault, which checks what the CPU reports with a handler of its own, this runs the
+// kernel's page fault handler, from the kernel's IDT. It halts after its report, so the fault exit
+// (see interrupts::set_fault_exit) checks that it got there.
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    tdos::init_output();
+    serial_print!("page_fault_handler::unmapped_address...\t");
+    tdos::gdt::init();
+    interrupts::init_dt();
+    interrupts::set_fault_exit(page_fault_exit);
+    unsafe {
+        (UNMAPPED_ADDRESS as *mut u64).write_volatile(42);
+    }
+    panic!("Execution continued after page fault");
+}
+
+/// Called by the kernel's page fault handler after its report, instead of halting
+fn page_fault_exit() -> ! {
+    assert_eq!(interrupts::count_of(PAGE_FAULT_VECTOR), 1);
+    assert_eq!(Cr2::read(), VirtAddr::new(UNMAPPED_ADDRESS));
+    // not a guard page, so there is no guard page diagnostic either
+    assert_eq!(interrupts::last_guard_page(), None);
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    tdos::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    tdos::test_runner::test_panic_handler(info)
+}
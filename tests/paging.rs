@@ -0,0 +1,57 @@
+#![no_std]
+#![no_main]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use tdos::{
+    memory::{self, BootInfoFrameAllocator},
+    qemu::{exit_qemu, QemuExitCode},
+    serial_print, serial_println,
+};
+use x86_64::structures::paging::{Page, PhysFrame};
+use x86_64::{PhysAddr, VirtAddr};
+
+/// A page that is not mapped by the bootloader
+const UNMAPPED_ADDRESS: u64 = 0xdeadbeaf000;
+
+/// Physical address of the VGA text buffer, which the bootloader identity maps
+const VGA_BUFFER_ADDRESS: u64 = 0xb8000;
+
+/// Offset into the VGA buffer that we write to, i.e. the middle of the 3rd row
+const OFFSET: u64 = 400;
+
+// memory::init needs the physical memory offset from the boot info, so this test needs its own
+// entry point.
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    tdos::init_output();
+    serial_print!("paging::create_mapping...\t");
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+
+    let page = Page::containing_address(VirtAddr::new(UNMAPPED_ADDRESS));
+    let frame = PhysFrame::containing_address(PhysAddr::new(VGA_BUFFER_ADDRESS));
+    unsafe { memory::create_mapping(page, frame, &mut mapper, &mut frame_allocator) }.expect("mapping failed");
+
+    // "New!" in white on black, written through the new page...
+    let text: u64 = 0x_0f21_0f77_0f65_0f4e;
+    unsafe {
+        (UNMAPPED_ADDRESS as *mut u64)
+            .byte_add(OFFSET as usize)
+            .write_volatile(text)
+    };
+    // ...has to show up in the VGA buffer
+    let written = unsafe { ((VGA_BUFFER_ADDRESS + OFFSET) as *const u64).read_volatile() };
+    assert_eq!(written, text);
+
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    tdos::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    tdos::test_runner::test_panic_handler(info)
+}
@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use tdos::{
+    fmt_buffer::FmtBuffer,
+    qemu::{exit_qemu, QemuExitCode},
+    serial_print, serial_println,
+    vga_buffer::{Color, BUFFER_HEIGHT, BUFFER_WIDTH, WRITER},
+};
+
+const MESSAGE: &str = "panic_report test message";
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    tdos::init_output();
+    serial_print!("panic_report::report_panic...\t");
+    // start the report on a new line in the host's log
+    serial_println!();
+    panic!("{}", MESSAGE);
+}
+
+/// Reports the panic like the kernel's panic handler does, and checks that the message made it to
+/// the screen in white on red, and into the host's copy of the report. We cannot read back what
+/// goes out on SERIAL1, so the host's copy goes into a buffer instead, which is then passed on to
+/// SERIAL1 so that it still shows up in the test's output.
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let mut host = FmtBuffer::<4096>::new();
+    tdos::report_panic_to(info, Some(&mut host));
+    serial_println!("{}", host);
+    // the report starts with the panic's location, followed by its message on the next line
+    if !host.as_str().lines().take(2).any(|line| line == MESSAGE) {
+        serial_println!("[failed]\nError: the host's report does not start with the panic message");
+        exit_qemu(QemuExitCode::Failed);
+    }
+
+    let found = {
+        let writer = WRITER.lock();
+        (0..BUFFER_HEIGHT).any(|row| {
+            (0..=BUFFER_WIDTH - MESSAGE.len()).any(|start| {
+                MESSAGE.bytes().enumerate().all(|(i, byte)| {
+                    return writer.read_char(row, start + i) == Some((byte, Color::White, Color::Red));
+                })
+            })
+        })
+    };
+    if found {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]\nError: the panic message is not on the screen in white on red");
+        exit_qemu(QemuExitCode::Failed);
+    }
+    tdos::hlt_loop();
+}
@@ -9,17 +9,18 @@ use tdos::{
 
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
+    tdos::init_output();
     should_fail();
     serial_println!("[test did not panic]");
     exit_qemu(QemuExitCode::Failed);
-    loop {}
+    tdos::hlt_loop();
 }
 
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
     serial_println!("[ok]");
     exit_qemu(QemuExitCode::Success);
-    loop {}
+    tdos::hlt_loop();
 }
 
 fn should_fail() {
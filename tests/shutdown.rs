@@ -0,0 +1,26 @@
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use tdos::{
+    qemu::{exit_qemu, QemuExitCode},
+    serial_print, serial_println,
+};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    tdos::init_output();
+    serial_print!("shutdown::shutdown...\t");
+    // once QEMU is off, we cannot print anything anymore
+    serial_println!("[ok]");
+    tdos::power::shutdown();
+}
+
+// shutdown does not return, so the only way to get here is a panic
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    serial_println!("[failed]\n");
+    serial_println!("Error: {}\n", info);
+    exit_qemu(QemuExitCode::Failed);
+    tdos::hlt_loop();
+}
@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(tdos::test_runner::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use tdos::time;
+
+// Sleeping needs the timer interrupt, and tdos::init sets it up (and needs the boot info for that)
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    tdos::init(boot_info);
+    test_main();
+    tdos::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    tdos::test_runner::test_panic_handler(info)
+}
+
+// At 100 Hz, 50 ms are 5 ticks, and sleeping for them lets at least that many ticks pass
+#[test_case]
+fn test_sleep_ms() {
+    assert_eq!(time::tick_hz(), 100);
+    assert_eq!(time::ms_to_ticks(50), 5);
+    let start = time::ticks();
+    time::sleep_ms(50);
+    assert!(time::ticks() - start >= 5);
+}
+
+// Short sleeps round up to a whole tick rather than down to not sleeping at all
+#[test_case]
+fn test_sleep_rounds_up() {
+    assert_eq!(time::ms_to_ticks(0), 0);
+    assert_eq!(time::ms_to_ticks(1), 1);
+    assert_eq!(time::ms_to_ticks(11), 2);
+    let start = time::ticks();
+    time::sleep_ms(1);
+    assert!(time::ticks() - start >= 1);
+}
@@ -4,7 +4,7 @@
 
 use core::panic::PanicInfo;
 use lazy_static::lazy_static;
-use tdos::{qemu::exit_qemu, serial_print};
+use tdos::{qemu::exit_qemu, serial_print, serial_println};
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
 
 lazy_static! {
@@ -25,6 +25,7 @@ pub fn init_test_idt() {
 
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
+    tdos::init_output();
     serial_print!("stack_overflow::stack_overflow...\t");
     tdos::gdt::init();
     init_test_idt();
@@ -33,9 +34,9 @@ pub extern "C" fn _start() -> ! {
 }
 
 extern "x86-interrupt" fn test_double_fault_handler(_stack_frame: InterruptStackFrame, _error_code: u64) -> ! {
-    serial_print!("[ok]");
+    serial_println!("[ok]");
     exit_qemu(tdos::qemu::QemuExitCode::Success);
-    loop {}
+    tdos::hlt_loop();
 }
 
 #[allow(unconditional_recursion)]
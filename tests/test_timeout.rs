@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(tdos::test_runner::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use tdos::{
+    qemu::{exit_qemu, QemuExitCode},
+    serial_println,
+};
+
+// The timeout needs the timer interrupt, so we need the boot info to call tdos::init
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    tdos::init(boot_info);
+    test_main();
+    serial_println!("[test did not time out]");
+    exit_qemu(QemuExitCode::Failed);
+    tdos::hlt_loop();
+}
+
+/// The test runner reports a timeout by panicking, which is exactly what we want here
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    if tdos::test_runner::timed_out() {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+        tdos::hlt_loop();
+    }
+    tdos::test_runner::test_panic_handler(info)
+}
+
+// Hangs until the test runner gives up on it
+#[test_case]
+fn spins_forever() {
+    loop {
+        core::hint::spin_loop();
+    }
+}